@@ -0,0 +1,73 @@
+#![no_std]
+#![no_main]
+
+extern crate panic_halt;
+
+use core::cell::RefCell;
+use cortex_m_rt::entry;
+use embedded_hal::blocking::i2c::{Write, WriteRead};
+use hal::flash::FlashExt;
+use hal::i2c::I2c;
+use hal::prelude::*;
+use hd44780_driver::bus::shared::RefCellDevice;
+use hd44780_driver::HD44780;
+
+// Connections:
+// VSS: GND
+// VDD: 5V
+// SCL: PB6
+// SDA: PB9
+// LCD backpack at 0x3F, 24C02 EEPROM at 0x50 on the same bus
+
+const LCD_ADDRESS: u8 = 0x3F;
+const EEPROM_ADDRESS: u8 = 0x50;
+
+#[entry]
+fn main() -> ! {
+    let cp = cortex_m::Peripherals::take().unwrap();
+    let dp = hal::stm32f30x::Peripherals::take().unwrap();
+
+    let mut flash = dp.FLASH.constrain();
+    let mut rcc = dp.RCC.constrain();
+    let mut gpiob = dp.GPIOB.split(&mut rcc.ahb);
+
+    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+    let mut delay = hal::delay::Delay::new(cp.SYST, clocks);
+
+    let scl = gpiob.pb6.into_af4(&mut gpiob.moder, &mut gpiob.afrl);
+    let sda = gpiob.pb9.into_af4(&mut gpiob.moder, &mut gpiob.afrh);
+
+    let i2c = RefCell::new(I2c::i2c1(
+        dp.I2C1,
+        (scl, sda),
+        400.khz(),
+        clocks,
+        &mut rcc.apb1,
+    ));
+
+    let mut lcd = HD44780::new_i2c(RefCellDevice::new(&i2c), LCD_ADDRESS, &mut delay).unwrap();
+    let mut eeprom = RefCellDevice::new(&i2c);
+
+    // Count boots, this one included, in the first byte of the EEPROM
+    let mut boots = [0u8];
+    let _ = eeprom.write_read(EEPROM_ADDRESS, &[0x00], &mut boots);
+    let _ = eeprom.write(EEPROM_ADDRESS, &[0x00, boots[0].wrapping_add(1)]);
+
+    // The display takes the bus while the EEPROM stores the byte
+    let _ = lcd.clear(&mut delay);
+    let _ = lcd.write_str("Boots: ", &mut delay);
+    delay.delay_ms(5u8);
+
+    // Show the count as stored, read back between writes to the display
+    let _ = eeprom.write_read(EEPROM_ADDRESS, &[0x00], &mut boots);
+
+    let mut digits = [b'0'; 3];
+    let mut n = boots[0];
+    for digit in digits.iter_mut().rev() {
+        *digit = b'0' + n % 10;
+        n /= 10;
+    }
+    let _ = lcd.write_bytes(&digits, &mut delay);
+
+    loop {}
+}
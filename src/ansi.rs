@@ -0,0 +1,320 @@
+//! A subset of the VT100 escape sequences, on top of a
+//! [`Terminal`](../terminal/struct.Terminal.html)
+//!
+//! Output from console code written for a serial terminal can be shown
+//! unchanged through an [`AnsiTerminal`]. These sequences are understood,
+//! others are dropped:
+//!
+//! | Sequence                    | Effect                                   |
+//! |-----------------------------|------------------------------------------|
+//! | `ESC[J`, `ESC[1J`, `ESC[2J` | Erase below, above, or the whole display |
+//! | `ESC[K`, `ESC[1K`, `ESC[2K` | Erase right, left, or the whole row      |
+//! | `ESC[r;cH`, `ESC[r;cf`      | Move to row `r`, column `c`, from 1      |
+//! | `ESC[nA` to `ESC[nD`        | Move up, down, right or left `n` cells   |
+//! | `ESC[?25h`, `ESC[?25l`      | Show or hide the cursor                  |
+//! | `ESC7`, `ESC[s`             | Save the cursor position                 |
+//! | `ESC8`, `ESC[u`             | Restore the cursor position              |
+//!
+//! ```rust,ignore
+//! let mut terminal = AnsiTerminal::new(Geometry::new(20, 4));
+//!
+//! write!(terminal, "\x1b[2J\x1b[?25l\x1b[2;5HReady")?;
+//! terminal.flush(&mut lcd, &mut delay)?;
+//! ```
+
+use core::fmt;
+
+use embedded_hal::blocking::delay::{DelayMs, DelayUs};
+
+use crate::bus::DataBus;
+use crate::error::Result;
+use crate::geometry::Geometry;
+use crate::terminal::{Erase, Terminal};
+use crate::{Cursor, HD44780};
+
+const ESC: u8 = 0x1B;
+
+/// Numeric parameters kept from a sequence, the rest are ignored
+const MAX_PARAMS: usize = 2;
+
+/// What a sequence, or a byte outside of one, asks for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// A byte to write as it is, printable or a control character
+    Byte(u8),
+    EraseDisplay(Erase),
+    EraseLine(Erase),
+    /// Move to (column, row), counting from 0
+    MoveTo((u8, u8)),
+    Up(u8),
+    Down(u8),
+    Forward(u8),
+    Back(u8),
+    ShowCursor(bool),
+    SaveCursor,
+    RestoreCursor,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Ground,
+    Escape,
+    /// Inside a control sequence, after `ESC[`
+    Csi,
+}
+
+/// Splits a byte stream into [`Action`]s
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Parser {
+    state: State,
+    params: [u16; MAX_PARAMS],
+    /// Index of the parameter being read
+    param: usize,
+    /// Whether the sequence started with `?`
+    private: bool,
+}
+
+impl Default for Parser {
+    fn default() -> Parser {
+        Parser {
+            state: State::Ground,
+            params: [0; MAX_PARAMS],
+            param: 0,
+            private: false,
+        }
+    }
+}
+
+impl Parser {
+    /// Feed the next byte, returning an action once one is complete
+    pub fn advance(&mut self, byte: u8) -> Option<Action> {
+        match (self.state, byte) {
+            (_, ESC) => {
+                self.state = State::Escape;
+                None
+            }
+            (State::Ground, byte) => Some(Action::Byte(byte)),
+            (State::Escape, b'[') => {
+                *self = Parser {
+                    state: State::Csi,
+                    ..Parser::default()
+                };
+                None
+            }
+            (State::Escape, byte) => {
+                self.state = State::Ground;
+
+                match byte {
+                    b'7' => Some(Action::SaveCursor),
+                    b'8' => Some(Action::RestoreCursor),
+                    _ => None,
+                }
+            }
+            (State::Csi, b'0'..=b'9') => {
+                if let Some(param) = self.params.get_mut(self.param) {
+                    *param = param
+                        .saturating_mul(10)
+                        .saturating_add((byte - b'0') as u16);
+                }
+                None
+            }
+            (State::Csi, b';') => {
+                self.param += 1;
+                None
+            }
+            (State::Csi, b'?') => {
+                self.private = true;
+                None
+            }
+            (State::Csi, 0x40..=0x7E) => {
+                self.state = State::Ground;
+                self.dispatch(byte)
+            }
+            // Intermediate bytes, which none of the supported sequences use
+            (State::Csi, _) => None,
+        }
+    }
+
+    fn dispatch(&self, last: u8) -> Option<Action> {
+        let [first, second] = self.params;
+        // Counts and positions of 0 mean the same as leaving them out
+        let count = first.clamp(1, u8::MAX as u16) as u8;
+        let erase = match first {
+            0 => Some(Erase::ToEnd),
+            1 => Some(Erase::ToStart),
+            2 => Some(Erase::All),
+            _ => None,
+        };
+
+        match (self.private, last) {
+            (false, b'H') | (false, b'f') => {
+                let row = first.clamp(1, u8::MAX as u16) as u8 - 1;
+                let column = second.clamp(1, u8::MAX as u16) as u8 - 1;
+
+                Some(Action::MoveTo((column, row)))
+            }
+            (false, b'J') => erase.map(Action::EraseDisplay),
+            (false, b'K') => erase.map(Action::EraseLine),
+            (false, b'A') => Some(Action::Up(count)),
+            (false, b'B') => Some(Action::Down(count)),
+            (false, b'C') => Some(Action::Forward(count)),
+            (false, b'D') => Some(Action::Back(count)),
+            (false, b's') => Some(Action::SaveCursor),
+            (false, b'u') => Some(Action::RestoreCursor),
+            (true, b'h') if first == 25 => Some(Action::ShowCursor(true)),
+            (true, b'l') if first == 25 => Some(Action::ShowCursor(false)),
+            _ => None,
+        }
+    }
+}
+
+/// A [`Terminal`] driven by text with escape sequences, see the
+/// [module documentation](index.html)
+pub struct AnsiTerminal {
+    terminal: Terminal,
+    parser: Parser,
+    saved: (u8, u8),
+    /// Cursor visibility to set on the next flush
+    show_cursor: Option<bool>,
+}
+
+impl AnsiTerminal {
+    /// Create an empty terminal covering a display of size `geometry`, up to
+    /// 40 columns and 4 rows
+    pub fn new(geometry: Geometry) -> AnsiTerminal {
+        AnsiTerminal {
+            terminal: Terminal::new(geometry),
+            parser: Parser::default(),
+            saved: (0, 0),
+            show_cursor: None,
+        }
+    }
+
+    /// Add text to the buffer, without drawing it yet
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            if let Some(action) = self.parser.advance(byte) {
+                self.apply(action);
+            }
+        }
+    }
+
+    /// Carry out an action, as if its sequence was written
+    pub fn apply(&mut self, action: Action) {
+        let (column, row) = self.terminal.cursor();
+
+        match action {
+            Action::Byte(byte) => self.terminal.write_byte(byte),
+            Action::EraseDisplay(erase) => self.terminal.erase_display(erase),
+            Action::EraseLine(erase) => self.terminal.erase_line(erase),
+            Action::MoveTo(position) => self.terminal.move_to(position),
+            Action::Up(count) => self.terminal.move_to((column, row.saturating_sub(count))),
+            Action::Down(count) => self.terminal.move_to((column, row.saturating_add(count))),
+            Action::Forward(count) => self.terminal.move_to((column.saturating_add(count), row)),
+            Action::Back(count) => self.terminal.move_to((column.saturating_sub(count), row)),
+            Action::ShowCursor(show) => self.show_cursor = Some(show),
+            Action::SaveCursor => self.saved = (column, row),
+            Action::RestoreCursor => self.terminal.move_to(self.saved),
+        }
+    }
+
+    /// Draw what changed since the last flush, see
+    /// [Terminal::flush](../terminal/struct.Terminal.html#method.flush)
+    pub fn flush<B: DataBus, D: DelayUs<u16> + DelayMs<u8>>(
+        &mut self,
+        lcd: &mut HD44780<B>,
+        delay: &mut D,
+    ) -> Result<()> {
+        self.terminal.flush(lcd, delay)?;
+
+        if let Some(show) = self.show_cursor.take() {
+            let visibility = if show {
+                Cursor::Visible
+            } else {
+                Cursor::Invisible
+            };
+
+            lcd.set_cursor_visibility(visibility, delay)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn terminal(&self) -> &Terminal {
+        &self.terminal
+    }
+
+    pub fn terminal_mut(&mut self) -> &mut Terminal {
+        &mut self.terminal
+    }
+}
+
+impl fmt::Write for AnsiTerminal {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_bytes(s.as_bytes());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn actions(bytes: &[u8]) -> ([Option<Action>; 16], usize) {
+        let mut parser = Parser::default();
+        let mut actions = [None; 16];
+        let mut len = 0;
+
+        for &byte in bytes {
+            if let Some(action) = parser.advance(byte) {
+                actions[len] = Some(action);
+                len += 1;
+            }
+        }
+
+        (actions, len)
+    }
+
+    #[test]
+    fn parses_sequences() {
+        let (actions, len) = actions(b"\x1b[2J\x1b[3;12Ha\x1b[?25l\x1b[K\x1b7\x1b[2C\x1b[5m");
+
+        assert_eq!(
+            actions[..len],
+            [
+                Some(Action::EraseDisplay(Erase::All)),
+                Some(Action::MoveTo((11, 2))),
+                Some(Action::Byte(b'a')),
+                Some(Action::ShowCursor(false)),
+                Some(Action::EraseLine(Erase::ToEnd)),
+                Some(Action::SaveCursor),
+                Some(Action::Forward(2)),
+            ]
+        );
+    }
+
+    #[test]
+    fn defaults_positions() {
+        let (actions, len) = actions(b"\x1b[H\x1b[;4f\x1b[0;0H");
+
+        assert_eq!(
+            actions[..len],
+            [
+                Some(Action::MoveTo((0, 0))),
+                Some(Action::MoveTo((3, 0))),
+                Some(Action::MoveTo((0, 0))),
+            ]
+        );
+    }
+
+    #[test]
+    fn drives_terminal() {
+        let mut terminal = AnsiTerminal::new(Geometry::new(8, 2));
+
+        terminal.write_bytes(b"abcdef\x1b[1;3H\x1b[K\x1b[2;2Hx\x1b[1;2H\x1b[1K");
+
+        assert_eq!(terminal.terminal().line(0), b"        ");
+        assert_eq!(terminal.terminal().line(1), b" x      ");
+    }
+}
@@ -0,0 +1,234 @@
+//! Horizontal and vertical bar graphs with sub-character resolution
+//!
+//! A character cell is 5 pixels wide and 8 pixels tall, so a bar can be drawn
+//! in steps of a single pixel column (horizontal) or row (vertical) by showing
+//! partially filled custom characters at its end. Completely filled cells use
+//! the solid block `0xFF` from the character ROM and empty cells use a space,
+//! so a horizontal bar needs 4 CGRAM slots and a vertical bar needs 7.
+//!
+//! Bars sharing the same first slot share their glyphs, which only need to be
+//! loaded once.
+//!
+//! ```rust,ignore
+//! let bar = HorizontalBar::new(0, (0, 1), 16);
+//! bar.load_glyphs(&mut lcd, &mut delay)?;
+//! bar.draw(&mut lcd, 42, 100, &mut delay)?;
+//! ```
+
+use embedded_hal::blocking::delay::{DelayMs, DelayUs};
+
+use crate::bus::DataBus;
+use crate::error::{Error, Result};
+use crate::HD44780;
+
+/// Pixel columns in a character cell
+pub(crate) const CELL_WIDTH: u8 = 5;
+
+/// Pixel rows in a character cell
+pub(crate) const CELL_HEIGHT: u8 = 8;
+
+/// Solid block in the character ROM
+const FULL: u8 = 0xFF;
+
+/// Blank cell
+const EMPTY: u8 = b' ';
+
+/// A bar filling from left to right with a resolution of 1/5 of a cell
+pub struct HorizontalBar {
+    first_slot: u8,
+    position: (u8, u8),
+    width: u8,
+}
+
+/// A bar filling from bottom to top with a resolution of 1/8 of a cell
+pub struct VerticalBar {
+    first_slot: u8,
+    position: (u8, u8),
+    height: u8,
+}
+
+impl HorizontalBar {
+    /// Create a bar starting at `position` (column, row) and spanning `width` cells.
+    /// The glyphs are stored in the CGRAM slots `first_slot..first_slot + 4`.
+    pub fn new(first_slot: u8, position: (u8, u8), width: u8) -> HorizontalBar {
+        HorizontalBar {
+            first_slot,
+            position,
+            width,
+        }
+    }
+
+    /// Upload the partial block glyphs used by this bar
+    pub fn load_glyphs<B: DataBus, D: DelayUs<u16> + DelayMs<u8>>(
+        &self,
+        lcd: &mut HD44780<B>,
+        delay: &mut D,
+    ) -> Result<()> {
+        if self.first_slot > 8 - (CELL_WIDTH - 1) {
+            return Err(Error::InvalidArgument);
+        }
+
+        for filled in 1..CELL_WIDTH {
+            lcd.set_custom_char(
+                self.first_slot + filled - 1,
+                &horizontal_glyph(filled),
+                delay,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Draw the bar filled to `value` out of `max`. Values above `max` draw a full bar.
+    pub fn draw<B: DataBus, D: DelayUs<u16> + DelayMs<u8>>(
+        &self,
+        lcd: &mut HD44780<B>,
+        value: u16,
+        max: u16,
+        delay: &mut D,
+    ) -> Result<()> {
+        let filled = scale(value, max, self.width, CELL_WIDTH);
+
+        lcd.set_cursor_xy(self.position, delay)?;
+
+        for cell in 0..self.width {
+            lcd.write_byte(cell_byte(filled, cell, CELL_WIDTH, self.first_slot), delay)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl VerticalBar {
+    /// Create a bar whose bottom cell is at `position` (column, row) and that
+    /// grows upwards for `height` cells. The glyphs are stored in the CGRAM
+    /// slots `first_slot..first_slot + 7`.
+    pub fn new(first_slot: u8, position: (u8, u8), height: u8) -> VerticalBar {
+        VerticalBar {
+            first_slot,
+            position,
+            height,
+        }
+    }
+
+    /// Upload the partial block glyphs used by this bar
+    pub fn load_glyphs<B: DataBus, D: DelayUs<u16> + DelayMs<u8>>(
+        &self,
+        lcd: &mut HD44780<B>,
+        delay: &mut D,
+    ) -> Result<()> {
+        if self.first_slot > 8 - (CELL_HEIGHT - 1) {
+            return Err(Error::InvalidArgument);
+        }
+
+        for filled in 1..CELL_HEIGHT {
+            lcd.set_custom_char(self.first_slot + filled - 1, &vertical_glyph(filled), delay)?;
+        }
+
+        Ok(())
+    }
+
+    /// Draw the bar filled to `value` out of `max`. Values above `max` draw a full bar.
+    pub fn draw<B: DataBus, D: DelayUs<u16> + DelayMs<u8>>(
+        &self,
+        lcd: &mut HD44780<B>,
+        value: u16,
+        max: u16,
+        delay: &mut D,
+    ) -> Result<()> {
+        let filled = scale(value, max, self.height, CELL_HEIGHT);
+        let (col, row) = self.position;
+
+        // A bar can't grow past the top row
+        for cell in 0..self.height.min(row.saturating_add(1)) {
+            lcd.set_cursor_xy((col, row - cell), delay)?;
+            lcd.write_byte(cell_byte(filled, cell, CELL_HEIGHT, self.first_slot), delay)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Number of pixels to fill in a bar of `cells` cells of `per_cell` pixels each
+fn scale(value: u16, max: u16, cells: u8, per_cell: u8) -> u32 {
+    let steps = cells as u32 * per_cell as u32;
+
+    if max == 0 {
+        return 0;
+    }
+
+    (value.min(max) as u32 * steps) / max as u32
+}
+
+/// Byte to show in the `cell`-th cell of a bar with `filled` pixels
+pub(crate) fn cell_byte(filled: u32, cell: u8, per_cell: u8, first_slot: u8) -> u8 {
+    let start = cell as u32 * per_cell as u32;
+
+    if filled >= start + per_cell as u32 {
+        FULL
+    } else if filled > start {
+        first_slot + (filled - start) as u8 - 1
+    } else {
+        EMPTY
+    }
+}
+
+/// Glyph with the `filled` leftmost columns set
+pub(crate) fn horizontal_glyph(filled: u8) -> [u8; 8] {
+    [(0b0001_1111 << (CELL_WIDTH - filled)) & 0b0001_1111; 8]
+}
+
+/// Glyph with the `filled` bottom rows set
+pub(crate) fn vertical_glyph(filled: u8) -> [u8; 8] {
+    let mut glyph = [0; 8];
+
+    for row in glyph.iter_mut().skip((CELL_HEIGHT - filled) as usize) {
+        *row = 0b0001_1111;
+    }
+
+    glyph
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::mock::{NoDelay, Recorder};
+
+    #[test]
+    fn glyphs() {
+        assert_eq!(horizontal_glyph(1), [0b1_0000; 8]);
+        assert_eq!(horizontal_glyph(4), [0b1_1110; 8]);
+
+        assert_eq!(vertical_glyph(1), [0, 0, 0, 0, 0, 0, 0, 0b1_1111]);
+        assert_eq!(vertical_glyph(7)[0], 0);
+        assert_eq!(vertical_glyph(7)[1], 0b1_1111);
+    }
+
+    #[test]
+    fn cells() {
+        // 3 cells of 5 pixels filled to 7 pixels
+        let filled = scale(7, 15, 3, CELL_WIDTH);
+
+        assert_eq!(filled, 7);
+        assert_eq!(cell_byte(filled, 0, CELL_WIDTH, 2), FULL);
+        assert_eq!(cell_byte(filled, 1, CELL_WIDTH, 2), 3);
+        assert_eq!(cell_byte(filled, 2, CELL_WIDTH, 2), EMPTY);
+    }
+
+    #[test]
+    fn scale_bounds() {
+        assert_eq!(scale(0, 100, 4, CELL_WIDTH), 0);
+        assert_eq!(scale(100, 100, 4, CELL_WIDTH), 20);
+        assert_eq!(scale(500, 100, 4, CELL_WIDTH), 20);
+        assert_eq!(scale(10, 0, 4, CELL_WIDTH), 0);
+    }
+
+    #[test]
+    fn draws_on_the_last_row() {
+        let mut lcd = HD44780::from_bus(Recorder::default(), 0b0011_1000);
+        let bar = VerticalBar::new(0, (0, 255), 2);
+
+        assert_eq!(bar.draw(&mut lcd, 1, 1, &mut NoDelay), Ok(()));
+    }
+}
@@ -0,0 +1,342 @@
+//! Display profiles, read from an INI style file
+//!
+//! ```text
+//! # A PCF8574 backpack, found by probing when the address is left out
+//! [default]
+//! interface = i2c
+//! device = /dev/i2c-1
+//! address = 0x27
+//! size = 20x4
+//!
+//! # A backpack with its own wiring, and a slow clone of the controller
+//! [status]
+//! interface = i2c
+//! device = /dev/i2c-1
+//! address = 0x20
+//! size = 16x2
+//! pins = rs:6 rw:5 en:4 backlight:7 d4:0 d5:1 d6:2 d7:3
+//! command_us = 80
+//! long_command_us = 3000
+//!
+//! # A display wired to GPIO lines, numbered as on the chip
+//! [panel]
+//! interface = gpio
+//! chip = /dev/gpiochip0
+//! size = 16x2
+//! pins = rs:25 en:24 d4:23 d5:17 d6:18 d7:22 backlight:27
+//! ```
+
+use std::collections::HashMap;
+
+use hd44780_driver::bus::{parse_address, PinMap};
+use hd44780_driver::timing::Timing;
+use hd44780_driver::Geometry;
+
+/// How the display is connected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Interface {
+    I2c {
+        device: String,
+        /// Probed when not given
+        address: Option<u8>,
+        pins: PinMap,
+    },
+    Gpio {
+        chip: String,
+        pins: GpioPins,
+    },
+}
+
+/// Line offsets on the GPIO chip of a display in 4-bit mode, with R/W tied
+/// low
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GpioPins {
+    pub rs: u32,
+    pub enable: u32,
+    /// D4 to D7
+    pub data: [u32; 4],
+    pub backlight: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profile {
+    pub interface: Interface,
+    pub geometry: Geometry,
+    pub timing: Timing,
+}
+
+impl Default for Profile {
+    /// A 20x4 display on a PCF8574 backpack on the first I2C bus of a
+    /// Raspberry Pi
+    fn default() -> Profile {
+        Profile {
+            interface: Interface::I2c {
+                device: "/dev/i2c-1".into(),
+                address: None,
+                pins: PinMap::default(),
+            },
+            geometry: Geometry::new(20, 4),
+            timing: Timing::default(),
+        }
+    }
+}
+
+/// Find the profile `name` in the text of a config file
+pub fn profile(config: &str, name: &str) -> Result<Profile, String> {
+    let mut sections: HashMap<&str, HashMap<&str, (usize, &str)>> = HashMap::new();
+    let mut section = None;
+
+    for (number, line) in config
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+    {
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+
+        if let Some(header) = line
+            .strip_prefix('[')
+            .and_then(|rest| rest.strip_suffix(']'))
+        {
+            section = Some(header.trim());
+            sections.entry(header.trim()).or_default();
+            continue;
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .ok_or(format!("line {}: expected key = value", number))?;
+        let section = section.ok_or(format!("line {}: key outside of a [profile]", number))?;
+
+        sections
+            .entry(section)
+            .or_default()
+            .insert(key.trim(), (number, value.trim()));
+    }
+
+    let keys = sections
+        .remove(name)
+        .ok_or(format!("no profile named {}", name))?;
+
+    parse_profile(&keys)
+}
+
+fn parse_profile(keys: &HashMap<&str, (usize, &str)>) -> Result<Profile, String> {
+    let get = |key: &str| keys.get(key).map(|&(_, value)| value);
+    let context = |key: &str, error: String| match keys.get(key) {
+        Some((number, _)) => format!("line {}: {}", number, error),
+        None => error,
+    };
+
+    if let Some(&(number, _)) = keys
+        .keys()
+        .find(|key| !KEYS.contains(key))
+        .map(|key| &keys[key])
+    {
+        return Err(format!("line {}: unknown key", number));
+    }
+
+    let mut profile = Profile::default();
+
+    if let Some(size) = get("size") {
+        profile.geometry = size
+            .parse()
+            .map_err(|_| context("size", format!("invalid size {}, expected COLSxROWS", size)))?;
+    }
+    if let Some(us) = get("command_us") {
+        profile.timing.command_us =
+            parse_number(us).map_err(|error| context("command_us", error))?;
+    }
+    if let Some(us) = get("long_command_us") {
+        profile.timing.long_command_us =
+            parse_number(us).map_err(|error| context("long_command_us", error))?;
+    }
+
+    let pins = parse_pins(get("pins").unwrap_or("")).map_err(|error| context("pins", error))?;
+    let pin = |name: &str| {
+        pins.iter()
+            .find(|&&(pin, _)| pin == name)
+            .map(|&(_, line)| line)
+    };
+
+    profile.interface = match get("interface").unwrap_or("i2c") {
+        "i2c" => {
+            let mut map = PinMap::default();
+            let set = |name: &str, bit: &mut u8| match pin(name) {
+                Some(line) if line < 8 => {
+                    *bit = line as u8;
+                    Ok(())
+                }
+                Some(line) => Err(context(
+                    "pins",
+                    format!("{}:{} is not a bit of the port", name, line),
+                )),
+                None => Ok(()),
+            };
+
+            set("rs", &mut map.rs)?;
+            set("rw", &mut map.rw)?;
+            set("en", &mut map.enable)?;
+            set("backlight", &mut map.backlight)?;
+            for (name, bit) in DATA_PINS.iter().zip(map.data.iter_mut()) {
+                set(name, bit)?;
+            }
+
+            Interface::I2c {
+                device: get("device").unwrap_or("/dev/i2c-1").into(),
+                address: get("address")
+                    .map(|address| {
+                        parse_address(address)
+                            .map_err(|_| context("address", format!("invalid address {}", address)))
+                    })
+                    .transpose()?,
+                pins: map,
+            }
+        }
+        "gpio" => {
+            let required = |name: &str| {
+                pin(name).ok_or_else(|| context("pins", format!("missing the {} line", name)))
+            };
+
+            Interface::Gpio {
+                chip: get("chip").unwrap_or("/dev/gpiochip0").into(),
+                pins: GpioPins {
+                    rs: required("rs")?,
+                    enable: required("en")?,
+                    data: [
+                        required("d4")?,
+                        required("d5")?,
+                        required("d6")?,
+                        required("d7")?,
+                    ],
+                    backlight: pin("backlight"),
+                },
+            }
+        }
+        other => {
+            return Err(context(
+                "interface",
+                format!("unknown interface {}, expected i2c or gpio", other),
+            ))
+        }
+    };
+
+    Ok(profile)
+}
+
+const KEYS: [&str; 8] = [
+    "interface",
+    "device",
+    "address",
+    "chip",
+    "size",
+    "pins",
+    "command_us",
+    "long_command_us",
+];
+
+const DATA_PINS: [&str; 4] = ["d4", "d5", "d6", "d7"];
+
+/// Parse `rs:0 en:2 ...` into (name, number) pairs
+fn parse_pins(pins: &str) -> Result<Vec<(&str, u32)>, String> {
+    pins.split_whitespace()
+        .map(|pin| {
+            let invalid = || format!("invalid pin {}, expected NAME:NUMBER", pin);
+            let (name, number) = pin.split_once(':').ok_or_else(invalid)?;
+
+            if !["rs", "rw", "en", "backlight"].contains(&name) && !DATA_PINS.contains(&name) {
+                return Err(format!("unknown pin {}", name));
+            }
+
+            Ok((name, number.parse().map_err(|_| invalid())?))
+        })
+        .collect()
+}
+
+fn parse_number(number: &str) -> Result<u16, String> {
+    number
+        .parse()
+        .map_err(|_| format!("invalid number {}", number))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    const CONFIG: &str = "
+        # Displays on the bench
+        [status]
+        interface = i2c
+        address = 0x20
+        size = 16x2
+        pins = rs:6 rw:5 en:4 backlight:7 d4:0 d5:1 d6:2 d7:3
+        command_us = 80
+
+        [panel]
+        interface = gpio
+        pins = rs:25 en:24 d4:23 d5:17 d6:18 d7:22
+    ";
+
+    #[test]
+    fn reads_profiles() {
+        let status = profile(CONFIG, "status").unwrap();
+
+        assert_eq!(
+            status.interface,
+            Interface::I2c {
+                device: "/dev/i2c-1".into(),
+                address: Some(0x20),
+                pins: PinMap {
+                    rs: 6,
+                    rw: 5,
+                    enable: 4,
+                    backlight: 7,
+                    data: [0, 1, 2, 3],
+                },
+            }
+        );
+        assert_eq!(status.geometry, Geometry::new(16, 2));
+        assert_eq!(status.timing.command_us, 80);
+        assert_eq!(
+            status.timing.long_command_us,
+            Timing::default().long_command_us
+        );
+
+        let panel = profile(CONFIG, "panel").unwrap();
+
+        assert_eq!(
+            panel.interface,
+            Interface::Gpio {
+                chip: "/dev/gpiochip0".into(),
+                pins: GpioPins {
+                    rs: 25,
+                    enable: 24,
+                    data: [23, 17, 18, 22],
+                    backlight: None,
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn reports_errors() {
+        assert_eq!(
+            profile(CONFIG, "missing"),
+            Err("no profile named missing".into())
+        );
+        assert_eq!(
+            profile("[a]\nsize = 16\n", "a"),
+            Err("line 2: invalid size 16, expected COLSxROWS".into())
+        );
+        assert_eq!(
+            profile("[a]\ninterface = gpio\npins = rs:1\n", "a"),
+            Err("line 3: missing the en line".into())
+        );
+        assert_eq!(
+            profile("[a]\ncolour = red\n", "a"),
+            Err("line 2: unknown key".into())
+        );
+    }
+}
@@ -0,0 +1,422 @@
+//! Control a display from the command line
+//!
+//! ```text
+//! hd44780 init
+//! hd44780 print --at 0,1 "Hello world"
+//! hd44780 --profile panel glyph 0 00000 01010 11111 11111 01110 00100 00000 00000
+//! hd44780 --simulate print "Hello"
+//! ```
+//!
+//! Displays are described by profiles in a config file, see the [config]
+//! module. Every command but `init` takes over the display as it is, so text
+//! printed by one command stays for the next. An I2C backpack can't report
+//! its backlight, which is switched back on by the next command.
+//!
+//! A backlight on a GPIO line only keeps its state while a process holds the
+//! line, as the kernel may reset it once the line is released. `backlight`
+//! with a GPIO profile therefore keeps running, holding the line, until it
+//! is stopped. Run it in the background or as a service.
+
+mod config;
+
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::io::ErrorKind;
+use std::process;
+
+use embedded_hal::blocking::delay::{DelayMs, DelayUs};
+use hd44780_driver::bus::DataBus;
+use hd44780_driver::sim::{NoDelay, SimBus};
+use hd44780_driver::HD44780;
+
+use config::{Interface, Profile};
+
+const USAGE: &str = "\
+Usage: hd44780 [OPTIONS] COMMAND
+
+Commands:
+    init                        Initialize and clear the display
+    clear                       Clear the display
+    print [--at COL,ROW] TEXT   Write text at the cursor, or at a position
+                                counted from 0,0 in the top left corner
+    glyph SLOT ROWS...          Define the custom character in SLOT 0 to 7
+                                from 8 rows of 5 pixels, such as 01110
+    backlight on|off            Switch the backlight. On a GPIO line it is
+                                held until the command is stopped.
+
+Options:
+    --config FILE     Profiles of the displays [default: $HD44780_CONFIG,
+                      or ~/.config/hd44780.conf]
+    --profile NAME    Profile of the display to use [default: default]
+    --simulate        Show the result on a simulated display instead
+    --help            Show this message";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Command {
+    Init,
+    Clear,
+    Print { at: Option<(u8, u8)>, text: String },
+    Glyph { slot: u8, bitmap: [u8; 8] },
+    Backlight(bool),
+}
+
+struct Options {
+    config: Option<String>,
+    profile: String,
+    simulate: bool,
+    command: Command,
+}
+
+fn parse(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut config = None;
+    let mut profile = "default".to_string();
+    let mut simulate = false;
+
+    let command = loop {
+        let arg = args.next().ok_or("no command given")?;
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+
+        match arg.as_str() {
+            "--config" => config = Some(value()?),
+            "--profile" => profile = value()?,
+            "--simulate" => simulate = true,
+            "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+            _ => break parse_command(&arg, args.collect())?,
+        }
+    };
+
+    Ok(Options {
+        config,
+        profile,
+        simulate,
+        command,
+    })
+}
+
+fn parse_command(name: &str, mut args: Vec<String>) -> Result<Command, String> {
+    let no_arguments = |command| match args.is_empty() {
+        true => Ok(command),
+        false => Err(format!("{} takes no arguments", name)),
+    };
+
+    match name {
+        "init" => no_arguments(Command::Init),
+        "clear" => no_arguments(Command::Clear),
+        "print" => {
+            let at = match args.first().map(String::as_str) {
+                Some("--at") if args.len() > 1 => {
+                    let position = parse_position(&args[1])?;
+                    args.drain(..2);
+                    Some(position)
+                }
+                Some("--at") => return Err("--at needs a value".into()),
+                _ => None,
+            };
+
+            Ok(Command::Print {
+                at,
+                text: args.join(" "),
+            })
+        }
+        "glyph" => {
+            let invalid_slot = || "the slot is a number from 0 to 7".to_string();
+            let (slot, rows) = args.split_first().ok_or_else(invalid_slot)?;
+            let slot = slot
+                .parse()
+                .ok()
+                .filter(|&slot| slot < 8)
+                .ok_or_else(invalid_slot)?;
+
+            if rows.len() != 8 {
+                return Err(format!("glyph needs 8 rows, not {}", rows.len()));
+            }
+
+            let mut bitmap = [0; 8];
+            for (pixels, row) in bitmap.iter_mut().zip(rows) {
+                *pixels = u8::from_str_radix(row, 2)
+                    .ok()
+                    .filter(|_| row.len() == 5)
+                    .ok_or(format!(
+                        "invalid row {}, expected 5 pixels such as 01110",
+                        row
+                    ))?;
+            }
+
+            Ok(Command::Glyph { slot, bitmap })
+        }
+        "backlight" => match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+            ["on"] => Ok(Command::Backlight(true)),
+            ["off"] => Ok(Command::Backlight(false)),
+            _ => Err("backlight takes on or off".into()),
+        },
+        _ => Err(format!("unknown command {}", name)),
+    }
+}
+
+fn parse_position(position: &str) -> Result<(u8, u8), String> {
+    let invalid = || format!("invalid position {}, expected COL,ROW", position);
+    let (column, row) = position.split_once(',').ok_or_else(invalid)?;
+
+    Ok((
+        column.parse().map_err(|_| invalid())?,
+        row.parse().map_err(|_| invalid())?,
+    ))
+}
+
+/// Read the profile from the config file, if there is one. Without a file,
+/// the default profile is built in.
+fn load_profile(options: &Options) -> Result<Profile, Box<dyn Error>> {
+    let (path, given) = match (&options.config, env::var("HD44780_CONFIG")) {
+        (Some(path), _) => (path.clone(), true),
+        (None, Ok(path)) => (path, true),
+        (None, Err(_)) => {
+            let home = env::var("HOME").unwrap_or_default();
+            (format!("{}/.config/hd44780.conf", home), false)
+        }
+    };
+
+    match fs::read_to_string(&path) {
+        Ok(text) => Ok(config::profile(&text, &options.profile)
+            .map_err(|error| format!("{}: {}", path, error))?),
+        Err(error) if error.kind() == ErrorKind::NotFound && !given => {
+            match options.profile.as_str() {
+                "default" => Ok(Profile::default()),
+                name => Err(format!("no profile named {}, {} doesn't exist", name, path).into()),
+            }
+        }
+        Err(error) => Err(format!("{}: {}", path, error).into()),
+    }
+}
+
+fn execute<B: DataBus, D: DelayUs<u16> + DelayMs<u8>>(
+    lcd: &mut HD44780<B>,
+    profile: &Profile,
+    command: &Command,
+    delay: &mut D,
+) -> Result<(), Box<dyn Error>> {
+    // The timing was already given when the display was opened
+    lcd.set_geometry(profile.geometry);
+
+    match command {
+        // The display was initialized when it was opened
+        Command::Init => {}
+        Command::Clear => lcd.clear(delay)?,
+        Command::Print { at, text } => {
+            if let Some(position) = *at {
+                lcd.set_cursor_xy(position, delay)?;
+            }
+            lcd.write_str(text, delay)?;
+        }
+        Command::Glyph { slot, bitmap } => lcd.set_custom_char(*slot, bitmap, delay)?,
+        Command::Backlight(on) => lcd.set_backlight(*on, delay)?,
+    }
+
+    Ok(())
+}
+
+/// Carry out the command on a display that was just initialized, and draw
+/// it in the terminal
+fn simulate(profile: &Profile, command: &Command) -> Result<(), Box<dyn Error>> {
+    let sim = SimBus::new();
+    let mut lcd = HD44780::new_simulated(sim.clone(), &mut NoDelay)?;
+
+    execute(&mut lcd, profile, command, &mut NoDelay)?;
+
+    let geometry = profile.geometry;
+    let border = "-".repeat(geometry.columns as usize);
+
+    println!("+{}+", border);
+    for row in sim.text(geometry) {
+        println!("|{}|", row);
+    }
+    println!("+{}+", border);
+
+    if let Command::Glyph { slot, .. } = command {
+        for row in sim.custom_char(*slot).iter() {
+            let pixels: String = (0..5)
+                .rev()
+                .map(|bit| if row & 1 << bit != 0 { '#' } else { '.' })
+                .collect();
+            println!("{}", pixels);
+        }
+    }
+
+    println!("backlight {}", if sim.backlight() { "on" } else { "off" });
+
+    Ok(())
+}
+
+#[cfg(feature = "linux")]
+mod linux {
+    use std::error::Error;
+    use std::thread;
+
+    use linux_embedded_hal::gpio_cdev::{Chip, LineRequestFlags};
+    use linux_embedded_hal::{CdevPin, Delay, I2cdev};
+
+    use hd44780_driver::bus::{probe_i2c, FourBitBus, I2CBus, PinMap};
+    use hd44780_driver::HD44780;
+
+    use super::config::{GpioPins, Profile};
+    use super::{execute, Command};
+
+    const CONSUMER: &str = "hd44780";
+
+    pub fn run_i2c(
+        profile: &Profile,
+        device: &str,
+        address: Option<u8>,
+        pins: PinMap,
+        command: &Command,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut delay = Delay;
+        let mut i2c = I2cdev::new(device)?;
+
+        let address = match address {
+            Some(address) => address,
+            None => probe_i2c(&mut i2c)?,
+        };
+
+        let bus = I2CBus::with_pins(i2c, address, pins);
+        let mut lcd = match command {
+            Command::Init => HD44780::new_4bit_bus(bus, profile.timing, &mut delay)?,
+            _ => {
+                let mut lcd = HD44780::attach_4bit_bus(bus, profile.timing, &mut delay)?;
+                lcd.reload(&mut delay)?;
+                lcd
+            }
+        };
+
+        execute(&mut lcd, profile, command, &mut delay)
+    }
+
+    pub fn run_gpio(
+        profile: &Profile,
+        chip: &str,
+        pins: GpioPins,
+        command: &Command,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut delay = Delay;
+        let mut chip = Chip::new(chip)?;
+
+        // The backlight is switched on its own line, leaving the display be.
+        // The line is held until the process is stopped, as its value isn't
+        // guaranteed to last once it is released.
+        if let Command::Backlight(on) = *command {
+            let line = pins.backlight.ok_or("the profile has no backlight line")?;
+            let _handle =
+                chip.get_line(line)?
+                    .request(LineRequestFlags::OUTPUT, on as u8, CONSUMER)?;
+
+            loop {
+                thread::park();
+            }
+        }
+
+        let mut output = |line: u32| -> Result<CdevPin, Box<dyn Error>> {
+            let handle = chip
+                .get_line(line)?
+                .request(LineRequestFlags::OUTPUT, 0, CONSUMER)?;
+
+            Ok(CdevPin::new(handle)?)
+        };
+
+        let rs = output(pins.rs)?;
+        let en = output(pins.enable)?;
+        let [d4, d5, d6, d7] = pins.data;
+        let (d4, d5, d6, d7) = (output(d4)?, output(d5)?, output(d6)?, output(d7)?);
+
+        let bus = FourBitBus::from_pins(rs, en, d4, d5, d6, d7);
+        let mut lcd = match command {
+            Command::Init => HD44780::new_4bit_bus(bus, profile.timing, &mut delay)?,
+            _ => HD44780::attach_4bit_bus(bus, profile.timing, &mut delay)?,
+        };
+
+        execute(&mut lcd, profile, command, &mut delay)
+    }
+}
+
+fn run(options: Options) -> Result<(), Box<dyn Error>> {
+    let profile = load_profile(&options)?;
+
+    if options.simulate {
+        return simulate(&profile, &options.command);
+    }
+
+    #[cfg(feature = "linux")]
+    match &profile.interface {
+        Interface::I2c {
+            device,
+            address,
+            pins,
+        } => linux::run_i2c(&profile, device, *address, *pins, &options.command),
+        Interface::Gpio { chip, pins } => linux::run_gpio(&profile, chip, *pins, &options.command),
+    }
+
+    #[cfg(not(feature = "linux"))]
+    match profile.interface {
+        Interface::I2c { .. } | Interface::Gpio { .. } => {
+            Err("built without display support, enable the linux feature or use --simulate".into())
+        }
+    }
+}
+
+fn main() {
+    let result = parse(env::args().skip(1))
+        .map_err(|error| error.into())
+        .and_then(run);
+
+    if let Err(error) = result {
+        eprintln!("hd44780: {}", error);
+        eprintln!("{}", USAGE);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn command(args: &[&str]) -> Result<Command, String> {
+        parse(args.iter().map(|arg| arg.to_string())).map(|options| options.command)
+    }
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(
+            command(&["--simulate", "print", "--at", "2,1", "Hello", "world"]),
+            Ok(Command::Print {
+                at: Some((2, 1)),
+                text: "Hello world".into(),
+            })
+        );
+        assert_eq!(
+            command(&[
+                "glyph", "3", "00000", "01010", "11111", "11111", "01110", "00100", "00000",
+                "00000"
+            ]),
+            Ok(Command::Glyph {
+                slot: 3,
+                bitmap: [0, 0b01010, 0b11111, 0b11111, 0b01110, 0b00100, 0, 0],
+            })
+        );
+        assert_eq!(
+            command(&["backlight", "off"]),
+            Ok(Command::Backlight(false))
+        );
+        assert_eq!(
+            command(&["glyph", "8"]),
+            Err("the slot is a number from 0 to 7".into())
+        );
+        assert_eq!(
+            command(&["clear", "now"]),
+            Err("clear takes no arguments".into())
+        );
+    }
+}
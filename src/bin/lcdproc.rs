@@ -0,0 +1,167 @@
+//! An LCDproc server showing the screens of its clients on a display
+//!
+//! ```text
+//! hd44780-lcdproc --simulate
+//! hd44780-lcdproc --i2c /dev/i2c-1 --size 16x2
+//! ```
+
+use std::env;
+use std::error::Error;
+use std::net::TcpListener;
+use std::process;
+use std::sync::atomic::AtomicBool;
+use std::thread;
+use std::time::Duration;
+
+use embedded_hal::blocking::delay::{DelayMs, DelayUs};
+use hd44780_driver::bus::{parse_address, DataBus};
+use hd44780_driver::lcdproc;
+use hd44780_driver::sim::{NoDelay, SimBus};
+use hd44780_driver::{Geometry, HD44780};
+
+const USAGE: &str = "\
+Usage: hd44780-lcdproc [OPTIONS]
+
+Options:
+    --listen ADDRESS    Address to listen on [default: 127.0.0.1:13666]
+    --size COLSxROWS    Size of the display [default: 20x4]
+    --simulate          Show a simulated display in the terminal
+    --i2c DEVICE        I2C bus with the backpack, such as /dev/i2c-1
+    --address ADDRESS   I2C address of the backpack, probed when left out
+    --help              Show this message";
+
+struct Options {
+    listen: String,
+    geometry: Geometry,
+    simulate: bool,
+    i2c: Option<String>,
+    address: Option<u8>,
+}
+
+fn parse(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options {
+        listen: format!("127.0.0.1:{}", lcdproc::PORT),
+        geometry: Geometry::new(20, 4),
+        simulate: false,
+        i2c: None,
+        address: None,
+    };
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+
+        match arg.as_str() {
+            "--listen" => options.listen = value()?,
+            "--size" => {
+                let size = value()?;
+                options.geometry = size
+                    .parse()
+                    .map_err(|_| format!("invalid size {}, expected COLSxROWS", size))?;
+            }
+            "--simulate" => options.simulate = true,
+            "--i2c" => options.i2c = Some(value()?),
+            "--address" => {
+                let address = value()?;
+                options.address = Some(
+                    parse_address(&address).map_err(|_| format!("invalid address {}", address))?,
+                );
+            }
+            "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            _ => return Err(format!("unknown option {}", arg)),
+        }
+    }
+
+    Ok(options)
+}
+
+fn serve<B: DataBus, D: DelayUs<u16> + DelayMs<u8>>(
+    options: &Options,
+    mut lcd: HD44780<B>,
+    mut delay: D,
+) -> Result<(), Box<dyn Error>> {
+    let listener = TcpListener::bind(&options.listen)?;
+    let stop = AtomicBool::new(false);
+
+    eprintln!("listening on {}", listener.local_addr()?);
+    lcd.set_geometry(options.geometry);
+    lcdproc::serve(listener, &mut lcd, &mut delay, &stop)?;
+
+    Ok(())
+}
+
+/// Draw the simulated display in the terminal whenever it changes
+fn show(sim: SimBus, geometry: Geometry) {
+    let mut shown = Vec::new();
+
+    loop {
+        let text = sim.text(geometry);
+
+        if text != shown {
+            let border = "-".repeat(geometry.columns as usize);
+
+            // Clear the terminal and draw from the top
+            print!("\x1b[2J\x1b[H");
+            println!("+{}+", border);
+            for row in &text {
+                println!("|{}|", row);
+            }
+            println!("+{}+", border);
+
+            shown = text;
+        }
+
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+#[cfg(feature = "linux")]
+fn serve_i2c(options: &Options, device: &str) -> Result<(), Box<dyn Error>> {
+    use linux_embedded_hal::{Delay, I2cdev};
+
+    let mut delay = Delay;
+    let i2c = I2cdev::new(device)?;
+
+    let lcd = match options.address {
+        Some(address) => HD44780::new_i2c(i2c, address, &mut delay)?,
+        None => HD44780::new_i2c_probe(i2c, &mut delay)?.0,
+    };
+
+    serve(options, lcd, delay)
+}
+
+#[cfg(not(feature = "linux"))]
+fn serve_i2c(_options: &Options, _device: &str) -> Result<(), Box<dyn Error>> {
+    Err("built without I2C support, enable the linux feature".into())
+}
+
+fn run(options: Options) -> Result<(), Box<dyn Error>> {
+    if options.simulate {
+        let sim = SimBus::new();
+        let lcd = HD44780::new_simulated(sim.clone(), &mut NoDelay)?;
+        let geometry = options.geometry;
+
+        thread::spawn(move || show(sim, geometry));
+
+        return serve(&options, lcd, NoDelay);
+    }
+
+    match &options.i2c {
+        Some(device) => serve_i2c(&options, device),
+        None => Err("no display given, use --i2c or --simulate".into()),
+    }
+}
+
+fn main() {
+    let result = parse(env::args().skip(1))
+        .map_err(|error| error.into())
+        .and_then(run);
+
+    if let Err(error) = result {
+        eprintln!("hd44780-lcdproc: {}", error);
+        eprintln!("{}", USAGE);
+        process::exit(1);
+    }
+}
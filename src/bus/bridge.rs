@@ -0,0 +1,330 @@
+//! Drive a display at the other end of a byte stream (`bridge` feature)
+//!
+//! [`BridgeBus`] sends what the driver writes over any `embedded-io`
+//! stream, such as a UART or a TCP connection, and a [`Receiver`] at the
+//! other end replays it onto the bus of the display, waiting for each byte
+//! to be carried out.
+//!
+//! There is no flow control: the sender must wait as long as the receiver
+//! does, so it needs a real delay and the same
+//! [timing](../../timing/struct.Timing.html) as the receiver. The driver
+//! waits for each byte as its timing table says, and the bus adds the
+//! longer wait after a function set. A stream that holds on to bytes, such
+//! as a buffered TCP socket, hands them over in bursts, which the receiver
+//! then needs room to buffer.
+//!
+//! Each byte travels as a frame of two bytes: a control byte, then the low
+//! seven bits of the byte. The control byte holds
+//!
+//! | Bits | Meaning                                                     |
+//! |------|-------------------------------------------------------------|
+//! | 7    | Set, marking the start of a frame                           |
+//! | 6    | Bit 7 of the byte                                           |
+//! | 5-4  | Clear                                                       |
+//! | 3-2  | How long the byte takes, see [`Hint`]                       |
+//! | 1    | Backlight on                                                |
+//! | 0    | Register select, set for data and clear for an instruction  |
+//!
+//! Only control bytes have bit 7 set, so a receiver joining the stream
+//! halfway through finds the frames again at the next one. The stream
+//! initializes the display in 4-bit mode, so the bus replayed onto must be
+//! an I2C backpack or a [`FourBitBus`](../struct.FourBitBus.html).
+//!
+//! ```rust,ignore
+//! // On the host
+//! let mut lcd = HD44780::new_bridge(uart_tx, &mut delay)?;
+//! lcd.write_str("Hello", &mut delay)?;
+//!
+//! // Next to the display
+//! let mut receiver = Receiver::new(I2CBus::new(i2c, 0x27));
+//! loop {
+//!     receiver.receive(&mut uart_rx, &mut delay)?;
+//! }
+//! ```
+
+use embedded_hal::blocking::delay::{DelayMs, DelayUs};
+use embedded_io::{Read, Write};
+
+use crate::bus::DataBus;
+use crate::error::{Error, Result};
+use crate::timing::Timing;
+
+const CONTROL: u8 = 0b1000_0000;
+const HIGH_BIT: u8 = 0b0100_0000;
+const BACKLIGHT: u8 = 0b0000_0010;
+const REGISTER_SELECT: u8 = 0b0000_0001;
+
+/// Milliseconds waited after a function set, as the initialization needs
+const FUNCTION_SET_MS: u8 = 5;
+
+/// How long the display takes to carry out a byte, sent along with it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hint {
+    /// Data writes and most instructions
+    Command,
+    /// Clear display and return home
+    LongCommand,
+    /// Function set, which needs more time while the display is initialized
+    FunctionSet,
+}
+
+impl Hint {
+    /// The hint for `byte`, written as data or as an instruction
+    pub fn of(byte: u8, data: bool) -> Hint {
+        match byte {
+            _ if data => Hint::Command,
+            0b0000_0000..=0b0000_0011 => Hint::LongCommand,
+            0b0010_0000..=0b0011_1111 => Hint::FunctionSet,
+            _ => Hint::Command,
+        }
+    }
+
+    fn bits(self) -> u8 {
+        match self {
+            Hint::Command => 0b0000_0000,
+            Hint::LongCommand => 0b0000_0100,
+            Hint::FunctionSet => 0b0000_1000,
+        }
+    }
+
+    fn from_bits(control: u8) -> Hint {
+        match control & 0b0000_1100 {
+            0b0000_0100 => Hint::LongCommand,
+            0b0000_1000 => Hint::FunctionSet,
+            _ => Hint::Command,
+        }
+    }
+}
+
+/// Sends the bytes written to the display over a stream, see the
+/// [module documentation](index.html)
+pub struct BridgeBus<S: Write> {
+    stream: S,
+    backlight: bool,
+}
+
+impl<S: Write> BridgeBus<S> {
+    pub fn new(stream: S) -> BridgeBus<S> {
+        BridgeBus {
+            stream,
+            backlight: true,
+        }
+    }
+
+    /// Send the frames the stream holds on to
+    pub fn flush(&mut self) -> Result<()> {
+        self.stream.flush().map_err(|_| Error::Io)
+    }
+
+    /// Give back the stream
+    pub fn release(self) -> S {
+        self.stream
+    }
+}
+
+impl<S: Write> DataBus for BridgeBus<S> {
+    fn write<D: DelayUs<u16> + DelayMs<u8>>(
+        &mut self,
+        byte: u8,
+        data: bool,
+        delay: &mut D,
+    ) -> Result<()> {
+        let hint = Hint::of(byte, data);
+        let mut control = CONTROL | hint.bits();
+
+        if self.backlight {
+            control |= BACKLIGHT;
+        }
+        if data {
+            control |= REGISTER_SELECT;
+        }
+        if byte & 0b1000_0000 != 0 {
+            control |= HIGH_BIT;
+        }
+
+        self.stream
+            .write_all(&[control, byte & 0b0111_1111])
+            .map_err(|_| Error::Io)?;
+
+        // The driver only waits as long as for any other instruction, so
+        // make up the difference to keep up with the receiver
+        if hint == Hint::FunctionSet {
+            delay.delay_ms(FUNCTION_SET_MS);
+        }
+
+        Ok(())
+    }
+
+    fn set_backlight(&mut self, on: bool) {
+        self.backlight = on;
+    }
+}
+
+/// Replays the frames sent by a [`BridgeBus`] onto the bus of a display
+pub struct Receiver<B: DataBus> {
+    bus: B,
+    timing: Timing,
+    /// Control byte of the frame being received
+    control: Option<u8>,
+}
+
+impl<B: DataBus> Receiver<B> {
+    pub fn new(bus: B) -> Receiver<B> {
+        Receiver {
+            bus,
+            timing: Timing::default(),
+            control: None,
+        }
+    }
+
+    /// Wait for the display as `timing` says, for slower clones of the
+    /// controller
+    pub fn set_timing(&mut self, timing: Timing) {
+        self.timing = timing;
+    }
+
+    /// Handle the next byte from the stream, for sources that hand them
+    /// over one at a time such as a UART interrupt
+    pub fn feed<D: DelayUs<u16> + DelayMs<u8>>(
+        &mut self,
+        mut byte: u8,
+        delay: &mut D,
+    ) -> Result<()> {
+        if byte & CONTROL != 0 {
+            self.control = Some(byte);
+            return Ok(());
+        }
+
+        // Skip bytes until the start of a frame
+        let control = match self.control.take() {
+            Some(control) => control,
+            None => return Ok(()),
+        };
+
+        if control & HIGH_BIT != 0 {
+            byte |= 0b1000_0000;
+        }
+
+        self.bus.set_backlight(control & BACKLIGHT != 0);
+        self.bus
+            .write(byte, control & REGISTER_SELECT != 0, delay)?;
+
+        match Hint::from_bits(control) {
+            Hint::Command => delay.delay_us(self.timing.command_us),
+            Hint::LongCommand => delay.delay_us(self.timing.long_command_us),
+            Hint::FunctionSet => delay.delay_ms(FUNCTION_SET_MS),
+        }
+
+        Ok(())
+    }
+
+    /// Read from `stream` and replay the frames that arrived, returning the
+    /// number of bytes read. A frame cut in two by a read is finished by
+    /// the next one. Returns 0 at the end of the stream.
+    pub fn receive<R: Read, D: DelayUs<u16> + DelayMs<u8>>(
+        &mut self,
+        stream: &mut R,
+        delay: &mut D,
+    ) -> Result<usize> {
+        let mut buffer = [0; 32];
+        let len = stream.read(&mut buffer).map_err(|_| Error::Io)?;
+
+        for &byte in &buffer[..len] {
+            self.feed(byte, delay)?;
+        }
+
+        Ok(len)
+    }
+
+    /// Give back the bus of the display
+    pub fn release(self) -> B {
+        self.bus
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    use crate::mock::{NoDelay, Recorder};
+    use crate::HD44780;
+
+    #[test]
+    fn replays_the_stream() {
+        let mut stream = [0; 64];
+        let mut lcd = HD44780::new_bridge(&mut stream[..], &mut NoDelay).unwrap();
+
+        lcd.write_str("Hi", &mut NoDelay).unwrap();
+        lcd.set_backlight(false, &mut NoDelay).unwrap();
+
+        let left = lcd.release().release().len();
+        let sent = 64 - left;
+
+        // Read back in two parts, splitting a frame
+        let mut receiver = Receiver::new(Recorder::default());
+        let mut input = &stream[..sent];
+
+        // A stray byte before the first frame is skipped
+        receiver.feed(0x55, &mut NoDelay).unwrap();
+        assert_eq!(receiver.receive(&mut (&input[..5]), &mut NoDelay), Ok(5));
+        input = &input[5..];
+        while receiver.receive(&mut input, &mut NoDelay).unwrap() > 0 {}
+
+        let bus = receiver.release();
+
+        assert_eq!(
+            bus.written[..bus.len],
+            [
+                (0x33, false),
+                (0x32, false),
+                (0b0010_1000, false),
+                (0x0E, false),
+                (0x01, false),
+                (0b0000_0110, false),
+                (0x80, false),
+                (b'H', true),
+                (b'i', true),
+                (0b0000_1111, false),
+            ]
+        );
+        assert_eq!(stream[sent - 2] & BACKLIGHT, 0);
+        assert_eq!(stream[sent - 4] & BACKLIGHT, BACKLIGHT);
+    }
+
+    #[test]
+    fn carries_high_bytes() {
+        let mut stream = [0; 4];
+        let mut bus = BridgeBus::new(&mut stream[..]);
+
+        // Text and a DDRAM address, neither taken for a control byte
+        bus.write(0xA5, true, &mut NoDelay).unwrap();
+        bus.write(0xA0, false, &mut NoDelay).unwrap();
+
+        let mut receiver = Receiver::new(Recorder::default());
+        let mut input = &stream[..];
+        while receiver.receive(&mut input, &mut NoDelay).unwrap() > 0 {}
+
+        // Joining halfway through the first frame
+        let mut late = Receiver::new(Recorder::default());
+        late.receive(&mut (&stream[1..]), &mut NoDelay).unwrap();
+
+        let bus = receiver.release();
+        let late = late.release();
+
+        assert_eq!(bus.written[..bus.len], [(0xA5, true), (0xA0, false)]);
+        assert_eq!(late.written[..late.len], [(0xA0, false)]);
+    }
+
+    #[test]
+    fn hints_timing() {
+        assert_eq!(Hint::of(0x01, false), Hint::LongCommand);
+        assert_eq!(Hint::of(0x01, true), Hint::Command);
+        assert_eq!(Hint::of(0x33, false), Hint::FunctionSet);
+        assert_eq!(Hint::of(0x80, false), Hint::Command);
+
+        for hint in [Hint::Command, Hint::LongCommand, Hint::FunctionSet] {
+            assert_eq!(Hint::from_bits(CONTROL | hint.bits()), hint);
+        }
+    }
+}
@@ -41,6 +41,11 @@ impl<RS: OutputPin, EN: OutputPin, D4: OutputPin, D5: OutputPin, D6: OutputPin,
         }
     }
 
+    /// Give back the pins
+    pub fn release(self) -> (RS, EN, D4, D5, D6, D7) {
+        (self.rs, self.en, self.d4, self.d5, self.d6, self.d7)
+    }
+
     fn write_lower_nibble(&mut self, data: u8) -> Result<()> {
         let db0: bool = (0b0000_0001 & data) != 0;
         let db1: bool = (0b0000_0010 & data) != 0;
@@ -48,27 +53,27 @@ impl<RS: OutputPin, EN: OutputPin, D4: OutputPin, D5: OutputPin, D6: OutputPin,
         let db3: bool = (0b0000_1000 & data) != 0;
 
         if db0 {
-            self.d4.set_high().map_err(|_| Error)?;
+            self.d4.set_high().map_err(|_| Error::Io)?;
         } else {
-            self.d4.set_low().map_err(|_| Error)?;
+            self.d4.set_low().map_err(|_| Error::Io)?;
         }
 
         if db1 {
-            self.d5.set_high().map_err(|_| Error)?;
+            self.d5.set_high().map_err(|_| Error::Io)?;
         } else {
-            self.d5.set_low().map_err(|_| Error)?;
+            self.d5.set_low().map_err(|_| Error::Io)?;
         }
 
         if db2 {
-            self.d6.set_high().map_err(|_| Error)?;
+            self.d6.set_high().map_err(|_| Error::Io)?;
         } else {
-            self.d6.set_low().map_err(|_| Error)?;
+            self.d6.set_low().map_err(|_| Error::Io)?;
         }
 
         if db3 {
-            self.d7.set_high().map_err(|_| Error)?;
+            self.d7.set_high().map_err(|_| Error::Io)?;
         } else {
-            self.d7.set_low().map_err(|_| Error)?;
+            self.d7.set_low().map_err(|_| Error::Io)?;
         }
 
         Ok(())
@@ -81,27 +86,27 @@ impl<RS: OutputPin, EN: OutputPin, D4: OutputPin, D5: OutputPin, D6: OutputPin,
         let db7: bool = (0b1000_0000 & data) != 0;
 
         if db4 {
-            self.d4.set_high().map_err(|_| Error)?;
+            self.d4.set_high().map_err(|_| Error::Io)?;
         } else {
-            self.d4.set_low().map_err(|_| Error)?;
+            self.d4.set_low().map_err(|_| Error::Io)?;
         }
 
         if db5 {
-            self.d5.set_high().map_err(|_| Error)?;
+            self.d5.set_high().map_err(|_| Error::Io)?;
         } else {
-            self.d5.set_low().map_err(|_| Error)?;
+            self.d5.set_low().map_err(|_| Error::Io)?;
         }
 
         if db6 {
-            self.d6.set_high().map_err(|_| Error)?;
+            self.d6.set_high().map_err(|_| Error::Io)?;
         } else {
-            self.d6.set_low().map_err(|_| Error)?;
+            self.d6.set_low().map_err(|_| Error::Io)?;
         }
 
         if db7 {
-            self.d7.set_high().map_err(|_| Error)?;
+            self.d7.set_high().map_err(|_| Error::Io)?;
         } else {
-            self.d7.set_low().map_err(|_| Error)?;
+            self.d7.set_low().map_err(|_| Error::Io)?;
         }
         Ok(())
     }
@@ -117,27 +122,27 @@ impl<RS: OutputPin, EN: OutputPin, D4: OutputPin, D5: OutputPin, D6: OutputPin,
         delay: &mut D,
     ) -> Result<()> {
         if data {
-            self.rs.set_high().map_err(|_| Error)?;
+            self.rs.set_high().map_err(|_| Error::Io)?;
         } else {
-            self.rs.set_low().map_err(|_| Error)?;
+            self.rs.set_low().map_err(|_| Error::Io)?;
         }
 
         self.write_upper_nibble(byte)?;
 
         // Pulse the enable pin to recieve the upper nibble
-        self.en.set_high().map_err(|_| Error)?;
+        self.en.set_high().map_err(|_| Error::Io)?;
         delay.delay_ms(2u8);
-        self.en.set_low().map_err(|_| Error)?;
+        self.en.set_low().map_err(|_| Error::Io)?;
 
         self.write_lower_nibble(byte)?;
 
         // Pulse the enable pin to recieve the lower nibble
-        self.en.set_high().map_err(|_| Error)?;
+        self.en.set_high().map_err(|_| Error::Io)?;
         delay.delay_ms(2u8);
-        self.en.set_low().map_err(|_| Error)?;
+        self.en.set_low().map_err(|_| Error::Io)?;
 
         if data {
-            self.rs.set_low().map_err(|_| Error)?;
+            self.rs.set_low().map_err(|_| Error::Io)?;
         }
         Ok(())
     }
@@ -1,21 +1,133 @@
 use embedded_hal::blocking::delay::{DelayMs, DelayUs};
-use embedded_hal::blocking::i2c::Write;
+use embedded_hal::blocking::i2c::{Read, Write};
 
-use crate::{bus::DataBus, error::Result};
+use crate::{
+    bus::{DataBus, ReadableBus},
+    error::{Error, Result},
+};
 
 pub struct I2CBus<I2C: Write> {
     i2c_bus: I2C,
     address: u8,
+    pins: PinMap,
+    backlight: u8,
+}
+
+/// Which expander output drives each line of the `HD44780`, as bit numbers
+/// from 0 to 7
+///
+/// The default is the wiring of the common PCF8574 backpacks. Others, such
+/// as some sold with 16x2 displays, swap the lines around:
+///
+/// ```rust,ignore
+/// let pins = PinMap {
+///     rs: 6,
+///     rw: 5,
+///     enable: 4,
+///     backlight: 7,
+///     data: [0, 1, 2, 3],
+/// };
+/// let mut lcd = HD44780::new_i2c_with_pins(i2c, 0x20, pins, &mut delay)?;
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PinMap {
+    pub rs: u8,
+    pub rw: u8,
+    pub enable: u8,
+    pub backlight: u8,
+    /// D4 to D7
+    pub data: [u8; 4],
+}
+
+impl Default for PinMap {
+    fn default() -> PinMap {
+        PinMap {
+            rs: 0,
+            rw: 1,
+            enable: 2,
+            backlight: 3,
+            data: [4, 5, 6, 7],
+        }
+    }
+}
+
+impl PinMap {
+    /// Expander outputs for the nibble in the upper half of `nibble`
+    fn port(&self, nibble: u8) -> u8 {
+        self.data
+            .iter()
+            .enumerate()
+            .filter(|&(bit, _)| nibble & (0b0001_0000 << bit) != 0)
+            .fold(0, |port, (_, &pin)| port | 1 << pin)
+    }
+
+    /// The nibble read on the expander `port`, in the upper half
+    fn nibble(&self, port: u8) -> u8 {
+        self.data
+            .iter()
+            .enumerate()
+            .filter(|&(_, &pin)| port & (1 << pin) != 0)
+            .fold(0, |nibble, (bit, _)| nibble | 0b0001_0000 << bit)
+    }
 }
 
 const BACKLIGHT: u8 = 0b0000_1000;
-const ENABLE: u8 = 0b0000_0100;
-// const READ_WRITE: u8 = 0b0000_0010; // Not used as no reading of the `HD44780` is done
-const REGISTER_SELECT: u8 = 0b0000_0001;
+
+/// Addresses of PCF8574 and PCF8574A based backpacks, most common first
+pub const PROBE_ADDRESSES: [u8; 16] = [
+    0x27, 0x3F, 0x20, 0x21, 0x22, 0x23, 0x24, 0x25, 0x26, 0x38, 0x39, 0x3A, 0x3B, 0x3C, 0x3D, 0x3E,
+];
+
+/// Find the address of an I2C backpack by trying each of [`PROBE_ADDRESSES`]
+///
+/// At each address, all the lines of the expander are driven low except the
+/// backlight, which the `HD44780` ignores as its enable line stays low. The
+/// port is then read back, and a PCF8574 returns the lines it drives low as
+/// low. Other devices on the bus in these ranges, such as an MCP23008, may
+/// also receive the probing byte.
+pub fn probe_i2c<I2C: Write + Read>(i2c_bus: &mut I2C) -> Result<u8> {
+    PROBE_ADDRESSES
+        .iter()
+        .copied()
+        .find(|&address| {
+            let mut port = [0];
+
+            i2c_bus.write(address, &[BACKLIGHT]).is_ok()
+                && i2c_bus.read(address, &mut port).is_ok()
+                && port[0] & !BACKLIGHT == 0
+        })
+        .ok_or(Error::NotFound)
+}
+
+/// Parse an I2C address written in decimal or in hex after `0x`, as given
+/// on a command line or in a config file
+pub fn parse_address(address: &str) -> Result<u8> {
+    let parsed = match address.strip_prefix("0x") {
+        Some(hex) => u8::from_str_radix(hex, 16),
+        None => address.parse(),
+    };
+
+    parsed.map_err(|_| Error::InvalidArgument)
+}
 
 impl<I2C: Write> I2CBus<I2C> {
     pub fn new(i2c_bus: I2C, address: u8) -> I2CBus<I2C> {
-        I2CBus { i2c_bus, address }
+        I2CBus::with_pins(i2c_bus, address, PinMap::default())
+    }
+
+    /// Create a bus for a backpack wired differently from the common one
+    pub fn with_pins(i2c_bus: I2C, address: u8, pins: PinMap) -> I2CBus<I2C> {
+        I2CBus {
+            i2c_bus,
+            address,
+            pins,
+            backlight: 1 << pins.backlight,
+        }
+    }
+
+    /// Give back the I2C peripheral
+    pub fn release(self) -> I2C {
+        self.i2c_bus
     }
 
     /// Write a nibble to the lcd
@@ -25,16 +137,48 @@ impl<I2C: Write> I2CBus<I2C> {
         nibble: u8,
         data: bool,
         delay: &mut D,
-    ) {
+    ) -> Result<()> {
         let rs = match data {
             false => 0u8,
-            true => REGISTER_SELECT,
+            true => 1 << self.pins.rs,
         };
-        let byte = nibble | rs | BACKLIGHT;
+        let byte = self.pins.port(nibble) | rs | self.backlight;
+        let enable = 1 << self.pins.enable;
 
-        let _ = self.i2c_bus.write(self.address, &[byte, byte | ENABLE]);
+        self.i2c_bus
+            .write(self.address, &[byte, byte | enable])
+            .map_err(|_| Error::Io)?;
         delay.delay_ms(2u8);
-        let _ = self.i2c_bus.write(self.address, &[byte]);
+        self.i2c_bus
+            .write(self.address, &[byte])
+            .map_err(|_| Error::Io)
+    }
+}
+
+impl<I2C: Write + Read> I2CBus<I2C> {
+    /// Read a nibble from the lcd
+    /// The nibble is returned in the upper part of the byte
+    fn read_nibble(&mut self, data: bool) -> Result<u8> {
+        let rs = match data {
+            false => 0u8,
+            true => 1 << self.pins.rs,
+        };
+        // The data lines are left high so the `HD44780` can pull them low
+        let byte = self.pins.port(0xF0) | 1 << self.pins.rw | rs | self.backlight;
+        let enable = 1 << self.pins.enable;
+        let mut port = [0];
+
+        self.i2c_bus
+            .write(self.address, &[byte, byte | enable])
+            .map_err(|_| Error::Io)?;
+        self.i2c_bus
+            .read(self.address, &mut port)
+            .map_err(|_| Error::Io)?;
+        self.i2c_bus
+            .write(self.address, &[byte])
+            .map_err(|_| Error::Io)?;
+
+        Ok(self.pins.nibble(port[0]))
     }
 }
 
@@ -46,11 +190,97 @@ impl<I2C: Write> DataBus for I2CBus<I2C> {
         delay: &mut D,
     ) -> Result<()> {
         let upper_nibble = byte & 0xF0;
-        self.write_nibble(upper_nibble, data, delay);
+        self.write_nibble(upper_nibble, data, delay)?;
 
         let lower_nibble = (byte & 0x0F) << 4;
-        self.write_nibble(lower_nibble, data, delay);
+        self.write_nibble(lower_nibble, data, delay)
+    }
+
+    fn set_backlight(&mut self, on: bool) {
+        self.backlight = if on { 1 << self.pins.backlight } else { 0 };
+    }
+}
+
+impl<I2C: Write + Read> ReadableBus for I2CBus<I2C> {
+    fn read<D: DelayUs<u16> + DelayMs<u8>>(&mut self, data: bool, _delay: &mut D) -> Result<u8> {
+        let upper_nibble = self.read_nibble(data)?;
+        let lower_nibble = self.read_nibble(data)?;
+
+        Ok(upper_nibble | (lower_nibble >> 4))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    use crate::mock::{I2cLog, NoDelay};
+    use crate::HD44780;
+
+    #[test]
+    fn probes_addresses() {
+        let mut i2c = I2cLog::default();
+
+        i2c.present = Some(0x3B);
+        assert_eq!(probe_i2c(&mut i2c), Ok(0x3B));
+
+        i2c.present = Some(0x50);
+        assert_eq!(probe_i2c(&mut i2c), Err(Error::NotFound));
+    }
+
+    #[test]
+    fn parses_addresses() {
+        assert_eq!(parse_address("0x27"), Ok(0x27));
+        assert_eq!(parse_address("63"), Ok(0x3F));
+        assert_eq!(parse_address("0x127"), Err(Error::InvalidArgument));
+    }
+
+    #[test]
+    fn releases_peripheral() {
+        let lcd = HD44780::new_i2c(I2cLog::default(), 0x27, &mut NoDelay).unwrap();
+
+        let i2c = lcd.release().release();
+        assert!(i2c.len > 0);
+    }
+
+    #[test]
+    fn reads_nibbles() {
+        let mut i2c = I2cLog::default();
+        i2c.input = Some(0b1010_0000);
+
+        let mut bus = I2CBus::new(i2c, 0x27);
+        assert_eq!(bus.read(true, &mut NoDelay), Ok(0b1010_1010));
+
+        // Enable raised and lowered around each read, with R/W high
+        let i2c = bus.release();
+        assert_eq!(i2c.len, 4);
+        assert!(i2c.transfers[..4]
+            .iter()
+            .all(|&(_, byte)| byte & 0b0000_0011 != 0));
+    }
+
+    #[test]
+    fn remaps_pins() {
+        let pins = PinMap {
+            rs: 6,
+            rw: 5,
+            enable: 4,
+            backlight: 7,
+            data: [0, 1, 2, 3],
+        };
+        let mut i2c = I2cLog::default();
+        i2c.input = Some(0b0000_0101);
+
+        let mut bus = I2CBus::with_pins(i2c, 0x20, pins);
+        bus.write(0b1001_0110, true, &mut NoDelay).unwrap();
+        assert_eq!(bus.read(false, &mut NoDelay), Ok(0b0101_0101));
 
-        Ok(())
+        let i2c = bus.release();
+        // Enable raised then lowered, with D4 and D7 on pins 0 and 3
+        assert_eq!(
+            i2c.transfers[..2],
+            [(0x20, 0b1101_1001), (0x20, 0b1100_1001)]
+        );
     }
 }
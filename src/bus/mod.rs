@@ -1,12 +1,15 @@
 use embedded_hal::blocking::delay::{DelayMs, DelayUs};
 
+#[cfg(feature = "bridge")]
+pub mod bridge;
 mod eightbit;
 mod fourbit;
 mod i2c;
+pub mod shared;
 
 pub use self::eightbit::EightBitBus;
 pub use self::fourbit::FourBitBus;
-pub use self::i2c::I2CBus;
+pub use self::i2c::{parse_address, probe_i2c, I2CBus, PinMap, PROBE_ADDRESSES};
 
 use crate::error::Result;
 
@@ -18,6 +21,14 @@ pub trait DataBus {
         delay: &mut D,
     ) -> Result<()>;
 
-    // TODO
-    // fn read(...)
+    /// Turn the backlight on or off, for buses that control it. The change
+    /// takes effect with the next byte written. Does nothing by default.
+    fn set_backlight(&mut self, _on: bool) {}
+}
+
+/// A bus that can also read from the `HD44780`, with its R/W line wired
+pub trait ReadableBus: DataBus {
+    /// Read the busy flag and address counter if `data` is false, or the
+    /// byte at the address counter if it is true
+    fn read<D: DelayUs<u16> + DelayMs<u8>>(&mut self, data: bool, delay: &mut D) -> Result<u8>;
 }
@@ -0,0 +1,213 @@
+//! Sharing one I2C peripheral between the display and other devices
+//!
+//! [`I2CBus`](super::I2CBus) takes ownership of the peripheral it writes to.
+//! When an RTC, an EEPROM or sensors hang off the same bus, give each driver
+//! its own device instead, all borrowing the one peripheral:
+//!
+//! ```rust,ignore
+//! let i2c = RefCell::new(i2c);
+//!
+//! let mut lcd = HD44780::new_i2c(RefCellDevice::new(&i2c), 0x27, &mut delay)?;
+//! let mut rtc = Ds1307::new(RefCellDevice::new(&i2c));
+//!
+//! let time = rtc.get_time()?;
+//! lcd.write_str("12:00", &mut delay)?;
+//! ```
+//!
+//! A [`RefCellDevice`] may only be used from a single context. When the bus
+//! is also used from interrupt handlers, share it through a
+//! [`CriticalSectionDevice`] instead, available with the `critical-section`
+//! feature.
+//!
+//! Any other device implementing the embedded-hal 0.2 I2C traits over a
+//! shared bus, such as the proxies of `shared-bus`, can be handed to the
+//! driver just the same. These two cover the common cases without another
+//! dependency. The devices of `embedded-hal-bus` implement embedded-hal 1.0,
+//! which the blocking driver doesn't use.
+//!
+//! Every byte sent to the display is a few short I2C transactions, and other
+//! devices may use the bus between any two of them.
+//!
+//! The async `I2CBus` works the same way with async shared bus devices, such
+//! as those of `embassy-embedded-hal` borrowing a bus behind an async mutex.
+//! Each byte sent to the display is one transaction there, so a task reading
+//! a sensor waits at most for one byte, not for a whole string:
+//!
+//! ```rust,ignore
+//! use embassy_embedded_hal::shared_bus::asynch::i2c::I2cDevice;
+//! use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+//! use embassy_sync::mutex::Mutex;
+//!
+//! let i2c = Mutex::<NoopRawMutex, _>::new(i2c);
+//!
+//! let mut lcd = HD44780::new_i2c(I2cDevice::new(&i2c), 0x27, delay.clone()).await?;
+//! let mut sensor = I2cDevice::new(&i2c);
+//!
+//! loop {
+//!     let mut temperature = [0; 2];
+//!     sensor.write_read(0x48, &[0x00], &mut temperature).await?;
+//!
+//!     lcd.set_cursor_pos(0).await?;
+//!     lcd.write_str(if temperature[0] > 30 { "Hot " } else { "Cool" }).await?;
+//!
+//!     delay.delay_ms(1_000).await?;
+//! }
+//! ```
+
+use core::cell::RefCell;
+
+use embedded_hal::blocking::i2c::{Read, Write, WriteRead};
+
+/// A share of an I2C peripheral borrowed from a `RefCell`
+pub struct RefCellDevice<'a, I2C> {
+    bus: &'a RefCell<I2C>,
+}
+
+impl<'a, I2C> RefCellDevice<'a, I2C> {
+    pub fn new(bus: &'a RefCell<I2C>) -> RefCellDevice<'a, I2C> {
+        RefCellDevice { bus }
+    }
+}
+
+impl<'a, I2C: Write> Write for RefCellDevice<'a, I2C> {
+    type Error = I2C::Error;
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.bus.borrow_mut().write(address, bytes)
+    }
+}
+
+impl<'a, I2C: Read> Read for RefCellDevice<'a, I2C> {
+    type Error = I2C::Error;
+
+    fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), Self::Error> {
+        self.bus.borrow_mut().read(address, buffer)
+    }
+}
+
+impl<'a, I2C: WriteRead> WriteRead for RefCellDevice<'a, I2C> {
+    type Error = I2C::Error;
+
+    fn write_read(
+        &mut self,
+        address: u8,
+        bytes: &[u8],
+        buffer: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        self.bus.borrow_mut().write_read(address, bytes, buffer)
+    }
+}
+
+/// A share of an I2C peripheral behind a critical section mutex, usable from
+/// interrupt handlers too
+#[cfg(feature = "critical-section")]
+pub struct CriticalSectionDevice<'a, I2C> {
+    bus: &'a critical_section::Mutex<RefCell<I2C>>,
+}
+
+#[cfg(feature = "critical-section")]
+impl<'a, I2C> CriticalSectionDevice<'a, I2C> {
+    pub fn new(bus: &'a critical_section::Mutex<RefCell<I2C>>) -> CriticalSectionDevice<'a, I2C> {
+        CriticalSectionDevice { bus }
+    }
+}
+
+#[cfg(feature = "critical-section")]
+impl<'a, I2C: Write> Write for CriticalSectionDevice<'a, I2C> {
+    type Error = I2C::Error;
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Self::Error> {
+        critical_section::with(|cs| self.bus.borrow_ref_mut(cs).write(address, bytes))
+    }
+}
+
+#[cfg(feature = "critical-section")]
+impl<'a, I2C: Read> Read for CriticalSectionDevice<'a, I2C> {
+    type Error = I2C::Error;
+
+    fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), Self::Error> {
+        critical_section::with(|cs| self.bus.borrow_ref_mut(cs).read(address, buffer))
+    }
+}
+
+#[cfg(feature = "critical-section")]
+impl<'a, I2C: WriteRead> WriteRead for CriticalSectionDevice<'a, I2C> {
+    type Error = I2C::Error;
+
+    fn write_read(
+        &mut self,
+        address: u8,
+        bytes: &[u8],
+        buffer: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        critical_section::with(|cs| {
+            self.bus
+                .borrow_ref_mut(cs)
+                .write_read(address, bytes, buffer)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    use crate::mock::{I2cLog, NoDelay};
+    use crate::HD44780;
+
+    #[test]
+    fn interleaves_devices() {
+        let i2c = RefCell::new(I2cLog::default());
+
+        let mut lcd = HD44780::new_i2c(RefCellDevice::new(&i2c), 0x27, &mut NoDelay).unwrap();
+        let mut eeprom = RefCellDevice::new(&i2c);
+
+        eeprom.write(0x50, &[0x00, 0x2A]).unwrap();
+        lcd.write_byte(b'A', &mut NoDelay).unwrap();
+        eeprom.write(0x50, &[0x01, 0x2B]).unwrap();
+
+        let log = i2c.borrow();
+        let transfers = &log.transfers[..log.len];
+
+        // Two transactions per nibble, two nibbles per byte
+        assert_eq!(transfers[transfers.len() - 6], (0x50, 0x2A));
+        assert!(transfers[transfers.len() - 5..transfers.len() - 1]
+            .iter()
+            .all(|&(address, _)| address == 0x27));
+        assert_eq!(transfers[transfers.len() - 1], (0x50, 0x2B));
+    }
+
+    #[cfg(feature = "critical-section")]
+    #[test]
+    fn shares_through_critical_section() {
+        let i2c = critical_section::Mutex::new(RefCell::new(I2cLog::default()));
+
+        let mut lcd =
+            HD44780::new_i2c(CriticalSectionDevice::new(&i2c), 0x27, &mut NoDelay).unwrap();
+        let mut eeprom = CriticalSectionDevice::new(&i2c);
+
+        eeprom.write(0x50, &[0x00, 0x2A]).unwrap();
+        lcd.write_byte(b'A', &mut NoDelay).unwrap();
+
+        critical_section::with(|cs| {
+            let log = i2c.borrow_ref(cs);
+            let transfers = &log.transfers[..log.len];
+
+            assert_eq!(transfers[transfers.len() - 5], (0x50, 0x2A));
+            assert!(transfers[transfers.len() - 4..]
+                .iter()
+                .all(|&(address, _)| address == 0x27));
+        });
+    }
+
+    #[test]
+    fn reports_bus_errors() {
+        let i2c = RefCell::new(I2cLog::default());
+        let mut lcd = HD44780::new_i2c(RefCellDevice::new(&i2c), 0x27, &mut NoDelay).unwrap();
+
+        i2c.borrow_mut().fail = true;
+
+        assert!(lcd.write_byte(b'A', &mut NoDelay).is_err());
+    }
+}
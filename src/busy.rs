@@ -0,0 +1,126 @@
+//! Waiting on the busy flag instead of fixed times
+//!
+//! With a policy set through
+//! [`HD44780::set_busy_polling`](../struct.HD44780.html#method.set_busy_polling),
+//! the blocking driver reads the busy flag after each instruction and data
+//! write, and goes on as soon as the display is done. A display that stays
+//! busy for longer than the timeout, because it is dead, disconnected, or
+//! its read path doesn't work, either gives
+//! [`Error::Timeout`](../error/enum.Error.html#variant.Timeout), or makes
+//! the driver stop polling and wait the times from its
+//! [timing table](../timing/struct.Timing.html) from then on. So does a
+//! busy flag that can't be read, unless it gives the error of the bus.
+//!
+//! ```rust,ignore
+//! lcd.set_busy_polling(Some(BusyPolicy {
+//!     timeout_us: 10_000,
+//!     interval_us: 10,
+//!     fallback: true,
+//! }));
+//! ```
+//!
+//! Falling back suits read paths that may be unreliable, such as a 5 V
+//! display read by a 3.3 V microcontroller. A read path that always reports
+//! the display as ready can't be told apart from a fast display, so it isn't
+//! caught.
+
+use crate::erased::ReadFn;
+
+/// How to poll the busy flag
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BusyPolicy {
+    /// How long the display may stay busy
+    pub timeout_us: u32,
+    /// Time between reads of the busy flag, at least 1
+    pub interval_us: u16,
+    /// Whether to stop polling and use fixed times on timeout, instead of
+    /// returning an error
+    pub fallback: bool,
+}
+
+impl Default for BusyPolicy {
+    /// Time out after five times the longest instruction, and fall back
+    fn default() -> BusyPolicy {
+        BusyPolicy {
+            timeout_us: 10_000,
+            interval_us: 10,
+            fallback: true,
+        }
+    }
+}
+
+pub(crate) struct BusyPoller<B> {
+    pub policy: BusyPolicy,
+    pub read: ReadFn<B>,
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::error::Error;
+    use crate::mock::{NoDelay, Ram};
+    use crate::HD44780;
+
+    fn lcd(fallback: bool) -> HD44780<Ram> {
+        let mut lcd = HD44780::from_bus(Ram::default(), 0b0011_1000);
+        lcd.set_busy_polling(Some(BusyPolicy {
+            fallback,
+            ..BusyPolicy::default()
+        }));
+        lcd
+    }
+
+    #[test]
+    fn waits_for_ready() {
+        let mut lcd = lcd(false);
+
+        assert_eq!(lcd.write_str("ok", &mut NoDelay), Ok(()));
+        assert!(lcd.is_busy_polling());
+    }
+
+    #[test]
+    fn times_out() {
+        let mut lcd = lcd(false);
+        lcd.bus.busy = true;
+
+        assert_eq!(lcd.write_str("ok", &mut NoDelay), Err(Error::Timeout));
+    }
+
+    #[test]
+    fn falls_back() {
+        let mut lcd = lcd(true);
+        lcd.bus.busy = true;
+
+        assert_eq!(lcd.write_str("ok", &mut NoDelay), Ok(()));
+        assert!(!lcd.is_busy_polling());
+    }
+
+    #[test]
+    fn times_out_without_interval() {
+        let mut lcd = lcd(false);
+        lcd.set_busy_polling(Some(BusyPolicy {
+            interval_us: 0,
+            fallback: false,
+            ..BusyPolicy::default()
+        }));
+        lcd.bus.busy = true;
+
+        assert_eq!(lcd.write_str("ok", &mut NoDelay), Err(Error::Timeout));
+    }
+
+    #[test]
+    fn falls_back_on_read_errors() {
+        let mut strict = lcd(false);
+        strict.bus.unreadable = true;
+
+        assert_eq!(strict.write_str("ok", &mut NoDelay), Err(Error::Io));
+
+        let mut lenient = lcd(true);
+        lenient.bus.unreadable = true;
+
+        assert_eq!(lenient.write_str("ok", &mut NoDelay), Ok(()));
+        assert!(!lenient.is_busy_polling());
+        assert_eq!(&lenient.bus.ddram[..2], b"ok");
+    }
+}
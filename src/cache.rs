@@ -0,0 +1,107 @@
+//! The instructions last sent to the `HD44780`, so that sending one again
+//! when it wouldn't change anything can be skipped
+
+/// Instructions are told apart by their highest set bit
+const SET_DDRAM_ADDRESS: u32 = 0;
+const SET_CGRAM_ADDRESS: u32 = 1;
+const FUNCTION_SET: u32 = 2;
+const DISPLAY_CONTROL: u32 = 4;
+const ENTRY_MODE: u32 = 5;
+const RETURN_HOME: u32 = 6;
+const CLEAR_DISPLAY: u32 = 7;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) struct CommandCache {
+    function_set: Option<u8>,
+    display_control: Option<u8>,
+    entry_mode: Option<u8>,
+    /// Whether the address counter points into DDRAM, where the address
+    /// counter model says it does
+    ddram: bool,
+}
+
+impl CommandCache {
+    /// Whether sending `cmd` would leave the `HD44780` as it is, given the
+    /// `address` the model says the cursor is at
+    pub fn is_redundant(&self, cmd: u8, address: u8) -> bool {
+        match cmd.leading_zeros() {
+            SET_DDRAM_ADDRESS => self.ddram && cmd & 0b0111_1111 == address,
+            FUNCTION_SET => self.function_set == Some(cmd),
+            DISPLAY_CONTROL => self.display_control == Some(cmd),
+            ENTRY_MODE => self.entry_mode == Some(cmd),
+            _ => false,
+        }
+    }
+
+    /// Remember an instruction that was sent
+    pub fn record(&mut self, cmd: u8) {
+        match cmd.leading_zeros() {
+            SET_DDRAM_ADDRESS | RETURN_HOME => self.ddram = true,
+            SET_CGRAM_ADDRESS => self.ddram = false,
+            FUNCTION_SET => self.function_set = Some(cmd),
+            DISPLAY_CONTROL => self.display_control = Some(cmd),
+            ENTRY_MODE => self.entry_mode = Some(cmd),
+            CLEAR_DISPLAY => {
+                // Clearing also makes the cursor move right
+                self.ddram = true;
+                self.entry_mode = self.entry_mode.map(|cmd| cmd | 0b0000_0010);
+            }
+            _ => {}
+        }
+    }
+
+    /// Forget where the address counter points, after it moved in a way
+    /// the model doesn't follow
+    pub fn forget_address(&mut self) {
+        self.ddram = false;
+    }
+
+    /// Forget everything, so every instruction is sent again
+    pub fn invalidate(&mut self) {
+        *self = CommandCache::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn skips_repeats() {
+        let mut cache = CommandCache::default();
+
+        assert!(!cache.is_redundant(0b0000_1100, 0));
+        cache.record(0b0000_1100);
+        assert!(cache.is_redundant(0b0000_1100, 0));
+        assert!(!cache.is_redundant(0b0000_1111, 0));
+
+        // Clearing and returning home are never skipped
+        cache.record(0b0000_0001);
+        assert!(!cache.is_redundant(0b0000_0001, 0));
+        assert!(!cache.is_redundant(0b0000_0010, 0));
+    }
+
+    #[test]
+    fn follows_address() {
+        let mut cache = CommandCache::default();
+
+        assert!(!cache.is_redundant(0b1000_0101, 5));
+        cache.record(0b1000_0101);
+        assert!(cache.is_redundant(0b1000_0101, 5));
+        assert!(!cache.is_redundant(0b1000_0101, 6));
+
+        cache.record(0b0100_0000);
+        assert!(!cache.is_redundant(0b1000_0101, 5));
+    }
+
+    #[test]
+    fn clear_sets_increment() {
+        let mut cache = CommandCache::default();
+
+        cache.record(0b0000_0100);
+        cache.record(0b0000_0001);
+
+        assert!(cache.is_redundant(0b0000_0110, 0));
+    }
+}
@@ -0,0 +1,284 @@
+//! Allocation of CGRAM slots to custom glyphs
+//!
+//! The `HD44780` only has eight CGRAM slots. The [`CgramAllocator`] hands them
+//! out on demand: asking for a glyph that is already loaded returns the same
+//! slot, and once every handle to a glyph has been released its slot may be
+//! reused for another glyph. Cells placed through the allocator are tracked so
+//! that they can be blanked when the glyph they show is evicted, instead of
+//! silently changing to the new glyph.
+//!
+//! ```rust,ignore
+//! // Manage slots 4..8, leaving 0..4 to a horizontal bar, tracking up to 16 cells
+//! let mut glyphs = CgramAllocator::<16>::new(4, 4);
+//!
+//! let bell = glyphs.acquire(&mut lcd, &BELL, &mut delay)?;
+//! glyphs.place(&mut lcd, &bell, (19, 0), &mut delay)?;
+//! ...
+//! glyphs.release(bell);
+//! ```
+
+use embedded_hal::blocking::delay::{DelayMs, DelayUs};
+
+use crate::bus::DataBus;
+use crate::error::{Error, Result};
+use crate::HD44780;
+
+/// Number of CGRAM slots of the `HD44780`
+const SLOTS: usize = 8;
+
+/// Byte written over cells showing an evicted glyph
+const BLANK: u8 = b' ';
+
+/// A reference to a glyph loaded in CGRAM. Hand it back to the allocator with
+/// [`CgramAllocator::release`] once the glyph is no longer needed.
+#[derive(Debug, PartialEq, Eq)]
+pub struct GlyphHandle {
+    slot: u8,
+}
+
+impl GlyphHandle {
+    /// The byte that displays this glyph when written to DDRAM
+    pub fn byte(&self) -> u8 {
+        self.slot
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Slot {
+    bitmap: [u8; 8],
+    loaded: bool,
+    refs: u8,
+    last_used: u16,
+}
+
+#[derive(Clone, Copy)]
+struct Placed {
+    position: (u8, u8),
+    slot: u8,
+}
+
+/// Reference counting allocator for a range of CGRAM slots, remembering up
+/// to `CELLS` cells that display one of its glyphs
+pub struct CgramAllocator<const CELLS: usize> {
+    first_slot: u8,
+    count: u8,
+    slots: [Slot; SLOTS],
+    cells: [Option<Placed>; CELLS],
+    clock: u16,
+}
+
+impl<const CELLS: usize> CgramAllocator<CELLS> {
+    /// Create an allocator managing the `count` slots starting at `first_slot`.
+    /// Slots outside of that range are left alone, so they can be used directly.
+    pub fn new(first_slot: u8, count: u8) -> CgramAllocator<CELLS> {
+        CgramAllocator {
+            first_slot: first_slot.min(SLOTS as u8),
+            count: count.min(SLOTS as u8 - first_slot.min(SLOTS as u8)),
+            slots: [Slot {
+                bitmap: [0; 8],
+                loaded: false,
+                refs: 0,
+                last_used: 0,
+            }; SLOTS],
+            cells: [None; CELLS],
+            clock: 0,
+        }
+    }
+
+    /// Get a handle to `bitmap`, uploading it to a free slot if it isn't
+    /// loaded already. When every slot is in use, the least recently used
+    /// glyph without handles is evicted and the cells showing it are blanked.
+    /// Fails with `Error::Full` if all the slots are referenced, or if the
+    /// glyph already has 255 handles.
+    pub fn acquire<B: DataBus, D: DelayUs<u16> + DelayMs<u8>>(
+        &mut self,
+        lcd: &mut HD44780<B>,
+        bitmap: &[u8; 8],
+        delay: &mut D,
+    ) -> Result<GlyphHandle> {
+        self.clock = self.clock.wrapping_add(1);
+
+        let slot = self.choose_slot(bitmap).ok_or(Error::Full)?;
+        let entry = self.slots[slot as usize];
+
+        // Saturating the count would free the slot while handles are out
+        if entry.refs == u8::MAX {
+            return Err(Error::Full);
+        }
+
+        if !entry.loaded || entry.bitmap != *bitmap {
+            if entry.loaded {
+                self.blank_cells(lcd, slot, delay)?;
+            }
+
+            lcd.set_custom_char(slot, bitmap, delay)?;
+        }
+
+        let entry = &mut self.slots[slot as usize];
+        entry.bitmap = *bitmap;
+        entry.loaded = true;
+        entry.refs += 1;
+        entry.last_used = self.clock;
+
+        Ok(GlyphHandle { slot })
+    }
+
+    /// Give back a handle. The glyph stays loaded, and on screen, until its
+    /// slot is needed for another glyph.
+    pub fn release(&mut self, handle: GlyphHandle) {
+        let entry = &mut self.slots[handle.slot as usize];
+        // Only a handle from another allocator can find the count at 0
+        entry.refs = entry.refs.saturating_sub(1);
+    }
+
+    /// Show a glyph at `position` (column, row) and remember the cell so it
+    /// can be blanked if the glyph is evicted later
+    pub fn place<B: DataBus, D: DelayUs<u16> + DelayMs<u8>>(
+        &mut self,
+        lcd: &mut HD44780<B>,
+        handle: &GlyphHandle,
+        position: (u8, u8),
+        delay: &mut D,
+    ) -> Result<()> {
+        let index = self
+            .cells
+            .iter()
+            .position(|cell| matches!(cell, Some(cell) if cell.position == position))
+            .or_else(|| self.cells.iter().position(Option::is_none))
+            .ok_or(Error::Full)?;
+
+        lcd.set_cursor_xy(position, delay)?;
+        lcd.write_byte(handle.slot, delay)?;
+
+        self.cells[index] = Some(Placed {
+            position,
+            slot: handle.slot,
+        });
+        self.slots[handle.slot as usize].last_used = self.clock;
+
+        Ok(())
+    }
+
+    /// Forget about the glyph at `position`. Call this after writing something
+    /// else over a placed cell so it isn't blanked on eviction.
+    pub fn vacate(&mut self, position: (u8, u8)) {
+        for cell in self.cells.iter_mut() {
+            if matches!(cell, Some(placed) if placed.position == position) {
+                *cell = None;
+            }
+        }
+    }
+
+    /// Pick the slot for `bitmap`: the slot already holding it, an empty
+    /// slot, or the least recently used unreferenced slot, in that order
+    fn choose_slot(&self, bitmap: &[u8; 8]) -> Option<u8> {
+        let range = self.first_slot..self.first_slot + self.count;
+
+        let loaded = range.clone().find(|&slot| {
+            self.slots[slot as usize].loaded && self.slots[slot as usize].bitmap == *bitmap
+        });
+        let empty = || {
+            range
+                .clone()
+                .find(|&slot| !self.slots[slot as usize].loaded)
+        };
+        let unused = || {
+            range
+                .clone()
+                .filter(|&slot| self.slots[slot as usize].refs == 0)
+                .max_by_key(|&slot| self.clock.wrapping_sub(self.slots[slot as usize].last_used))
+        };
+
+        loaded.or_else(empty).or_else(unused)
+    }
+
+    /// Blank and forget the cells showing `slot`
+    fn blank_cells<B: DataBus, D: DelayUs<u16> + DelayMs<u8>>(
+        &mut self,
+        lcd: &mut HD44780<B>,
+        slot: u8,
+        delay: &mut D,
+    ) -> Result<()> {
+        for cell in self.cells.iter_mut() {
+            if let Some(placed) = *cell {
+                if placed.slot == slot {
+                    lcd.set_cursor_xy(placed.position, delay)?;
+                    lcd.write_byte(BLANK, delay)?;
+                    *cell = None;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::mock::{NoDelay, Recorder};
+
+    fn load(allocator: &mut CgramAllocator<4>, slot: u8, bitmap: [u8; 8], refs: u8, used: u16) {
+        allocator.slots[slot as usize] = Slot {
+            bitmap,
+            loaded: true,
+            refs,
+            last_used: used,
+        };
+    }
+
+    #[test]
+    fn reuses_loaded_glyph() {
+        let mut allocator = CgramAllocator::<4>::new(2, 3);
+        load(&mut allocator, 3, [1; 8], 1, 0);
+
+        assert_eq!(allocator.choose_slot(&[1; 8]), Some(3));
+        assert_eq!(allocator.choose_slot(&[2; 8]), Some(2));
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut allocator = CgramAllocator::<4>::new(0, 3);
+        allocator.clock = 10;
+        load(&mut allocator, 0, [1; 8], 0, 8);
+        load(&mut allocator, 1, [2; 8], 0, 3);
+        load(&mut allocator, 2, [3; 8], 1, 1);
+
+        assert_eq!(allocator.choose_slot(&[4; 8]), Some(1));
+
+        allocator.slots[1].refs = 1;
+        allocator.slots[0].refs = 1;
+
+        assert_eq!(allocator.choose_slot(&[4; 8]), None);
+    }
+
+    #[test]
+    fn limits_handles() {
+        let mut allocator = CgramAllocator::<4>::new(0, 1);
+        let mut lcd = HD44780::from_bus(Recorder::default(), 0b0011_1000);
+        let mut handles = 0;
+
+        while allocator.acquire(&mut lcd, &[1; 8], &mut NoDelay).is_ok() {
+            handles += 1;
+        }
+
+        assert_eq!(handles, u8::MAX);
+        assert_eq!(
+            allocator.acquire(&mut lcd, &[1; 8], &mut NoDelay),
+            Err(Error::Full)
+        );
+
+        // Giving back one handle makes room for one more
+        allocator.release(GlyphHandle { slot: 0 });
+        assert!(allocator.acquire(&mut lcd, &[1; 8], &mut NoDelay).is_ok());
+        assert_eq!(allocator.slots[0].refs, u8::MAX);
+    }
+
+    #[test]
+    fn range_is_clamped() {
+        let allocator = CgramAllocator::<4>::new(6, 5);
+
+        assert_eq!(allocator.count, 2);
+    }
+}
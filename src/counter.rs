@@ -0,0 +1,107 @@
+//! A model of the address counter and display shift of the `HD44780`
+//!
+//! The drivers update it along with every instruction and byte they send, so
+//! the cursor position is known without reading it back from the display.
+//! It assumes the two line mode set up by the drivers, where DDRAM holds
+//! `0x00..=0x27` and `0x40..=0x67`.
+
+use crate::entry_mode::{CursorMode, EntryMode, ShiftMode};
+
+/// Characters in each DDRAM line, which is also how far the display can shift
+const LINE_LENGTH: u8 = 40;
+
+const FIRST_LINE_END: u8 = 0x27;
+const SECOND_LINE: u8 = 0x40;
+const SECOND_LINE_END: u8 = 0x67;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) struct AddressCounter {
+    /// DDRAM address the next byte is written to
+    pub address: u8,
+    /// Positions the display is shifted to the left, from 0 to 39
+    pub shift: u8,
+}
+
+impl AddressCounter {
+    /// Follow a clear or a return home
+    pub fn home(&mut self) {
+        *self = AddressCounter::default();
+    }
+
+    /// Follow setting the DDRAM address
+    pub fn set(&mut self, address: u8) {
+        self.address = address & 0b0111_1111;
+    }
+
+    /// Follow moving the cursor by one position, wrapping from the end of
+    /// one line to the start of the other
+    pub fn step(&mut self, forward: bool) {
+        self.address = match (self.address, forward) {
+            (FIRST_LINE_END, true) => SECOND_LINE,
+            (SECOND_LINE_END, true) => 0,
+            (0, false) => SECOND_LINE_END,
+            (SECOND_LINE, false) => FIRST_LINE_END,
+            (address, true) => address.wrapping_add(1) & 0b0111_1111,
+            (address, false) => address.wrapping_sub(1) & 0b0111_1111,
+        };
+    }
+
+    /// Follow shifting the whole display by one position
+    pub fn shift_display(&mut self, left: bool) {
+        self.shift = if left {
+            (self.shift + 1) % LINE_LENGTH
+        } else {
+            (self.shift + LINE_LENGTH - 1) % LINE_LENGTH
+        };
+    }
+
+    /// Follow writing a byte to DDRAM
+    pub fn write(&mut self, entry_mode: &EntryMode) {
+        let forward = entry_mode.cursor_mode == CursorMode::Increment;
+
+        self.step(forward);
+
+        // With the display shifting, the cursor stays in place on screen
+        if entry_mode.shift_mode == ShiftMode::Enabled {
+            self.shift_display(forward);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn wraps_between_lines() {
+        let mut counter = AddressCounter::default();
+
+        counter.set(0x27);
+        counter.step(true);
+        assert_eq!(counter.address, 0x40);
+
+        counter.set(0x67);
+        counter.step(true);
+        assert_eq!(counter.address, 0x00);
+
+        counter.step(false);
+        assert_eq!(counter.address, 0x67);
+    }
+
+    #[test]
+    fn shifts_with_writes() {
+        let mut counter = AddressCounter::default();
+        let entry_mode = EntryMode {
+            cursor_mode: CursorMode::Decrement,
+            shift_mode: ShiftMode::Enabled,
+        };
+
+        counter.set(0x41);
+        counter.write(&entry_mode);
+        counter.write(&entry_mode);
+
+        assert_eq!(counter.address, 0x27);
+        assert_eq!(counter.shift, 38);
+    }
+}
@@ -0,0 +1,76 @@
+//! What [`HD44780::detect`](../struct.HD44780.html#method.detect) found out
+//! about a display
+//!
+//! The controller only knows how its memory is organized, not how much of it
+//! the glass shows. Two things can be told from reading it back:
+//!
+//! - whether the address counter jumps from `0x27` to `0x40`, which it does
+//!   for controllers in two line mode
+//! - whether DDRAM wraps around early, which compatible controllers with
+//!   less memory do
+//!
+//! A regular `HD44780` behind a 16x2, 20x2 or 20x4 panel reports two lines
+//! of 40 characters, so those panels still can't be told apart. For them
+//! [geometry](struct.Detection.html#method.geometry) falls back to the
+//! default 20x4 layout, which is correct for the rows each of them has.
+
+use crate::geometry::Geometry;
+
+/// DDRAM line lengths checked for wrapping around, shortest first
+pub(crate) const PROBE_LENGTHS: [u8; 6] = [8, 16, 20, 24, 32, 40];
+
+/// DDRAM bytes saved before probing and restored after, `0x00` up to and
+/// including `0x28`
+pub(crate) const PROBED_BYTES: usize = 41;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Detection {
+    /// Whether the controller is in two line mode
+    pub two_line: bool,
+    /// Characters in each DDRAM line before it wraps around
+    pub line_length: u8,
+}
+
+impl Detection {
+    /// A geometry matching what was detected, see the
+    /// [module documentation](index.html)
+    ///
+    /// ```rust,ignore
+    /// let detection = lcd.detect(&mut delay)?;
+    /// lcd.set_geometry(detection.geometry());
+    /// ```
+    pub fn geometry(&self) -> Geometry {
+        match (self.two_line, self.line_length) {
+            (false, length) => Geometry::new(length, 1),
+            (true, length) if length < 40 => Geometry::new(length, 2),
+            (true, _) => Geometry::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn picks_geometry() {
+        let detection = Detection {
+            two_line: true,
+            line_length: 40,
+        };
+        assert_eq!(detection.geometry(), Geometry::new(20, 4));
+
+        let detection = Detection {
+            two_line: true,
+            line_length: 16,
+        };
+        assert_eq!(detection.geometry(), Geometry::new(16, 2));
+
+        let detection = Detection {
+            two_line: false,
+            line_length: 40,
+        };
+        assert_eq!(detection.geometry(), Geometry::new(40, 1));
+    }
+}
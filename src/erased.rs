@@ -0,0 +1,48 @@
+//! Delays and bus reads behind types that don't depend on the delay or the
+//! bus in use
+//!
+//! The drivers are generic over [`DataBus`](../bus/trait.DataBus.html), and
+//! take a delay of any type with each call. Features that need to read from
+//! the display keep a function reading from their
+//! [`ReadableBus`](../bus/trait.ReadableBus.html), set up while the bus type
+//! is still known to implement it.
+
+use embedded_hal::blocking::delay::{DelayMs, DelayUs};
+
+use crate::error::Result;
+
+/// The blocking delay traits, behind methods that can be called through a
+/// trait object
+pub(crate) trait Delay {
+    fn wait_us(&mut self, us: u16);
+    fn wait_ms(&mut self, ms: u8);
+}
+
+impl<D: DelayUs<u16> + DelayMs<u8>> Delay for D {
+    fn wait_us(&mut self, us: u16) {
+        self.delay_us(us);
+    }
+
+    fn wait_ms(&mut self, ms: u8) {
+        self.delay_ms(ms);
+    }
+}
+
+/// Any delay, so that a function taking it doesn't depend on its type
+pub(crate) struct DynDelay<'a>(pub &'a mut dyn Delay);
+
+impl DelayUs<u16> for DynDelay<'_> {
+    fn delay_us(&mut self, us: u16) {
+        self.0.wait_us(us);
+    }
+}
+
+impl DelayMs<u8> for DynDelay<'_> {
+    fn delay_ms(&mut self, ms: u8) {
+        self.0.wait_ms(ms);
+    }
+}
+
+/// Calls [`ReadableBus::read`](../bus/trait.ReadableBus.html#tymethod.read)
+/// on a bus only known to implement `DataBus`
+pub(crate) type ReadFn<B> = fn(&mut B, bool, &mut DynDelay) -> Result<u8>;
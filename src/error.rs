@@ -1,11 +1,52 @@
+use core::fmt;
+
 use defmt::Format;
 
-#[derive(Debug)]
-pub struct Error;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// Setting a pin or writing to the I2C bus failed
+    Io,
+    /// An argument was out of range, such as a CGRAM slot above 7
+    InvalidArgument,
+    /// A queue or table had no room left
+    Full,
+    /// No I2C backpack answered at any of the probed addresses
+    NotFound,
+    /// A byte read back from the display differs from the one written
+    Mismatch,
+    /// The display stayed busy for longer than allowed
+    Timeout,
+}
+
 pub type Result<T> = core::result::Result<T, Error>;
 
 impl Format for Error {
     fn format(&self, fmt: defmt::Formatter) {
-        defmt::write!(fmt, "generic hd44780 error")
+        match self {
+            Error::Io => defmt::write!(fmt, "hd44780 bus error"),
+            Error::InvalidArgument => defmt::write!(fmt, "hd44780 argument out of range"),
+            Error::Full => defmt::write!(fmt, "hd44780 queue full"),
+            Error::NotFound => defmt::write!(fmt, "no hd44780 i2c backpack found"),
+            Error::Mismatch => defmt::write!(fmt, "hd44780 read back wrong data"),
+            Error::Timeout => defmt::write!(fmt, "hd44780 busy for too long"),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            Error::Io => "hd44780 bus error",
+            Error::InvalidArgument => "hd44780 argument out of range",
+            Error::Full => "hd44780 queue full",
+            Error::NotFound => "no hd44780 i2c backpack found",
+            Error::Mismatch => "hd44780 read back wrong data",
+            Error::Timeout => "hd44780 busy for too long",
+        };
+
+        f.write_str(message)
     }
 }
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
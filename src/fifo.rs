@@ -0,0 +1,405 @@
+//! A lock-free queue of instructions and data, drained from a timer interrupt
+//!
+//! The application writes to the display through a [`Producer`], which only
+//! copies bytes into the [`CommandFifo`] and never waits. A periodic timer
+//! interrupt calls [`Pump::service`], which sends at most one byte to the bus
+//! per call and skips as many calls as the `HD44780` needs to execute it.
+//!
+//! The queue has a single producer and a single consumer. It relies on atomic
+//! loads and stores only, so it also works on cores without compare-and-swap
+//! such as the Cortex-M0.
+//!
+//! ```rust,ignore
+//! static FIFO: StaticCell<CommandFifo<128>> = StaticCell::new();
+//! static PUMP: Mutex<RefCell<Option<Pump<'static, I2CBus<I2c>, PulseDelay, 128>>>> =
+//!     Mutex::new(RefCell::new(None));
+//!
+//! // In main
+//! let (mut producer, consumer) = FIFO.init(CommandFifo::new()).split();
+//! let pump = Pump::new(consumer, I2CBus::new(i2c, 0x27), pulse_delay, 100);
+//! critical_section::with(|cs| PUMP.borrow_ref_mut(cs).replace(pump));
+//! timer.start_periodic_us(100);
+//!
+//! producer.init_4bit()?;
+//! producer.set_cursor_pos(0x40)?;
+//! producer.write_str("Hello from main")?;
+//!
+//! #[interrupt]
+//! fn TIMER() {
+//!     critical_section::with(|cs| {
+//!         if let Some(pump) = PUMP.borrow_ref_mut(cs).as_mut() {
+//!             let _ = pump.service();
+//!         }
+//!     });
+//! }
+//! ```
+
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use embedded_hal::blocking::delay::{DelayMs, DelayUs};
+
+use crate::bus::DataBus;
+use crate::entry_mode::EntryMode;
+use crate::error::{Error, Result};
+use crate::timing;
+use crate::DisplayMode;
+
+/// Marks an entry as data rather than an instruction
+const DATA: u16 = 0x100;
+
+/// Marks an entry as a pause of as many milliseconds as its lower byte
+const WAIT: u16 = 0x200;
+
+/// Fixed capacity queue of up to `N` bytes for the `HD44780`
+pub struct CommandFifo<const N: usize> {
+    buffer: UnsafeCell<[u16; N]>,
+    /// Number of entries read so far modulo `2 * N`, only written by the
+    /// consumer
+    head: AtomicUsize,
+    /// Number of entries written so far modulo `2 * N`, only written by the
+    /// producer
+    tail: AtomicUsize,
+}
+
+// The producer and consumer never access the same entry at the same time,
+// and each index is only ever stored by one of them
+unsafe impl<const N: usize> Sync for CommandFifo<N> {}
+
+impl<const N: usize> CommandFifo<N> {
+    /// Evaluated by `new`, so that a queue without room fails to compile
+    /// rather than dividing by zero when it is first used
+    const HAS_ROOM: () = assert!(N > 0, "a CommandFifo needs room for an entry");
+
+    /// An empty queue. `N` must be at least 1:
+    ///
+    /// ```compile_fail
+    /// let fifo = hd44780_driver::fifo::CommandFifo::<0>::new();
+    /// ```
+    pub const fn new() -> CommandFifo<N> {
+        let () = Self::HAS_ROOM;
+
+        CommandFifo {
+            buffer: UnsafeCell::new([0; N]),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        }
+    }
+
+    /// Split the queue into its two ends. The producer is used from thread
+    /// mode and the consumer is given to a [`Pump`] serviced by an interrupt.
+    pub fn split(&mut self) -> (Producer<'_, N>, Consumer<'_, N>) {
+        let fifo: &CommandFifo<N> = self;
+
+        (Producer { fifo }, Consumer { fifo })
+    }
+
+    fn len(&self) -> usize {
+        let head = self.head.load(Ordering::Acquire);
+        let tail = self.tail.load(Ordering::Acquire);
+
+        (tail + 2 * N - head) % (2 * N)
+    }
+}
+
+/// Move a head or tail count on by `by` entries. The counts wrap at `2 * N`
+/// rather than at `usize::MAX`, which would make `count % N` jump unless `N`
+/// is a power of two, and twice the capacity still tells a full queue from
+/// an empty one.
+fn advance<const N: usize>(count: usize, by: usize) -> usize {
+    (count + by) % (2 * N)
+}
+
+impl<const N: usize> Default for CommandFifo<N> {
+    fn default() -> CommandFifo<N> {
+        CommandFifo::new()
+    }
+}
+
+/// The writing end of a [`CommandFifo`]
+pub struct Producer<'a, const N: usize> {
+    fifo: &'a CommandFifo<N>,
+}
+
+impl<'a, const N: usize> Producer<'a, N> {
+    /// Number of bytes that can still be queued
+    pub fn free(&self) -> usize {
+        N - self.fifo.len()
+    }
+
+    /// Queue the initialization sequence of a 4-bit bus, such as a
+    /// `FourBitBus` or an `I2CBus`
+    pub fn init_4bit(&mut self) -> Result<()> {
+        self.push(&[
+            WAIT | (timing::POWER_ON_US / 1000) as u16,
+            0x33,
+            WAIT | (timing::INIT_US / 1000) as u16,
+            0x32,
+            0x28,
+            DisplayMode::default().as_byte() as u16,
+            0x01,
+            EntryMode::default().as_byte() as u16,
+        ])
+    }
+
+    /// Queue the initialization sequence of an `EightBitBus`
+    pub fn init_8bit(&mut self) -> Result<()> {
+        self.push(&[
+            WAIT | (timing::POWER_ON_US / 1000) as u16,
+            0b0011_0000,
+            WAIT | (timing::INIT_US / 1000) as u16,
+            0b0011_1000,
+            DisplayMode::default().as_byte() as u16,
+            0x01,
+            EntryMode::default().as_byte() as u16,
+        ])
+    }
+
+    /// Queue an instruction
+    pub fn write_command(&mut self, cmd: u8) -> Result<()> {
+        self.push(&[cmd as u16])
+    }
+
+    /// Queue a byte to be written at the cursor
+    pub fn write_byte(&mut self, data: u8) -> Result<()> {
+        self.push(&[DATA | data as u16])
+    }
+
+    /// Queue a sequence of bytes. Fails without queuing anything if they
+    /// don't all fit in the queue.
+    pub fn write_bytes(&mut self, string: &[u8]) -> Result<()> {
+        if string.len() > self.free() {
+            return Err(Error::Full);
+        }
+
+        for &b in string {
+            self.write_byte(b)?;
+        }
+
+        Ok(())
+    }
+
+    /// Queue a string, see [`write_bytes`](#method.write_bytes)
+    pub fn write_str(&mut self, string: &str) -> Result<()> {
+        self.write_bytes(string.as_bytes())
+    }
+
+    /// Queue clearing the entire display
+    pub fn clear(&mut self) -> Result<()> {
+        self.write_command(0b0000_0001)
+    }
+
+    /// Queue moving the cursor to a DDRAM address
+    pub fn set_cursor_pos(&mut self, position: u8) -> Result<()> {
+        self.write_command(0b1000_0000 | (position & 0b0111_1111))
+    }
+
+    /// Queue defining a custom character, see
+    /// [`HD44780::set_custom_char`](../struct.HD44780.html#method.set_custom_char)
+    pub fn set_custom_char(&mut self, slot: u8, bitmap: &[u8; 8]) -> Result<()> {
+        if slot > 7 {
+            return Err(Error::InvalidArgument);
+        }
+
+        let mut entries = [0; 9];
+        entries[0] = (0b0100_0000 | (slot << 3)) as u16;
+        for (entry, &row) in entries[1..].iter_mut().zip(bitmap) {
+            *entry = DATA | (row & 0b0001_1111) as u16;
+        }
+
+        self.push(&entries)
+    }
+
+    fn push(&mut self, entries: &[u16]) -> Result<()> {
+        if entries.len() > self.free() {
+            return Err(Error::Full);
+        }
+
+        let tail = self.fifo.tail.load(Ordering::Relaxed);
+
+        for (i, &entry) in entries.iter().enumerate() {
+            let index = advance::<N>(tail, i) % N;
+
+            // The consumer doesn't read past `tail`, which is only published below
+            unsafe { (*self.fifo.buffer.get())[index] = entry };
+        }
+
+        self.fifo
+            .tail
+            .store(advance::<N>(tail, entries.len()), Ordering::Release);
+
+        Ok(())
+    }
+}
+
+/// The reading end of a [`CommandFifo`]
+pub struct Consumer<'a, const N: usize> {
+    fifo: &'a CommandFifo<N>,
+}
+
+impl<'a, const N: usize> Consumer<'a, N> {
+    /// Take the oldest entry
+    fn pop(&mut self) -> Option<u16> {
+        let head = self.fifo.head.load(Ordering::Relaxed);
+
+        if self.fifo.tail.load(Ordering::Acquire) == head {
+            return None;
+        }
+
+        // The producer doesn't write to this entry until `head` moves past it
+        let entry = unsafe { (*self.fifo.buffer.get())[head % N] };
+
+        self.fifo
+            .head
+            .store(advance::<N>(head, 1), Ordering::Release);
+
+        Some(entry)
+    }
+}
+
+/// Feeds the entries of a [`CommandFifo`] to a bus, meant to be called from
+/// a periodic timer interrupt
+pub struct Pump<'a, B: DataBus, D: DelayUs<u16> + DelayMs<u8>, const N: usize> {
+    consumer: Consumer<'a, N>,
+    bus: B,
+    delay: D,
+    tick_us: u32,
+    /// Calls to skip while the last byte is executed
+    hold: u32,
+}
+
+impl<'a, B: DataBus, D: DelayUs<u16> + DelayMs<u8>, const N: usize> Pump<'a, B, D, N> {
+    /// Create a pump serviced every `tick_us` microseconds. The display must
+    /// already be initialized, and the delay is only used by the bus to pulse
+    /// its enable pin.
+    pub fn new(consumer: Consumer<'a, N>, bus: B, delay: D, tick_us: u32) -> Pump<'a, B, D, N> {
+        Pump {
+            consumer,
+            bus,
+            delay,
+            tick_us: tick_us.max(1),
+            hold: 0,
+        }
+    }
+
+    /// Send the next queued byte if the previous one has been executed.
+    /// Returns whether a byte was sent.
+    ///
+    /// This never blocks, except for the enable pulse of the bus, and may be
+    /// called from an interrupt handler.
+    pub fn service(&mut self) -> Result<bool> {
+        if self.hold > 0 {
+            self.hold -= 1;
+            return Ok(false);
+        }
+
+        let entry = match self.consumer.pop() {
+            Some(entry) => entry,
+            None => return Ok(false),
+        };
+        let byte = entry as u8;
+        let data = entry & DATA != 0;
+
+        let micros = if entry & WAIT != 0 {
+            byte as u32 * 1000
+        } else {
+            self.bus.write(byte, data, &mut self.delay)?;
+            timing::execution_time_us(byte, data)
+        };
+
+        self.hold = micros.div_ceil(self.tick_us).saturating_sub(1);
+
+        Ok(entry & WAIT == 0)
+    }
+
+    /// Give back the consumer, bus and delay
+    pub fn release(self) -> (Consumer<'a, N>, B, D) {
+        (self.consumer, self.bus, self.delay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    use crate::mock::{NoDelay, Recorder};
+
+    #[test]
+    fn fills_up() {
+        let mut fifo = CommandFifo::<4>::new();
+        let (mut producer, mut consumer) = fifo.split();
+
+        producer.write_str("abc").unwrap();
+        assert_eq!(producer.write_str("de"), Err(Error::Full));
+        producer.write_command(0x01).unwrap();
+        assert_eq!(producer.free(), 0);
+
+        assert_eq!(consumer.pop(), Some(DATA | b'a' as u16));
+        producer.write_byte(b'z').unwrap();
+
+        assert_eq!(consumer.pop(), Some(DATA | b'b' as u16));
+        assert_eq!(consumer.pop(), Some(DATA | b'c' as u16));
+        assert_eq!(consumer.pop(), Some(0x01));
+        assert_eq!(consumer.pop(), Some(DATA | b'z' as u16));
+        assert_eq!(consumer.pop(), None);
+    }
+
+    #[test]
+    fn keeps_order_when_counts_wrap() {
+        let mut fifo = CommandFifo::<3>::new();
+        let (mut producer, mut consumer) = fifo.split();
+
+        for round in 0..10u8 {
+            producer.write_bytes(&[round, round + 1]).unwrap();
+            assert_eq!(producer.free(), 1);
+
+            assert_eq!(consumer.pop(), Some(DATA | round as u16));
+            assert_eq!(consumer.pop(), Some(DATA | (round + 1) as u16));
+            assert_eq!(consumer.pop(), None);
+        }
+    }
+
+    #[test]
+    fn paces_writes() {
+        let mut fifo = CommandFifo::<16>::new();
+        let (mut producer, consumer) = fifo.split();
+        let mut pump = Pump::new(consumer, Recorder::default(), NoDelay, 500);
+
+        producer.clear().unwrap();
+        producer.write_str("hi").unwrap();
+
+        // Clearing takes 2 ms, or 4 ticks of 500 us
+        assert_eq!(pump.service(), Ok(true));
+        assert_eq!(pump.service(), Ok(false));
+        assert_eq!(pump.service(), Ok(false));
+        assert_eq!(pump.service(), Ok(false));
+        assert_eq!(pump.service(), Ok(true));
+        assert_eq!(pump.service(), Ok(true));
+        assert_eq!(pump.service(), Ok(false));
+
+        let (_, bus, _) = pump.release();
+        assert_eq!(
+            bus.written[..bus.len],
+            [(0x01, false), (b'h', true), (b'i', true)]
+        );
+    }
+
+    #[test]
+    fn waits_during_init() {
+        let mut fifo = CommandFifo::<16>::new();
+        let (mut producer, consumer) = fifo.split();
+        let mut pump = Pump::new(consumer, Recorder::default(), NoDelay, 1000);
+
+        producer.init_4bit().unwrap();
+
+        // 15 ms after power on before the first instruction
+        for _ in 0..15 {
+            assert_eq!(pump.service(), Ok(false));
+        }
+        assert_eq!(pump.service(), Ok(true));
+
+        let (_, bus, _) = pump.release();
+        assert_eq!(bus.written[..bus.len], [(0x33, false)]);
+    }
+}
@@ -0,0 +1,120 @@
+use core::str::FromStr;
+
+use crate::error::{Error, Result};
+
+/// The number of visible columns and rows of a display, used to translate a
+/// (column, row) position into a DDRAM address
+///
+/// The `HD44780` addresses two lines of 40 characters, starting at `0x00` and
+/// `0x40`. Four row displays show the second half of each of those lines as
+/// their third and fourth row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Geometry {
+    pub columns: u8,
+    pub rows: u8,
+}
+
+impl Geometry {
+    pub const fn new(columns: u8, rows: u8) -> Geometry {
+        Geometry { columns, rows }
+    }
+
+    /// DDRAM address of the cell at `position` (column, row)
+    pub fn address(&self, position: (u8, u8)) -> u8 {
+        let (col, row) = position;
+
+        let offset = match row % 4 {
+            0 => 0x00,
+            1 => 0x40,
+            2 => self.columns,
+            _ => 0x40u8.wrapping_add(self.columns),
+        };
+
+        // Positions past the end of a line wrap around the 7-bit address
+        // space rather than overflowing
+        offset.wrapping_add(col) & 0b0111_1111
+    }
+
+    /// The (column, row) position shown at a DDRAM address, ignoring any
+    /// display shift, or `None` if the address is outside the display
+    pub fn position(&self, address: u8) -> Option<(u8, u8)> {
+        (0..self.rows.min(4)).find_map(|row| {
+            let start = self.address((0, row));
+
+            if address >= start && address - start < self.columns {
+                Some((address - start, row))
+            } else {
+                None
+            }
+        })
+    }
+}
+
+impl FromStr for Geometry {
+    type Err = Error;
+
+    /// Parse a size written as `COLSxROWS`, such as `20x4`
+    fn from_str(size: &str) -> Result<Geometry> {
+        let (columns, rows) = size.split_once('x').ok_or(Error::InvalidArgument)?;
+
+        Ok(Geometry::new(
+            columns.parse().map_err(|_| Error::InvalidArgument)?,
+            rows.parse().map_err(|_| Error::InvalidArgument)?,
+        ))
+    }
+}
+
+impl Default for Geometry {
+    /// A 20x4 display, which has the same layout as 16x2 and 20x2 displays
+    /// for the rows those have
+    fn default() -> Geometry {
+        Geometry::new(20, 4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn addresses() {
+        let geometry = Geometry::new(20, 4);
+
+        assert_eq!(geometry.address((0, 0)), 0x00);
+        assert_eq!(geometry.address((3, 1)), 0x43);
+        assert_eq!(geometry.address((0, 2)), 0x14);
+        assert_eq!(geometry.address((19, 3)), 0x67);
+
+        let geometry = Geometry::new(16, 4);
+
+        assert_eq!(geometry.address((0, 2)), 0x10);
+        assert_eq!(geometry.address((0, 3)), 0x50);
+
+        let geometry = Geometry::new(40, 4);
+
+        assert_eq!(geometry.address((152, 3)), 0x00);
+        assert_eq!(geometry.address((255, 3)), 0x67);
+    }
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!("16x2".parse(), Ok(Geometry::new(16, 2)));
+        assert_eq!("16".parse::<Geometry>(), Err(Error::InvalidArgument));
+        assert_eq!("16x-2".parse::<Geometry>(), Err(Error::InvalidArgument));
+    }
+
+    #[test]
+    fn positions() {
+        let geometry = Geometry::new(20, 4);
+
+        assert_eq!(geometry.position(0x43), Some((3, 1)));
+        assert_eq!(geometry.position(0x14), Some((0, 2)));
+        assert_eq!(geometry.position(0x67), Some((19, 3)));
+        assert_eq!(geometry.position(0x28), None);
+
+        let geometry = Geometry::new(16, 2);
+
+        assert_eq!(geometry.position(0x10), None);
+    }
+}
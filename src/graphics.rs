@@ -0,0 +1,266 @@
+//! Drawing on the display with [`embedded-graphics`](https://docs.rs/embedded-graphics)
+//!
+//! A character display has no addressable pixels, so [`CellTarget`] treats
+//! every character cell as one "pixel" whose color is the byte shown in it.
+//! Together with the [`CellTextStyle`] renderer this lets `embedded-graphics`
+//! text and its alignment lay out strings on the display:
+//!
+//! ```rust,ignore
+//! let mut target = CellTarget::new(&mut lcd, &mut delay);
+//!
+//! Text::new("Hello", Point::new(0, 0), CellTextStyle).draw(&mut target)?;
+//! Text::with_alignment("right", Point::new(19, 1), CellTextStyle, Alignment::Right)
+//!     .draw(&mut target)?;
+//! ```
+//!
+//! Drawings fitting in a single 5x8 cell can be rasterized into a custom
+//! character with a [`GlyphCanvas`]:
+//!
+//! ```rust,ignore
+//! let mut canvas = GlyphCanvas::new();
+//! Circle::new(Point::new(0, 1), 5)
+//!     .into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 1))
+//!     .draw(&mut canvas)?;
+//! canvas.upload(&mut lcd, 0, &mut delay)?;
+//! ```
+
+use core::convert::Infallible;
+
+use embedded_graphics::pixelcolor::raw::RawU8;
+use embedded_graphics::pixelcolor::{BinaryColor, PixelColor};
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::Rectangle;
+use embedded_graphics::text::renderer::{TextMetrics, TextRenderer};
+use embedded_graphics::text::Baseline;
+use embedded_hal::blocking::delay::{DelayMs, DelayUs};
+
+use crate::bus::DataBus;
+use crate::error::{Error, Result};
+use crate::HD44780;
+
+/// Shown for characters that don't fit in a byte
+const REPLACEMENT: u8 = b'?';
+
+/// The "color" of a character cell: the byte displayed in it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CharColor(pub u8);
+
+impl PixelColor for CharColor {
+    type Raw = RawU8;
+}
+
+impl From<RawU8> for CharColor {
+    fn from(raw: RawU8) -> CharColor {
+        CharColor(raw.into_inner())
+    }
+}
+
+/// A draw target with one pixel per character cell, sized after the display
+/// [geometry](../struct.HD44780.html#method.set_geometry)
+pub struct CellTarget<'a, B: DataBus, D: DelayUs<u16> + DelayMs<u8>> {
+    lcd: &'a mut HD44780<B>,
+    delay: &'a mut D,
+}
+
+impl<'a, B: DataBus, D: DelayUs<u16> + DelayMs<u8>> CellTarget<'a, B, D> {
+    pub fn new(lcd: &'a mut HD44780<B>, delay: &'a mut D) -> CellTarget<'a, B, D> {
+        CellTarget { lcd, delay }
+    }
+
+    fn write_cell(&mut self, position: (u8, u8), byte: u8) -> Result<()> {
+        let address = self.lcd.geometry().address(position);
+
+        if self.lcd.cursor_pos() != address {
+            self.lcd.set_cursor_pos(address, self.delay)?;
+        }
+
+        self.lcd.write_byte(byte, self.delay)
+    }
+}
+
+impl<'a, B: DataBus, D: DelayUs<u16> + DelayMs<u8>> OriginDimensions for CellTarget<'a, B, D> {
+    fn size(&self) -> Size {
+        let geometry = self.lcd.geometry();
+
+        Size::new(geometry.columns as u32, geometry.rows as u32)
+    }
+}
+
+impl<'a, B: DataBus, D: DelayUs<u16> + DelayMs<u8>> DrawTarget for CellTarget<'a, B, D> {
+    type Color = CharColor;
+    type Error = Error;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<()>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        let bounds = self.bounding_box();
+
+        for Pixel(point, CharColor(byte)) in pixels {
+            if bounds.contains(point) {
+                self.write_cell((point.x as u8, point.y as u8), byte)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Text renderer for [`CellTarget`], drawing one character per cell. The
+/// baseline is ignored: the position of the text is the cell of its first
+/// character.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CellTextStyle;
+
+impl TextRenderer for CellTextStyle {
+    type Color = CharColor;
+
+    fn draw_string<T>(
+        &self,
+        text: &str,
+        position: Point,
+        _baseline: Baseline,
+        target: &mut T,
+    ) -> core::result::Result<Point, T::Error>
+    where
+        T: DrawTarget<Color = Self::Color>,
+    {
+        let pixels = text.chars().enumerate().map(|(i, c)| {
+            let byte = if (c as u32) <= 0xFF {
+                c as u8
+            } else {
+                REPLACEMENT
+            };
+
+            Pixel(position + Point::new(i as i32, 0), CharColor(byte))
+        });
+
+        target.draw_iter(pixels)?;
+
+        Ok(position + Point::new(text.chars().count() as i32, 0))
+    }
+
+    fn draw_whitespace<T>(
+        &self,
+        width: u32,
+        position: Point,
+        _baseline: Baseline,
+        target: &mut T,
+    ) -> core::result::Result<Point, T::Error>
+    where
+        T: DrawTarget<Color = Self::Color>,
+    {
+        let pixels = (0..width as i32).map(|i| Pixel(position + Point::new(i, 0), CharColor(b' ')));
+
+        target.draw_iter(pixels)?;
+
+        Ok(position + Point::new(width as i32, 0))
+    }
+
+    fn measure_string(&self, text: &str, position: Point, _baseline: Baseline) -> TextMetrics {
+        let width = text.chars().count() as u32;
+
+        TextMetrics {
+            bounding_box: Rectangle::new(position, Size::new(width, if width > 0 { 1 } else { 0 })),
+            next_position: position + Point::new(width as i32, 0),
+        }
+    }
+
+    fn line_height(&self) -> u32 {
+        1
+    }
+}
+
+/// A 5x8 pixel canvas, rasterizing what is drawn on it into a custom character
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct GlyphCanvas {
+    bitmap: [u8; 8],
+}
+
+impl GlyphCanvas {
+    pub fn new() -> GlyphCanvas {
+        GlyphCanvas::default()
+    }
+
+    /// The drawing as rows of a custom character, see
+    /// [set_custom_char](../struct.HD44780.html#method.set_custom_char)
+    pub fn bitmap(&self) -> [u8; 8] {
+        self.bitmap
+    }
+
+    /// Store the drawing in one of the eight CGRAM slots
+    pub fn upload<B: DataBus, D: DelayUs<u16> + DelayMs<u8>>(
+        &self,
+        lcd: &mut HD44780<B>,
+        slot: u8,
+        delay: &mut D,
+    ) -> Result<()> {
+        lcd.set_custom_char(slot, &self.bitmap, delay)
+    }
+}
+
+impl OriginDimensions for GlyphCanvas {
+    fn size(&self) -> Size {
+        Size::new(5, 8)
+    }
+}
+
+impl DrawTarget for GlyphCanvas {
+    type Color = BinaryColor;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> core::result::Result<(), Infallible>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        let bounds = self.bounding_box();
+
+        for Pixel(point, color) in pixels {
+            if bounds.contains(point) {
+                let bit = 0b1_0000 >> point.x;
+                let row = &mut self.bitmap[point.y as usize];
+
+                match color {
+                    BinaryColor::On => *row |= bit,
+                    BinaryColor::Off => *row &= !bit,
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    use embedded_graphics::primitives::{Line, PrimitiveStyle};
+
+    #[test]
+    fn canvas() {
+        let mut canvas = GlyphCanvas::new();
+
+        Line::new(Point::new(0, 0), Point::new(4, 0))
+            .into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 1))
+            .draw(&mut canvas)
+            .unwrap();
+        Pixel(Point::new(4, 7), BinaryColor::On)
+            .draw(&mut canvas)
+            .unwrap();
+        Pixel(Point::new(9, 9), BinaryColor::On)
+            .draw(&mut canvas)
+            .unwrap();
+
+        assert_eq!(canvas.bitmap(), [0b1_1111, 0, 0, 0, 0, 0, 0, 0b0_0001]);
+    }
+
+    #[test]
+    fn measure() {
+        let metrics = CellTextStyle.measure_string("abc", Point::new(2, 1), Baseline::Top);
+
+        assert_eq!(metrics.next_position, Point::new(5, 1));
+        assert_eq!(metrics.bounding_box.size, Size::new(3, 1));
+    }
+}
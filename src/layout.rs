@@ -0,0 +1,317 @@
+//! Writing text into a rectangular region of the display
+//!
+//! [`HD44780::write_str`] writes bytes one after another and lets the
+//! `HD44780` move the cursor, which runs off the end of a row into whatever
+//! row happens to follow it in DDRAM. The functions here instead lay the
+//! text out inside a [`Region`]: every line is positioned explicitly, aligned,
+//! and padded to the full width of the region so that previous contents are
+//! overwritten.
+//!
+//! ```rust,ignore
+//! let label = Field::new((0, 0), 10, Align::Left);
+//! let value = Field::new((10, 0), 10, Align::Right);
+//!
+//! label.draw(&mut lcd, "Voltage", &mut delay)?;
+//! value.draw(&mut lcd, "12.3 V", &mut delay)?;
+//!
+//! let style = TextStyle {
+//!     overflow: Overflow::Wrap,
+//!     ..Default::default()
+//! };
+//! write_in(&mut lcd, Region::new((0, 1), 20, 3), "A longer message wrapped over three rows", &style, &mut delay)?;
+//! ```
+
+use embedded_hal::blocking::delay::{DelayMs, DelayUs};
+
+use crate::bus::DataBus;
+use crate::error::Result;
+use crate::HD44780;
+
+/// The longest line the `HD44780` can address
+const MAX_WIDTH: usize = 80;
+
+/// Horizontal placement of a line within its region
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+/// What to do with text that doesn't fit the width of its region
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    /// Cut the line at the edge of the region
+    Truncate,
+    /// Cut the line and replace its last visible character with the ellipsis
+    Ellipsis,
+    /// Continue on the next row, breaking lines between words where possible
+    Wrap,
+}
+
+/// How text is laid out within a region
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextStyle {
+    pub align: Align,
+    pub overflow: Overflow,
+    /// Byte filling the cells of the region not covered by text
+    pub padding: u8,
+    /// Byte marking a cut line when using [`Overflow::Ellipsis`]. Defaults to
+    /// `0x7E`, which is a right arrow in the common character ROMs.
+    pub ellipsis: u8,
+}
+
+impl Default for TextStyle {
+    fn default() -> TextStyle {
+        TextStyle {
+            align: Align::Left,
+            overflow: Overflow::Truncate,
+            padding: b' ',
+            ellipsis: 0x7E,
+        }
+    }
+}
+
+/// A rectangle of character cells
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    /// Top-left cell (column, row)
+    pub position: (u8, u8),
+    pub width: u8,
+    pub height: u8,
+}
+
+impl Region {
+    pub fn new(position: (u8, u8), width: u8, height: u8) -> Region {
+        Region {
+            position,
+            width,
+            height,
+        }
+    }
+
+    /// The part of this region that is visible on a display of `columns` by `rows` cells
+    fn clip(&self, columns: u8, rows: u8) -> Region {
+        let (col, row) = self.position;
+
+        Region {
+            position: self.position,
+            width: self.width.min(columns.saturating_sub(col)),
+            height: self.height.min(rows.saturating_sub(row)),
+        }
+    }
+}
+
+/// A single row region with a fixed style, for labels and values that are
+/// redrawn in place
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Field {
+    region: Region,
+    style: TextStyle,
+}
+
+impl Field {
+    /// Create a field `width` cells wide at `position` (column, row)
+    pub fn new(position: (u8, u8), width: u8, align: Align) -> Field {
+        Field {
+            region: Region::new(position, width, 1),
+            style: TextStyle {
+                align,
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Create a field using a custom style
+    pub fn with_style(position: (u8, u8), width: u8, style: TextStyle) -> Field {
+        Field {
+            region: Region::new(position, width, 1),
+            style,
+        }
+    }
+
+    /// Replace the contents of the field with `text`
+    pub fn draw<B: DataBus, D: DelayUs<u16> + DelayMs<u8>>(
+        &self,
+        lcd: &mut HD44780<B>,
+        text: &str,
+        delay: &mut D,
+    ) -> Result<()> {
+        write_in(lcd, self.region, text, &self.style, delay)
+    }
+}
+
+/// Write `text` into `region`, filling every cell of the region. Line breaks
+/// (`\n`) in the text start a new row. Rows of the region left after the
+/// text are filled with the padding byte, and text that doesn't fit in the
+/// region's rows is dropped. The region is clipped to the display
+/// [geometry](../struct.HD44780.html#method.set_geometry).
+pub fn write_in<B: DataBus, D: DelayUs<u16> + DelayMs<u8>>(
+    lcd: &mut HD44780<B>,
+    region: Region,
+    text: &str,
+    style: &TextStyle,
+    delay: &mut D,
+) -> Result<()> {
+    let geometry = lcd.geometry();
+    let region = region.clip(geometry.columns, geometry.rows);
+    let width = (region.width as usize).min(MAX_WIDTH);
+    let (col, row) = region.position;
+
+    let mut lines = Lines::new(text.as_bytes(), width, style.overflow == Overflow::Wrap);
+    let mut buffer = [0; MAX_WIDTH];
+
+    for offset in 0..region.height {
+        let line = lines.next().unwrap_or((&[], false));
+        let cells = compose(line, style, &mut buffer[..width]);
+
+        lcd.set_cursor_xy((col, row + offset), delay)?;
+        lcd.write_bytes(cells, delay)?;
+    }
+
+    Ok(())
+}
+
+/// Fill `out` with the aligned and padded `line`, which is marked as cut if it
+/// didn't fit the region
+fn compose<'a>(line: (&[u8], bool), style: &TextStyle, out: &'a mut [u8]) -> &'a [u8] {
+    let (text, cut) = line;
+    let width = out.len();
+    let len = text.len().min(width);
+
+    let start = match style.align {
+        Align::Left => 0,
+        Align::Center => (width - len) / 2,
+        Align::Right => width - len,
+    };
+
+    for cell in out.iter_mut() {
+        *cell = style.padding;
+    }
+    out[start..start + len].copy_from_slice(&text[..len]);
+
+    if cut && style.overflow == Overflow::Ellipsis && width > 0 {
+        out[width - 1] = style.ellipsis;
+    }
+
+    out
+}
+
+/// Splits text into the lines of a region, yielding each line and whether it
+/// was cut
+struct Lines<'a> {
+    rest: &'a [u8],
+    width: usize,
+    wrap: bool,
+}
+
+impl<'a> Lines<'a> {
+    fn new(text: &'a [u8], width: usize, wrap: bool) -> Lines<'a> {
+        Lines {
+            rest: text,
+            width,
+            wrap,
+        }
+    }
+}
+
+impl<'a> Iterator for Lines<'a> {
+    type Item = (&'a [u8], bool);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.rest.is_empty() {
+            return None;
+        }
+
+        let end = self
+            .rest
+            .iter()
+            .position(|&b| b == b'\n')
+            .unwrap_or(self.rest.len());
+        let line = &self.rest[..end];
+
+        if !self.wrap || line.len() <= self.width {
+            self.rest = &self.rest[(end + 1).min(self.rest.len())..];
+            return Some((line, line.len() > self.width));
+        }
+
+        // Break at the last space that keeps the line within the width, or in
+        // the middle of the word if there is none
+        match line[..=self.width].iter().rposition(|&b| b == b' ') {
+            Some(space) if space > 0 => {
+                self.rest = &self.rest[space + 1..];
+                Some((&line[..space], false))
+            }
+            _ => {
+                self.rest = &self.rest[self.width..];
+                Some((&line[..self.width], false))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn lines(text: &str, width: usize, wrap: bool) -> [(&[u8], bool); 4] {
+        let mut out = [(&b""[..], false); 4];
+        for (slot, line) in out.iter_mut().zip(Lines::new(text.as_bytes(), width, wrap)) {
+            *slot = line;
+        }
+        out
+    }
+
+    #[test]
+    fn truncate() {
+        let out = lines("Hello, World!\nSecond", 5, false);
+
+        assert_eq!(out[0], (&b"Hello, World!"[..], true));
+        assert_eq!(out[1], (&b"Second"[..], true));
+        assert_eq!(out[2], (&b""[..], false));
+    }
+
+    #[test]
+    fn wrap() {
+        let out = lines("the quick brown fox", 10, true);
+
+        assert_eq!(out[0].0, b"the quick");
+        assert_eq!(out[1].0, b"brown fox");
+
+        let out = lines("abcdefghij klm", 4, true);
+
+        assert_eq!(out[0].0, b"abcd");
+        assert_eq!(out[1].0, b"efgh");
+        assert_eq!(out[2].0, b"ij");
+        assert_eq!(out[3].0, b"klm");
+    }
+
+    #[test]
+    fn align() {
+        let mut buffer = [0; 8];
+        let mut style = TextStyle::default();
+
+        assert_eq!(compose((b"abc", false), &style, &mut buffer), b"abc     ");
+
+        style.align = Align::Right;
+        assert_eq!(compose((b"abc", false), &style, &mut buffer), b"     abc");
+
+        style.align = Align::Center;
+        style.padding = b'-';
+        assert_eq!(compose((b"abc", false), &style, &mut buffer), b"--abc---");
+    }
+
+    #[test]
+    fn ellipsis() {
+        let mut buffer = [0; 4];
+        let style = TextStyle {
+            overflow: Overflow::Ellipsis,
+            ellipsis: b'>',
+            ..Default::default()
+        };
+
+        assert_eq!(compose((b"abcdef", true), &style, &mut buffer), b"abc>");
+        assert_eq!(compose((b"abcd", false), &style, &mut buffer), b"abcd");
+    }
+}
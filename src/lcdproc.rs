@@ -0,0 +1,760 @@
+//! A server for the LCDproc client protocol
+//!
+//! LCDproc clients connect over TCP, port 13666 by default, and describe
+//! screens made of widgets, which the server lays out on the display. A
+//! [`Server`] keeps track of the screens and draws the one being shown onto
+//! an [`HD44780`] over any bus, and [`serve`] runs it for the clients
+//! connecting to a socket.
+//!
+//! These commands are supported:
+//!
+//! - `hello`, `client_set`, `noop` and `bye`
+//! - `screen_add`, `screen_set` and `screen_del`. Screens with
+//!   `-priority hidden` aren't shown, the others are shown in turn by
+//!   [`next_screen`](Server::next_screen).
+//! - `widget_add`, `widget_set` and `widget_del`, for the widget types
+//!   `string`, `title`, `hbar`, `vbar` and `num`
+//! - `backlight on` and `backlight off`
+//!
+//! Big numbers need more custom characters than are left after the bars, so
+//! `num` widgets are drawn as a plain digit in the middle row, or `:` for
+//! 10. Vertical bars have a resolution of two pixel rows for the same
+//! reason.
+//!
+//! ```rust,ignore
+//! let listener = TcpListener::bind(("127.0.0.1", lcdproc::PORT))?;
+//! let stop = AtomicBool::new(false);
+//!
+//! lcdproc::serve(listener, &mut lcd, &mut delay, &stop)?;
+//! ```
+
+use std::borrow::ToOwned;
+use std::format;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::string::{String, ToString};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use std::vec::Vec;
+
+use embedded_hal::blocking::delay::{DelayMs, DelayUs};
+
+use crate::bar::{cell_byte, horizontal_glyph, vertical_glyph, CELL_HEIGHT, CELL_WIDTH};
+use crate::bus::DataBus;
+use crate::error::Result;
+use crate::geometry::Geometry;
+use crate::terminal::Terminal;
+use crate::{Cursor, CursorBlink, HD44780};
+
+/// Port LCDproc servers listen on
+pub const PORT: u16 = 13666;
+
+/// How long each screen is shown before the next
+pub const SCREEN_DURATION: Duration = Duration::from_secs(4);
+
+/// First of the slots with horizontal bar glyphs
+const HBAR_SLOT: u8 = 0;
+
+/// First of the slots with vertical bar glyphs
+const VBAR_SLOT: u8 = 4;
+
+/// Pixel rows per step of a vertical bar
+const VBAR_STEP: u8 = 2;
+
+/// Time between checks for clients and their commands
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Bytes of replies held for a client before it is taken for gone
+const MAX_PENDING: usize = 64 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Kind {
+    String { x: u8, y: u8, text: String },
+    Title(String),
+    HBar { x: u8, y: u8, length: u16 },
+    VBar { x: u8, y: u8, length: u16 },
+    Num { x: u8, digit: u8 },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Widget {
+    id: String,
+    kind: Kind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Screen {
+    client: usize,
+    id: String,
+    hidden: bool,
+    widgets: Vec<Widget>,
+}
+
+/// The screens of all clients, see the [module documentation](index.html)
+pub struct Server {
+    geometry: Geometry,
+    screens: Vec<Screen>,
+    /// Index of the screen being shown
+    current: usize,
+    backlight: Option<bool>,
+    terminal: Terminal,
+    /// Whether the screens changed since they were last drawn
+    dirty: bool,
+    /// Whether the glyphs and display mode were set up
+    ready: bool,
+}
+
+impl Server {
+    /// A server for a display of size `geometry`, up to 40 columns and 4 rows
+    pub fn new(geometry: Geometry) -> Server {
+        Server {
+            geometry,
+            screens: Vec::new(),
+            current: 0,
+            backlight: None,
+            terminal: Terminal::new(geometry),
+            dirty: true,
+            ready: false,
+        }
+    }
+
+    /// Carry out a command line from `client`, returning the reply to send,
+    /// or `None` if the client said goodbye
+    pub fn handle(&mut self, client: usize, line: &str) -> Option<String> {
+        let args = tokenize(line);
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+        let reply = match args.as_slice() {
+            ["hello", ..] => Ok(format!(
+                "connect LCDproc 0.5.9 protocol 0.3 lcd wid {} hgt {} cellwid {} cellhgt {}",
+                self.geometry.columns, self.geometry.rows, CELL_WIDTH, CELL_HEIGHT
+            )),
+            ["bye", ..] => return None,
+            ["noop"] | ["client_set", ..] => Ok("success".to_owned()),
+            ["screen_add", id] => self.add_screen(client, id),
+            ["screen_set", id, options @ ..] => self.set_screen(client, id, options),
+            ["screen_del", id] => self.delete_screen(client, id),
+            ["widget_add", screen, id, kind, ..] => self.add_widget(client, screen, id, kind),
+            ["widget_set", screen, id, values @ ..] => self.set_widget(client, screen, id, values),
+            ["widget_del", screen, id] => self.delete_widget(client, screen, id),
+            ["backlight", state] => match *state {
+                "on" | "off" => {
+                    self.backlight = Some(*state == "on");
+                    Ok("success".to_owned())
+                }
+                _ => Err("Invalid backlight state"),
+            },
+            [] => Err("Empty command"),
+            _ => Err("Invalid command"),
+        };
+
+        Some(match reply {
+            Ok(reply) => reply + "\n",
+            Err(error) => format!("huh? {}\n", error),
+        })
+    }
+
+    /// Remove the screens of a client that went away
+    pub fn disconnect(&mut self, client: usize) {
+        self.screens.retain(|screen| screen.client != client);
+        self.dirty = true;
+    }
+
+    /// Show the next screen that isn't hidden
+    pub fn next_screen(&mut self) {
+        let count = self.screens.len();
+
+        if let Some(next) = (1..=count)
+            .map(|step| (self.current + step) % count)
+            .find(|&index| !self.screens[index].hidden)
+        {
+            self.dirty |= next != self.current;
+            self.current = next;
+        }
+    }
+
+    /// Draw the screen being shown, if anything changed since the last time
+    pub fn render<B: DataBus, D: DelayUs<u16> + DelayMs<u8>>(
+        &mut self,
+        lcd: &mut HD44780<B>,
+        delay: &mut D,
+    ) -> Result<()> {
+        if !self.ready {
+            for filled in 1..CELL_WIDTH {
+                lcd.set_custom_char(HBAR_SLOT + filled - 1, &horizontal_glyph(filled), delay)?;
+            }
+
+            for step in 1..CELL_HEIGHT / VBAR_STEP {
+                lcd.set_custom_char(
+                    VBAR_SLOT + step - 1,
+                    &vertical_glyph(step * VBAR_STEP),
+                    delay,
+                )?;
+            }
+
+            lcd.set_cursor_visibility(Cursor::Invisible, delay)?;
+            lcd.set_cursor_blink(CursorBlink::Off, delay)?;
+            self.ready = true;
+        }
+
+        if let Some(on) = self.backlight.take() {
+            lcd.set_backlight(on, delay)?;
+        }
+
+        if !self.dirty {
+            return Ok(());
+        }
+
+        self.terminal.clear();
+
+        if let Some(screen) = self
+            .screens
+            .get(self.current)
+            .filter(|screen| !screen.hidden)
+        {
+            for widget in &screen.widgets {
+                draw(&mut self.terminal, self.geometry, &widget.kind);
+            }
+        }
+
+        self.terminal.flush(lcd, delay)?;
+        self.dirty = false;
+
+        Ok(())
+    }
+
+    fn add_screen(
+        &mut self,
+        client: usize,
+        id: &str,
+    ) -> core::result::Result<String, &'static str> {
+        if self.screen(client, id).is_some() {
+            return Err("Screen already exists");
+        }
+
+        self.screens.push(Screen {
+            client,
+            id: id.to_owned(),
+            hidden: false,
+            widgets: Vec::new(),
+        });
+
+        // Show the new screen right away
+        self.current = self.screens.len() - 1;
+        self.dirty = true;
+
+        Ok("success".to_owned())
+    }
+
+    fn set_screen(
+        &mut self,
+        client: usize,
+        id: &str,
+        options: &[&str],
+    ) -> core::result::Result<String, &'static str> {
+        let screen = self.screen(client, id).ok_or("Unknown screen id")?;
+
+        // Options other than the priority don't change how screens are drawn
+        for pair in options.windows(2) {
+            if pair[0] == "-priority" {
+                screen.hidden = pair[1] == "hidden";
+            }
+        }
+
+        self.dirty = true;
+        self.next_if_hidden();
+
+        Ok("success".to_owned())
+    }
+
+    fn delete_screen(
+        &mut self,
+        client: usize,
+        id: &str,
+    ) -> core::result::Result<String, &'static str> {
+        let index = self
+            .screens
+            .iter()
+            .position(|screen| screen.client == client && screen.id == id)
+            .ok_or("Unknown screen id")?;
+
+        self.screens.remove(index);
+
+        if self.current >= index && self.current > 0 {
+            self.current -= 1;
+        }
+
+        self.dirty = true;
+        self.next_if_hidden();
+
+        Ok("success".to_owned())
+    }
+
+    fn add_widget(
+        &mut self,
+        client: usize,
+        screen: &str,
+        id: &str,
+        kind: &str,
+    ) -> core::result::Result<String, &'static str> {
+        let kind = match kind {
+            "string" => Kind::String {
+                x: 1,
+                y: 1,
+                text: String::new(),
+            },
+            "title" => Kind::Title(String::new()),
+            "hbar" => Kind::HBar {
+                x: 1,
+                y: 1,
+                length: 0,
+            },
+            "vbar" => Kind::VBar {
+                x: 1,
+                y: 1,
+                length: 0,
+            },
+            "num" => Kind::Num { x: 1, digit: 0 },
+            _ => return Err("Unsupported widget type"),
+        };
+
+        let screen = self.screen(client, screen).ok_or("Unknown screen id")?;
+
+        if screen.widgets.iter().any(|widget| widget.id == id) {
+            return Err("Widget already exists");
+        }
+
+        screen.widgets.push(Widget {
+            id: id.to_owned(),
+            kind,
+        });
+
+        Ok("success".to_owned())
+    }
+
+    fn set_widget(
+        &mut self,
+        client: usize,
+        screen: &str,
+        id: &str,
+        values: &[&str],
+    ) -> core::result::Result<String, &'static str> {
+        let screen = self.screen(client, screen).ok_or("Unknown screen id")?;
+        let widget = screen
+            .widgets
+            .iter_mut()
+            .find(|widget| widget.id == id)
+            .ok_or("Unknown widget id")?;
+
+        let number = |index: usize| -> core::result::Result<u16, &'static str> {
+            values
+                .get(index)
+                .and_then(|value| value.parse().ok())
+                .ok_or("Invalid coordinates")
+        };
+        let small = |index: usize| number(index).map(|value| value.min(u8::MAX as u16) as u8);
+
+        widget.kind = match widget.kind {
+            Kind::String { .. } => Kind::String {
+                x: small(0)?,
+                y: small(1)?,
+                text: values
+                    .get(2)
+                    .ok_or("Wrong number of arguments")?
+                    .to_string(),
+            },
+            Kind::Title(_) => Kind::Title(
+                values
+                    .first()
+                    .ok_or("Wrong number of arguments")?
+                    .to_string(),
+            ),
+            Kind::HBar { .. } => Kind::HBar {
+                x: small(0)?,
+                y: small(1)?,
+                length: number(2)?,
+            },
+            Kind::VBar { .. } => Kind::VBar {
+                x: small(0)?,
+                y: small(1)?,
+                length: number(2)?,
+            },
+            Kind::Num { .. } => Kind::Num {
+                x: small(0)?,
+                digit: small(1)?,
+            },
+        };
+
+        self.dirty = true;
+
+        Ok("success".to_owned())
+    }
+
+    fn delete_widget(
+        &mut self,
+        client: usize,
+        screen: &str,
+        id: &str,
+    ) -> core::result::Result<String, &'static str> {
+        let screen = self.screen(client, screen).ok_or("Unknown screen id")?;
+        let count = screen.widgets.len();
+
+        screen.widgets.retain(|widget| widget.id != id);
+
+        if screen.widgets.len() == count {
+            return Err("Unknown widget id");
+        }
+
+        self.dirty = true;
+
+        Ok("success".to_owned())
+    }
+
+    fn screen(&mut self, client: usize, id: &str) -> Option<&mut Screen> {
+        self.screens
+            .iter_mut()
+            .find(|screen| screen.client == client && screen.id == id)
+    }
+
+    fn next_if_hidden(&mut self) {
+        if self
+            .screens
+            .get(self.current)
+            .is_some_and(|screen| screen.hidden)
+        {
+            self.next_screen();
+        }
+    }
+}
+
+/// Draw a widget into the terminal buffer. Positions count from 1, and
+/// whatever falls outside of the display is cut off.
+fn draw(terminal: &mut Terminal, geometry: Geometry, kind: &Kind) {
+    let mut put = |x: u8, y: u8, byte: u8| {
+        if (1..=geometry.columns).contains(&x) && (1..=geometry.rows).contains(&y) {
+            terminal.move_to((x - 1, y - 1));
+            terminal.write_byte(byte);
+        }
+    };
+
+    match kind {
+        Kind::String { x, y, text } => {
+            // Whatever is past the width of the display would be cut off
+            for (offset, byte) in text.bytes().enumerate().take(geometry.columns as usize) {
+                put(x.saturating_add(offset as u8), *y, printable(byte));
+            }
+        }
+        Kind::Title(text) => {
+            let title = format!("## {} ", text);
+            let bytes = title.bytes().chain(core::iter::repeat(b'#'));
+
+            for (x, byte) in (1..=geometry.columns).zip(bytes) {
+                put(x, 1, printable(byte));
+            }
+        }
+        Kind::HBar { x, y, length } => {
+            let cells = (*length / CELL_WIDTH as u16 + 1).min(u8::MAX as u16) as u8;
+
+            for cell in 0..cells {
+                let byte = cell_byte(*length as u32, cell, CELL_WIDTH, HBAR_SLOT);
+                put(x.saturating_add(cell), *y, byte);
+            }
+        }
+        Kind::VBar { x, y, length } => {
+            let steps = (length / VBAR_STEP as u16) as u32;
+            let per_cell = CELL_HEIGHT / VBAR_STEP;
+
+            for cell in 0..*y {
+                put(*x, y - cell, cell_byte(steps, cell, per_cell, VBAR_SLOT));
+            }
+        }
+        Kind::Num { x, digit } => {
+            let byte = match digit {
+                0..=9 => b'0' + digit,
+                _ => b':',
+            };
+
+            put(*x, geometry.rows / 2 + 1, byte);
+        }
+    }
+}
+
+/// Replace bytes the terminal would take for control characters
+fn printable(byte: u8) -> u8 {
+    if byte < b' ' {
+        b'?'
+    } else {
+        byte
+    }
+}
+
+/// Split a command line into words. Words can be quoted with `"` or wrapped
+/// in `{}`, and a backslash takes the next character as it is.
+fn tokenize(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut chars = line.trim().chars().peekable();
+
+    while let Some(&first) = chars.peek() {
+        if first.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        let close = match first {
+            '"' => Some('"'),
+            '{' => Some('}'),
+            _ => None,
+        };
+
+        if close.is_some() {
+            chars.next();
+        }
+
+        let mut word = String::new();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => word.extend(chars.next()),
+                c if Some(c) == close => break,
+                c if close.is_none() && c.is_whitespace() => break,
+                c => word.push(c),
+            }
+        }
+
+        words.push(word);
+    }
+
+    words
+}
+
+struct Connection {
+    stream: TcpStream,
+    /// Received bytes not yet making up a whole line
+    buffer: Vec<u8>,
+    /// Replies the client hasn't taken yet
+    pending: Vec<u8>,
+}
+
+/// Answer the clients connecting to `listener` and show their screens on
+/// `lcd`, until `stop` is set. The display size is taken from its
+/// [geometry](../struct.HD44780.html#method.geometry).
+pub fn serve<B: DataBus, D: DelayUs<u16> + DelayMs<u8>>(
+    listener: TcpListener,
+    lcd: &mut HD44780<B>,
+    delay: &mut D,
+    stop: &AtomicBool,
+) -> io::Result<()> {
+    let mut server = Server::new(lcd.geometry());
+    let mut connections: Vec<Option<Connection>> = Vec::new();
+    let mut shown_since = Instant::now();
+
+    listener.set_nonblocking(true)?;
+
+    while !stop.load(Ordering::Relaxed) {
+        loop {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    stream.set_nonblocking(true)?;
+
+                    let connection = Connection {
+                        stream,
+                        buffer: Vec::new(),
+                        pending: Vec::new(),
+                    };
+
+                    // Client numbers are the index of their connection
+                    match connections.iter().position(Option::is_none) {
+                        Some(free) => connections[free] = Some(connection),
+                        None => connections.push(Some(connection)),
+                    }
+                }
+                Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+                Err(error) => return Err(error),
+            }
+        }
+
+        for (client, slot) in connections.iter_mut().enumerate() {
+            if let Some(connection) = slot {
+                if !poll_connection(&mut server, client, connection) {
+                    server.disconnect(client);
+                    *slot = None;
+                }
+            }
+        }
+
+        if shown_since.elapsed() >= SCREEN_DURATION {
+            server.next_screen();
+            shown_since = Instant::now();
+        }
+
+        server.render(lcd, delay).map_err(io::Error::other)?;
+
+        thread::sleep(POLL_INTERVAL);
+    }
+
+    Ok(())
+}
+
+/// Read and answer the commands a client sent, returning whether it is
+/// still connected
+fn poll_connection(server: &mut Server, client: usize, connection: &mut Connection) -> bool {
+    let mut chunk = [0; 512];
+
+    loop {
+        match connection.stream.read(&mut chunk) {
+            Ok(0) => return false,
+            Ok(len) => connection.buffer.extend_from_slice(&chunk[..len]),
+            Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+            Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+            Err(_) => return false,
+        }
+    }
+
+    while let Some(end) = connection.buffer.iter().position(|&byte| byte == b'\n') {
+        let line: Vec<u8> = connection.buffer.drain(..=end).collect();
+        let line = String::from_utf8_lossy(&line);
+
+        match server.handle(client, &line) {
+            Some(reply) => connection.pending.extend_from_slice(reply.as_bytes()),
+            None => return false,
+        }
+    }
+
+    // A client that sends commands without ever reading the replies is gone
+    send_pending(connection) && connection.pending.len() <= MAX_PENDING
+}
+
+/// Write as much of the pending replies as the client takes without
+/// blocking, returning whether it is still connected
+fn send_pending(connection: &mut Connection) -> bool {
+    while !connection.pending.is_empty() {
+        match connection.stream.write(&connection.pending) {
+            Ok(0) => return false,
+            Ok(len) => {
+                connection.pending.drain(..len);
+            }
+            Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+            Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+            Err(_) => return false,
+        }
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::sim::{NoDelay, SimBus};
+
+    #[test]
+    fn splits_words() {
+        assert_eq!(
+            tokenize(r#"widget_set s w 1 2 "a \"b\"" {c d}"#),
+            ["widget_set", "s", "w", "1", "2", "a \"b\"", "c d"]
+        );
+    }
+
+    #[test]
+    fn replies() {
+        let mut server = Server::new(Geometry::new(20, 4));
+
+        assert_eq!(
+            server.handle(0, "hello").as_deref(),
+            Some("connect LCDproc 0.5.9 protocol 0.3 lcd wid 20 hgt 4 cellwid 5 cellhgt 8\n")
+        );
+        assert_eq!(
+            server.handle(0, "screen_add s").as_deref(),
+            Some("success\n")
+        );
+        assert_eq!(
+            server.handle(0, "widget_add s w clock").as_deref(),
+            Some("huh? Unsupported widget type\n")
+        );
+        assert_eq!(
+            server.handle(1, "screen_del s").as_deref(),
+            Some("huh? Unknown screen id\n")
+        );
+        assert_eq!(server.handle(0, "bye"), None);
+    }
+
+    #[test]
+    fn keeps_replies_for_slow_clients() {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        stream.set_nonblocking(true).unwrap();
+
+        // More than the socket buffers hold, while the client reads nothing
+        let mut connection = Connection {
+            stream,
+            buffer: Vec::new(),
+            pending: vec![b'x'; 16 * 1024 * 1024],
+        };
+
+        assert!(send_pending(&mut connection));
+        assert!(!connection.pending.is_empty());
+
+        drop(client);
+    }
+
+    #[test]
+    fn draws_widgets() {
+        let sim = SimBus::new();
+        let geometry = Geometry::new(20, 4);
+        let mut lcd = HD44780::new_simulated(sim.clone(), &mut NoDelay).unwrap();
+        let mut server = Server::new(geometry);
+
+        for line in [
+            "screen_add s",
+            "widget_add s t title",
+            "widget_set s t Load",
+            "widget_add s text string",
+            "widget_set s text 2 2 {cpu 42%}",
+            "widget_add s bar hbar",
+            "widget_set s bar 1 4 12",
+            "widget_add s n num",
+            "widget_set s n 20 7",
+        ] {
+            assert_eq!(server.handle(0, line).as_deref(), Some("success\n"));
+        }
+
+        server.render(&mut lcd, &mut NoDelay).unwrap();
+
+        assert_eq!(
+            sim.text(geometry),
+            [
+                "## Load ############",
+                " cpu 42%            ",
+                "                   7",
+                "##*                 ",
+            ]
+        );
+        assert_eq!(sim.custom_char(HBAR_SLOT + 1), horizontal_glyph(2));
+    }
+
+    #[test]
+    fn cuts_off_long_strings() {
+        let sim = SimBus::new();
+        let geometry = Geometry::new(20, 4);
+        let mut lcd = HD44780::new_simulated(sim.clone(), &mut NoDelay).unwrap();
+        let mut server = Server::new(geometry);
+
+        // Longer than an offset into the line can count
+        let text = format!("{}b", "a".repeat(256));
+
+        for line in [
+            "screen_add s".to_string(),
+            "widget_add s text string".to_string(),
+            format!("widget_set s text 1 1 {}", text),
+        ] {
+            assert_eq!(server.handle(0, &line).as_deref(), Some("success\n"));
+        }
+
+        server.render(&mut lcd, &mut NoDelay).unwrap();
+
+        assert_eq!(sim.text(geometry)[0], "a".repeat(20));
+    }
+}
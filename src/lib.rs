@@ -1,4 +1,4 @@
-#![no_std]
+#![cfg_attr(not(feature = "std"), no_std)]
 #![cfg_attr(feature = "async", feature(generic_associated_types))]
 #![cfg_attr(feature = "async", feature(type_alias_impl_trait))]
 
@@ -10,10 +10,10 @@ use embedded_hal::blocking::i2c;
 use embedded_hal::digital::v2::OutputPin;
 
 pub mod bus;
-use bus::{DataBus, EightBitBus, FourBitBus, I2CBus};
+use bus::{DataBus, EightBitBus, FourBitBus, I2CBus, PinMap, ReadableBus};
 
 pub mod error;
-use error::Result;
+use error::{Error, Result};
 
 pub mod entry_mode;
 
@@ -23,6 +23,70 @@ pub mod display_mode;
 
 pub use display_mode::DisplayMode;
 
+pub mod geometry;
+
+pub use geometry::Geometry;
+
+pub mod bar;
+
+pub mod cgram;
+
+pub mod layout;
+
+pub mod terminal;
+
+pub mod ansi;
+
+pub mod matrix_orbital;
+
+#[cfg(feature = "std")]
+pub mod sim;
+#[cfg(feature = "std")]
+use sim::SimBus;
+
+#[cfg(feature = "std")]
+pub mod lcdproc;
+
+pub mod poll;
+
+pub mod fifo;
+
+pub mod timing;
+use timing::Timing;
+
+mod counter;
+use counter::AddressCounter;
+
+mod cache;
+use cache::CommandCache;
+
+mod shadow;
+use shadow::Shadow;
+
+pub mod reinit;
+use reinit::ReinitSchedule;
+
+pub mod detect;
+use detect::{Detection, PROBED_BYTES, PROBE_LENGTHS};
+
+mod erased;
+use erased::{DynDelay, ReadFn};
+
+pub mod verify;
+use verify::{Verifier, VerifyPolicy};
+
+pub mod busy;
+use busy::{BusyPolicy, BusyPoller};
+
+#[cfg(test)]
+mod mock;
+
+#[cfg(feature = "menu")]
+pub mod menu;
+
+#[cfg(feature = "graphics")]
+pub mod graphics;
+
 /// Implementation of async functionality
 #[cfg(feature = "async")]
 pub mod non_blocking;
@@ -31,6 +95,15 @@ pub struct HD44780<B: DataBus> {
     bus: B,
     entry_mode: EntryMode,
     display_mode: DisplayMode,
+    geometry: Geometry,
+    counter: AddressCounter,
+    /// Function set instruction selecting the interface width
+    function_set: u8,
+    cache: CommandCache,
+    shadow: Shadow,
+    verifier: Option<Verifier<B>>,
+    timing: Timing,
+    busy: Option<BusyPoller<B>>,
 }
 
 /// Used in the direction argument for shifting the cursor and the display
@@ -92,11 +165,10 @@ impl<
         d7: D7,
         delay: &mut D,
     ) -> Result<HD44780<EightBitBus<RS, EN, D0, D1, D2, D3, D4, D5, D6, D7>>> {
-        let mut hd = HD44780 {
-            bus: EightBitBus::from_pins(rs, en, d0, d1, d2, d3, d4, d5, d6, d7),
-            entry_mode: EntryMode::default(),
-            display_mode: DisplayMode::default(),
-        };
+        let mut hd = HD44780::from_bus(
+            EightBitBus::from_pins(rs, en, d0, d1, d2, d3, d4, d5, d6, d7),
+            0b0011_1000,
+        );
 
         hd.init_8bit(delay)?;
 
@@ -135,15 +207,75 @@ impl<RS: OutputPin, EN: OutputPin, D4: OutputPin, D5: OutputPin, D6: OutputPin,
         d7: D7,
         delay: &mut D,
     ) -> Result<HD44780<FourBitBus<RS, EN, D4, D5, D6, D7>>> {
-        let mut hd = HD44780 {
-            bus: FourBitBus::from_pins(rs, en, d4, d5, d6, d7),
-            entry_mode: EntryMode::default(),
-            display_mode: DisplayMode::default(),
-        };
+        HD44780::new_4bit_bus(
+            FourBitBus::from_pins(rs, en, d4, d5, d6, d7),
+            Timing::default(),
+            delay,
+        )
+    }
 
-        hd.init_4bit(delay)?;
+    /// Take over a display that is already initialized without clearing
+    /// it, resynchronizing the interface as
+    /// [attach_i2c](#method.attach_i2c) does.
+    ///
+    /// With its R/W line tied low the display can't be read back, so the
+    /// cursor is left where it is and the driver doesn't know the text or
+    /// glyphs shown. The display and entry modes are set to their defaults.
+    pub fn attach_4bit<D: DelayUs<u16> + DelayMs<u8>>(
+        rs: RS,
+        en: EN,
+        d4: D4,
+        d5: D5,
+        d6: D6,
+        d7: D7,
+        delay: &mut D,
+    ) -> Result<HD44780<FourBitBus<RS, EN, D4, D5, D6, D7>>> {
+        HD44780::attach_4bit_bus(
+            FourBitBus::from_pins(rs, en, d4, d5, d6, d7),
+            Timing::default(),
+            delay,
+        )
+    }
+}
 
-        return Ok(hd);
+#[cfg(feature = "bridge")]
+impl<S: embedded_io::Write> HD44780<bus::bridge::BridgeBus<S>> {
+    /// Create an instance of a `HD44780` on a display at the other end of
+    /// `stream`, see [bridge](bus/bridge/index.html)
+    ///
+    /// ```rust,ignore
+    /// let mut lcd = HD44780::new_bridge(uart_tx, &mut delay)?;
+    /// ```
+    pub fn new_bridge<D: DelayUs<u16> + DelayMs<u8>>(
+        stream: S,
+        delay: &mut D,
+    ) -> Result<HD44780<bus::bridge::BridgeBus<S>>> {
+        HD44780::new_4bit_bus(
+            bus::bridge::BridgeBus::new(stream),
+            Timing::default(),
+            delay,
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl HD44780<SimBus> {
+    /// Create a new instance of a HD44780 on a simulated display, see
+    /// [sim](sim/index.html)
+    ///
+    /// ```rust,ignore
+    /// let sim = SimBus::new();
+    /// let mut lcd = HD44780::new_simulated(sim.clone(), &mut NoDelay)?;
+    /// ```
+    pub fn new_simulated<D: DelayUs<u16> + DelayMs<u8>>(
+        bus: SimBus,
+        delay: &mut D,
+    ) -> Result<HD44780<SimBus>> {
+        let mut hd = HD44780::from_bus(bus, 0b0011_1000);
+
+        hd.init_8bit(delay)?;
+
+        Ok(hd)
     }
 }
 
@@ -162,15 +294,382 @@ impl<I2C: i2c::Write> HD44780<I2CBus<I2C>> {
         address: u8,
         delay: &mut D,
     ) -> Result<HD44780<I2CBus<I2C>>> {
-        let mut hd = HD44780 {
-            bus: I2CBus::new(i2c_bus, address),
+        HD44780::new_i2c_with_pins(i2c_bus, address, PinMap::default(), delay)
+    }
+
+    /// Create an instance of a `HD44780` on an I2C backpack whose expander
+    /// is wired to the display differently from the common PCF8574 boards,
+    /// see [`PinMap`](bus/struct.PinMap.html)
+    pub fn new_i2c_with_pins<D: DelayUs<u16> + DelayMs<u8>>(
+        i2c_bus: I2C,
+        address: u8,
+        pins: PinMap,
+        delay: &mut D,
+    ) -> Result<HD44780<I2CBus<I2C>>> {
+        HD44780::new_4bit_bus(
+            I2CBus::with_pins(i2c_bus, address, pins),
+            Timing::default(),
+            delay,
+        )
+    }
+}
+
+impl<I2C: i2c::Write + i2c::Read> HD44780<I2CBus<I2C>> {
+    /// Create an instance of a `HD44780` on an I2C backpack whose address
+    /// isn't known, returning it along with the address found. See
+    /// [`probe_i2c`](bus/fn.probe_i2c.html) for the addresses tried.
+    ///
+    /// Fails with `Error::NotFound` if no backpack answers.
+    ///
+    /// ```rust,ignore
+    /// let (mut lcd, address) = HD44780::new_i2c_probe(i2c, &mut delay)?;
+    /// ```
+    pub fn new_i2c_probe<D: DelayUs<u16> + DelayMs<u8>>(
+        mut i2c_bus: I2C,
+        delay: &mut D,
+    ) -> Result<(HD44780<I2CBus<I2C>>, u8)> {
+        let address = bus::probe_i2c(&mut i2c_bus)?;
+
+        Ok((HD44780::new_i2c(i2c_bus, address, delay)?, address))
+    }
+
+    /// Take over a display that is already initialized, for example by the
+    /// firmware before a restart, without clearing it. The interface is
+    /// resynchronized, then the text, glyphs and cursor position are read
+    /// back from the display, see [reload](#method.reload).
+    ///
+    /// The display and entry modes can't be read back, so they are set to
+    /// their defaults.
+    ///
+    /// ```rust,ignore
+    /// let mut lcd = HD44780::attach_i2c(i2c, 0x27, &mut delay)?;
+    /// ```
+    pub fn attach_i2c<D: DelayUs<u16> + DelayMs<u8>>(
+        i2c_bus: I2C,
+        address: u8,
+        delay: &mut D,
+    ) -> Result<HD44780<I2CBus<I2C>>> {
+        HD44780::attach_i2c_with_pins(i2c_bus, address, PinMap::default(), delay)
+    }
+
+    /// Take over a display that is already initialized, like
+    /// [attach_i2c](#method.attach_i2c), on a backpack wired as `pins`
+    pub fn attach_i2c_with_pins<D: DelayUs<u16> + DelayMs<u8>>(
+        i2c_bus: I2C,
+        address: u8,
+        pins: PinMap,
+        delay: &mut D,
+    ) -> Result<HD44780<I2CBus<I2C>>> {
+        let mut hd = HD44780::attach_4bit_bus(
+            I2CBus::with_pins(i2c_bus, address, pins),
+            Timing::default(),
+            delay,
+        )?;
+
+        hd.reload(delay)?;
+
+        Ok(hd)
+    }
+}
+
+impl<B: DataBus> HD44780<B> {
+    /// A driver for the display behind `bus`, which is left uninitialized.
+    /// The constructors set it up for the interface width in `function_set`.
+    fn from_bus(bus: B, function_set: u8) -> HD44780<B> {
+        HD44780 {
+            bus,
             entry_mode: EntryMode::default(),
             display_mode: DisplayMode::default(),
-        };
+            geometry: Geometry::default(),
+            counter: AddressCounter::default(),
+            function_set,
+            cache: CommandCache::default(),
+            shadow: Shadow::default(),
+            verifier: None,
+            timing: Timing::default(),
+            busy: None,
+        }
+    }
+
+    /// Create an instance of a `HD44780` on a bus that drives it in 4-bit
+    /// mode, such as an [`I2CBus`](bus/struct.I2CBus.html) or a
+    /// [`FourBitBus`](bus/struct.FourBitBus.html), waiting as `timing` says
+    /// from the first instruction on. Slow clones of the controller may not
+    /// even initialize with the times from the datasheet.
+    ///
+    /// ```rust,ignore
+    /// let timing = Timing {
+    ///     command_us: 200,
+    ///     long_command_us: 4_000,
+    /// };
+    /// let mut lcd = HD44780::new_4bit_bus(I2CBus::new(i2c, 0x27), timing, &mut delay)?;
+    /// ```
+    pub fn new_4bit_bus<D: DelayUs<u16> + DelayMs<u8>>(
+        bus: B,
+        timing: Timing,
+        delay: &mut D,
+    ) -> Result<HD44780<B>> {
+        let mut hd = HD44780::from_bus(bus, 0b0010_1000);
+        hd.timing = timing;
 
         hd.init_4bit(delay)?;
 
-        return Ok(hd);
+        Ok(hd)
+    }
+
+    /// Take over a display that is already initialized without clearing it,
+    /// like [attach_4bit](#method.attach_4bit), on a bus that drives it in
+    /// 4-bit mode, waiting as `timing` says
+    pub fn attach_4bit_bus<D: DelayUs<u16> + DelayMs<u8>>(
+        bus: B,
+        timing: Timing,
+        delay: &mut D,
+    ) -> Result<HD44780<B>> {
+        let mut hd = HD44780::from_bus(bus, 0b0010_1000);
+        hd.timing = timing;
+
+        // Whether or not the interface was halfway through a byte, the
+        // nibbles 0x3, 0x3, 0x3 switch to 8-bit mode, and 0x2 back to 4-bit
+        hd.bus.write(0x33, false, delay)?;
+        delay.delay_ms(5u8);
+        hd.bus.write(0x32, false, delay)?;
+        delay.delay_ms(5u8);
+
+        hd.send_command(hd.function_set, delay)?;
+        hd.send_command(hd.display_mode.as_byte(), delay)?;
+        hd.send_command(hd.entry_mode.as_byte(), delay)?;
+
+        Ok(hd)
+    }
+}
+
+impl<B> HD44780<B>
+where
+    B: ReadableBus,
+{
+    /// Read the byte shown at `position` (column, row)
+    ///
+    /// ```rust,ignore
+    /// let byte = lcd.read_char((3, 1), &mut delay)?
+    /// ```
+    pub fn read_char<D: DelayUs<u16> + DelayMs<u8>>(
+        &mut self,
+        position: (u8, u8),
+        delay: &mut D,
+    ) -> Result<u8> {
+        let mut byte = [0];
+
+        self.read_ram(
+            0b1000_0000 | self.geometry.address(position),
+            &mut byte,
+            delay,
+        )?;
+
+        Ok(byte[0])
+    }
+
+    /// Read a row of the display into `buffer`, up to the number of columns
+    /// of the display [geometry](#method.set_geometry). Returns the number
+    /// of bytes read.
+    pub fn read_row<D: DelayUs<u16> + DelayMs<u8>>(
+        &mut self,
+        row: u8,
+        buffer: &mut [u8],
+        delay: &mut D,
+    ) -> Result<usize> {
+        let len = buffer.len().min(self.geometry.columns as usize);
+        let address = self.geometry.address((0, row));
+
+        self.read_ram(0b1000_0000 | address, &mut buffer[..len], delay)?;
+
+        Ok(len)
+    }
+
+    /// Read back the custom character in one of the eight CGRAM slots, see
+    /// [set_custom_char](#method.set_custom_char)
+    pub fn read_custom_char<D: DelayUs<u16> + DelayMs<u8>>(
+        &mut self,
+        slot: u8,
+        delay: &mut D,
+    ) -> Result<[u8; 8]> {
+        if slot > 7 {
+            return Err(Error::InvalidArgument);
+        }
+
+        let mut bitmap = [0; 8];
+
+        self.read_ram(0b0100_0000 | (slot << 3), &mut bitmap, delay)?;
+
+        for row in bitmap.iter_mut() {
+            *row &= 0b0001_1111;
+        }
+
+        Ok(bitmap)
+    }
+
+    /// Read the text and glyphs stored in the display and the cursor
+    /// position, replacing what the driver remembers of them. This picks up
+    /// a display that was written to before the driver was created, for
+    /// example by the firmware before a restart.
+    pub fn reload<D: DelayUs<u16> + DelayMs<u8>>(&mut self, delay: &mut D) -> Result<()> {
+        let address = self.bus.read(false, delay)? & 0b0111_1111;
+        self.counter.set(address);
+
+        let mut shadow = self.shadow;
+
+        for slot in 0..8 {
+            self.read_ram(
+                0b0100_0000 | (slot << 3),
+                &mut shadow.cgram[slot as usize],
+                delay,
+            )?;
+        }
+
+        for line in [0x00, 0x40] {
+            self.read_ram(0b1000_0000 | line, shadow.line_mut(line), delay)?;
+        }
+
+        self.shadow = shadow;
+
+        Ok(())
+    }
+
+    /// Read back everything [write_str](#method.write_str) and
+    /// [write_bytes](#method.write_bytes) write from now on, or stop doing so
+    /// with `None`. See [verify](verify/index.html).
+    pub fn set_verify(&mut self, policy: Option<VerifyPolicy>) {
+        self.verifier = policy.map(|policy| Verifier {
+            policy,
+            read: |bus: &mut B, data, delay: &mut DynDelay| bus.read(data, delay),
+        });
+    }
+
+    /// Poll the busy flag instead of waiting fixed times, or stop doing so
+    /// with `None`. See [busy](busy/index.html).
+    pub fn set_busy_polling(&mut self, policy: Option<BusyPolicy>) {
+        self.busy = policy.map(|policy| BusyPoller {
+            policy,
+            read: |bus: &mut B, data, delay: &mut DynDelay| bus.read(data, delay),
+        });
+    }
+
+    /// Find out how the display memory is organized, and check that bytes
+    /// written to it read back unchanged. A garbled read back, for example
+    /// from a four bit interface that is out of step, gives
+    /// [Error::Mismatch](error/enum.Error.html#variant.Mismatch).
+    ///
+    /// The text is restored afterwards. See [detect](detect/index.html) for
+    /// what can and can't be detected.
+    ///
+    /// ```rust,ignore
+    /// let detection = lcd.detect(&mut delay)?;
+    /// lcd.set_geometry(detection.geometry());
+    /// ```
+    pub fn detect<D: DelayUs<u16> + DelayMs<u8>>(&mut self, delay: &mut D) -> Result<Detection> {
+        let mut saved = [0; PROBED_BYTES];
+        self.read_ram(0b1000_0000, &mut saved, delay)?;
+
+        let detection = self.probe(delay);
+
+        // Put the text back even if probing failed
+        self.write_command(0b0000_0110, delay)?;
+        self.send_command(0b1000_0000, delay)?;
+
+        for &byte in saved.iter() {
+            self.write_data(byte, delay)?;
+        }
+
+        self.write_command(self.entry_mode.as_byte(), delay)?;
+        self.send_command(0b1000_0000 | self.counter.address, delay)?;
+
+        detection
+    }
+
+    fn probe<D: DelayUs<u16> + DelayMs<u8>>(&mut self, delay: &mut D) -> Result<Detection> {
+        // Distinct nibbles, so swapped or shifted ones show up
+        for &pattern in [0b1010_0101, 0b0101_1010].iter() {
+            self.poke(0x00, pattern, delay)?;
+
+            if self.peek(0x00, delay)? != pattern {
+                return Err(Error::Mismatch);
+            }
+        }
+
+        // In two line mode the address counter skips to the second line
+        self.poke(0x27, b' ', delay)?;
+        let two_line = self.bus.read(false, delay)? & 0b0111_1111 == 0x40;
+
+        let mut detection = Detection {
+            two_line,
+            line_length: if two_line { 40 } else { 80 },
+        };
+
+        self.poke(0x00, 0xFF, delay)?;
+
+        for &length in PROBE_LENGTHS.iter() {
+            // 0x28 isn't a valid address in two line mode
+            if two_line && length == 40 {
+                break;
+            }
+
+            self.poke(length, length, delay)?;
+
+            if self.peek(0x00, delay)? == length {
+                detection.line_length = length;
+                break;
+            }
+        }
+
+        Ok(detection)
+    }
+
+    /// Write a byte to DDRAM without the driver following it
+    fn poke<D: DelayUs<u16> + DelayMs<u8>>(
+        &mut self,
+        address: u8,
+        byte: u8,
+        delay: &mut D,
+    ) -> Result<()> {
+        self.write_command(0b0000_0110, delay)?;
+        self.send_command(0b1000_0000 | address, delay)?;
+        self.cache.forget_address();
+
+        self.write_data(byte, delay)
+    }
+
+    fn peek<D: DelayUs<u16> + DelayMs<u8>>(&mut self, address: u8, delay: &mut D) -> Result<u8> {
+        let mut byte = [0];
+        self.read_ram(0b1000_0000 | address, &mut byte, delay)?;
+
+        Ok(byte[0])
+    }
+
+    /// Read consecutive bytes from DDRAM or CGRAM, starting at the address
+    /// set by `cmd`, then move the cursor back
+    fn read_ram<D: DelayUs<u16> + DelayMs<u8>>(
+        &mut self,
+        cmd: u8,
+        buffer: &mut [u8],
+        delay: &mut D,
+    ) -> Result<()> {
+        let entry_mode = self.entry_mode.as_byte();
+
+        // Read moving right, reading never shifts the display
+        self.write_command(0b0000_0110, delay)?;
+
+        // Reading only works right after setting the address
+        self.send_command(cmd, delay)?;
+        self.cache.forget_address();
+
+        for byte in buffer.iter_mut() {
+            *byte = self.bus.read(true, delay)?;
+
+            // Wait for the read to be processed, as long as for a write
+            self.wait(*byte, true, delay)?;
+        }
+
+        self.write_command(entry_mode, delay)?;
+        self.send_command(0b1000_0000 | self.counter.address, delay)
     }
 }
 
@@ -185,6 +684,7 @@ where
     /// ```
     pub fn reset<D: DelayUs<u16> + DelayMs<u8>>(&mut self, delay: &mut D) -> Result<()> {
         self.write_command(0b0000_0010, delay)?;
+        self.counter.home();
 
         Ok(())
     }
@@ -208,6 +708,105 @@ where
         Ok(())
     }
 
+    /// Turn the backlight on or off. Only the `I2CBus` controls a backlight,
+    /// on other buses this does nothing.
+    ///
+    /// ```rust,ignore
+    /// lcd.set_backlight(false, &mut delay)?;
+    /// ```
+    pub fn set_backlight<D: DelayUs<u16> + DelayMs<u8>>(
+        &mut self,
+        on: bool,
+        delay: &mut D,
+    ) -> Result<()> {
+        self.bus.set_backlight(on);
+
+        // The backlight is only updated along with a write
+        let cmd_byte = self.display_mode.as_byte();
+
+        self.send_command(cmd_byte, delay)
+    }
+
+    /// Send the function set, display mode, entry mode and cursor position
+    /// again. Instructions that wouldn't change anything are normally
+    /// skipped, so use this if the `HD44780` may have lost its state, for
+    /// example after a glitch on its supply or bus.
+    ///
+    /// ```rust,ignore
+    /// lcd.resend_state(&mut delay)?;
+    /// ```
+    pub fn resend_state<D: DelayUs<u16> + DelayMs<u8>>(&mut self, delay: &mut D) -> Result<()> {
+        self.cache.invalidate();
+
+        self.send_command(self.function_set, delay)?;
+        self.send_command(self.display_mode.as_byte(), delay)?;
+        self.send_command(self.entry_mode.as_byte(), delay)?;
+        self.send_command(0b1000_0000 | self.counter.address, delay)
+    }
+
+    /// Run the initialization sequence again, then restore the glyphs, the
+    /// text, the display and entry modes, the display shift and the cursor.
+    /// Use this when the display may have been scrambled or blanked, for
+    /// example by electrical noise.
+    ///
+    /// The driver keeps a copy of everything written to the display for this,
+    /// and rewrites all of it, which takes a while.
+    ///
+    /// ```rust,ignore
+    /// lcd.reinit(&mut delay)?;
+    /// ```
+    pub fn reinit<D: DelayUs<u16> + DelayMs<u8>>(&mut self, delay: &mut D) -> Result<()> {
+        self.cache.invalidate();
+
+        if self.function_set & 0b0001_0000 != 0 {
+            self.init_8bit(delay)?;
+        } else {
+            self.init_4bit(delay)?;
+        }
+
+        // Restore memory moving right without shifting the display
+        self.send_command(0b0000_0110, delay)?;
+
+        let shadow = self.shadow;
+
+        self.send_command(0b0100_0000, delay)?;
+        for &row in shadow.cgram.iter().flatten() {
+            self.write_data(row, delay)?;
+        }
+
+        for line in [0x00, 0x40] {
+            self.send_command(0b1000_0000 | line, delay)?;
+
+            for &byte in shadow.line(line) {
+                self.write_data(byte, delay)?;
+            }
+        }
+
+        self.send_command(self.display_mode.as_byte(), delay)?;
+        self.send_command(self.entry_mode.as_byte(), delay)?;
+
+        for _ in 0..self.counter.shift {
+            self.send_command(0b0001_1000, delay)?;
+        }
+
+        self.send_command(0b1000_0000 | self.counter.address, delay)
+    }
+
+    /// Re-initialize the display if `schedule` says it is due at `now`,
+    /// see the [reinit](reinit/index.html) module
+    pub fn reinit_if_due<D: DelayUs<u16> + DelayMs<u8>>(
+        &mut self,
+        schedule: &mut ReinitSchedule,
+        now: u32,
+        delay: &mut D,
+    ) -> Result<()> {
+        if schedule.is_due(now) {
+            self.reinit(delay)?;
+        }
+
+        Ok(())
+    }
+
     /// Clear the entire display
     ///
     /// ```rust,ignore
@@ -216,6 +815,11 @@ where
     pub fn clear<D: DelayUs<u16> + DelayMs<u8>>(&mut self, delay: &mut D) -> Result<()> {
         self.write_command(0b0000_0001, delay)?;
 
+        // Clearing also makes the cursor move right
+        self.counter.home();
+        self.shadow.clear();
+        self.entry_mode.cursor_mode = CursorMode::Increment;
+
         Ok(())
     }
 
@@ -321,10 +925,86 @@ where
         let lower_7_bits = 0b0111_1111 & position;
 
         self.write_command(0b1000_0000 | lower_7_bits, delay)?;
+        self.counter.set(lower_7_bits);
 
         Ok(())
     }
 
+    /// Set the cursor position using a column and a row, counted from the
+    /// top-left corner of the display. The position is translated using the
+    /// display [geometry](#method.set_geometry).
+    ///
+    /// ```rust,ignore
+    /// // Move to the 3rd column of the second row
+    /// lcd.set_cursor_xy((2, 1), &mut delay)
+    /// ```
+    pub fn set_cursor_xy<D: DelayUs<u16> + DelayMs<u8>>(
+        &mut self,
+        position: (u8, u8),
+        delay: &mut D,
+    ) -> Result<()> {
+        self.set_cursor_pos(self.geometry.address(position), delay)
+    }
+
+    /// Set the fixed times waited after instructions and data writes, see
+    /// [Timing](timing/struct.Timing.html)
+    pub fn set_timing(&mut self, timing: Timing) {
+        self.timing = timing;
+    }
+
+    /// Whether the busy flag is polled, which stops after a timeout if
+    /// [set_busy_polling](#method.set_busy_polling) was told to fall back
+    pub fn is_busy_polling(&self) -> bool {
+        self.busy.is_some()
+    }
+
+    /// Set the number of columns and rows of the display. This doesn't send
+    /// anything to the `HD44780`, it only changes how positions are translated
+    /// to addresses. Defaults to 20x4, which also fits 16x2 and 20x2 displays.
+    ///
+    /// ```rust,ignore
+    /// lcd.set_geometry(Geometry::new(16, 4));
+    /// ```
+    pub fn set_geometry(&mut self, geometry: Geometry) {
+        self.geometry = geometry;
+    }
+
+    /// The number of columns and rows of the display
+    pub fn geometry(&self) -> Geometry {
+        self.geometry
+    }
+
+    /// The DDRAM address the next byte will be written to. The driver keeps
+    /// track of it as it sends instructions and bytes, without reading it
+    /// back from the display.
+    pub fn cursor_pos(&self) -> u8 {
+        self.counter.address
+    }
+
+    /// The (column, row) position of the cursor, as given to
+    /// [set_cursor_xy](#method.set_cursor_xy), or `None` if the cursor is
+    /// outside the display [geometry](#method.set_geometry). This ignores
+    /// any display shift.
+    pub fn cursor_xy(&self) -> Option<(u8, u8)> {
+        self.geometry.position(self.counter.address)
+    }
+
+    /// How many positions the display is shifted to the left, from 0 to 39.
+    /// Shifting right from 0 gives 39.
+    pub fn display_shift(&self) -> u8 {
+        self.counter.shift
+    }
+
+    /// Give back the bus, leaving the display as it is. The pins or I2C
+    /// peripheral can then be taken back from the bus.
+    ///
+    /// ```rust,ignore
+    /// let (rs, en, d4, d5, d6, d7) = lcd.release().release();
+    /// ```
+    pub fn release(self) -> B {
+        self.bus
+    }
+
     /// Shift just the cursor to the left or the right
     ///
     /// ```rust,ignore
@@ -342,6 +1022,7 @@ where
         };
 
         self.write_command(0b0001_0000 | bits | bits, delay)?;
+        self.counter.step(matches!(dir, Direction::Right));
 
         Ok(())
     }
@@ -363,6 +1044,7 @@ where
         };
 
         self.write_command(0b0001_1000 | bits, delay)?;
+        self.counter.shift_display(matches!(dir, Direction::Left));
 
         Ok(())
     }
@@ -383,16 +1065,75 @@ where
         self.write_byte(data as u8, delay)
     }
 
+    /// Define a custom character in one of the eight CGRAM slots. Each entry
+    /// of `bitmap` is one row of the 5x8 glyph, from top to bottom, using the
+    /// lower 5 bits with bit 4 being the leftmost pixel.
+    ///
+    /// The glyph is displayed by writing the slot number as a byte. The
+    /// cursor is moved back to where it was afterwards.
+    ///
+    /// ```rust,ignore
+    /// lcd.set_custom_char(0, &[0x00, 0x0A, 0x0A, 0x00, 0x11, 0x0E, 0x00, 0x00], &mut delay)?;
+    /// lcd.write_byte(0, &mut delay)?; // prints a smiley
+    /// ```
+    pub fn set_custom_char<D: DelayUs<u16> + DelayMs<u8>>(
+        &mut self,
+        slot: u8,
+        bitmap: &[u8; 8],
+        delay: &mut D,
+    ) -> Result<()> {
+        if slot > 7 {
+            return Err(Error::InvalidArgument);
+        }
+
+        // Fill the glyph in the direction the address counter moves
+        match self.entry_mode.cursor_mode {
+            CursorMode::Increment => {
+                self.write_command(0b0100_0000 | (slot << 3), delay)?;
+
+                for &row in bitmap {
+                    self.write_data(row & 0b0001_1111, delay)?;
+                }
+            }
+            CursorMode::Decrement => {
+                self.write_command(0b0100_0000 | (slot << 3) | 0b111, delay)?;
+
+                for &row in bitmap.iter().rev() {
+                    self.write_data(row & 0b0001_1111, delay)?;
+                }
+            }
+        }
+
+        for (shadow, &row) in self.shadow.cgram[slot as usize].iter_mut().zip(bitmap) {
+            *shadow = row & 0b0001_1111;
+        }
+
+        self.set_cursor_pos(self.counter.address, delay)
+    }
+
+    /// Send an instruction, unless it wouldn't change anything
     fn write_command<D: DelayUs<u16> + DelayMs<u8>>(
         &mut self,
         cmd: u8,
         delay: &mut D,
+    ) -> Result<()> {
+        if self.cache.is_redundant(cmd, self.counter.address) {
+            return Ok(());
+        }
+
+        self.send_command(cmd, delay)
+    }
+
+    /// Send an instruction, even if it was just sent
+    fn send_command<D: DelayUs<u16> + DelayMs<u8>>(
+        &mut self,
+        cmd: u8,
+        delay: &mut D,
     ) -> Result<()> {
         self.bus.write(cmd, false, delay)?;
+        self.cache.record(cmd);
 
-        // Wait for the command to be processed
-        delay.delay_us(100);
-        Ok(())
+        self.wait(cmd, false, delay)
     }
 
     fn init_4bit<D: DelayUs<u16> + DelayMs<u8>>(&mut self, delay: &mut D) -> Result<()> {
@@ -409,35 +1150,35 @@ where
         self.bus.write(0x32, false, delay)?;
 
         // Wait for the command to be processed
-        delay.delay_us(100);
+        delay.delay_us(self.timing.command_us);
 
-        self.bus.write(0x28, false, delay)?;
+        self.bus.write(self.function_set, false, delay)?;
 
         // Wait for the command to be processed
-        delay.delay_us(100);
+        delay.delay_us(self.timing.command_us);
 
         // Clear Display
         self.bus.write(0x0E, false, delay)?;
 
         // Wait for the command to be processed
-        delay.delay_us(100);
+        delay.delay_us(self.timing.command_us);
 
         // Move the cursor to beginning of first line
         self.bus.write(0x01, false, delay)?;
 
         // Wait for the command to be processed
-        delay.delay_us(100);
+        delay.delay_us(self.timing.long_command_us);
 
         // Set entry mode
         self.bus.write(self.entry_mode.as_byte(), false, delay)?;
 
         // Wait for the command to be processed
-        delay.delay_us(100);
+        delay.delay_us(self.timing.command_us);
 
         self.bus.write(0x80, false, delay)?;
 
         // Wait for the command to be processed
-        delay.delay_us(100);
+        delay.delay_us(self.timing.command_us);
 
         Ok(())
     }
@@ -454,33 +1195,33 @@ where
         delay.delay_ms(5u8);
 
         // Sets 8-bit operation and enables 5x7 mode for chars
-        self.bus.write(0b0011_1000, false, delay)?;
+        self.bus.write(self.function_set, false, delay)?;
 
         // Wait for the command to be processed
-        delay.delay_us(100);
+        delay.delay_us(self.timing.command_us);
 
         self.bus.write(0b0000_1110, false, delay)?;
 
         // Wait for the command to be processed
-        delay.delay_us(100);
+        delay.delay_us(self.timing.command_us);
 
         // Clear Display
         self.bus.write(0b0000_0001, false, delay)?;
 
         // Wait for the command to be processed
-        delay.delay_us(100);
+        delay.delay_us(self.timing.long_command_us);
 
         // Move the cursor to beginning of first line
         self.bus.write(0b000_0111, false, delay)?;
 
         // Wait for the command to be processed
-        delay.delay_us(100);
+        delay.delay_us(self.timing.command_us);
 
         // Set entry mode
         self.bus.write(self.entry_mode.as_byte(), false, delay)?;
 
         // Wait for the command to be processed
-        delay.delay_us(100);
+        delay.delay_us(self.timing.command_us);
 
         Ok(())
     }
@@ -511,10 +1252,73 @@ where
         string: &[u8],
         delay: &mut D,
     ) -> Result<()> {
+        let start = self.counter;
+
         for &b in string {
             self.write_byte(b, delay)?;
         }
-        Ok(())
+
+        match self.verifier {
+            Some(Verifier { policy, read }) => {
+                self.verify(start, string.len(), policy, read, &mut DynDelay(delay))
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Check the `len` bytes written starting from `start` against the
+    /// shadow copy, writing them again or re-initializing as `policy` allows
+    fn verify(
+        &mut self,
+        start: AddressCounter,
+        len: usize,
+        policy: VerifyPolicy,
+        read: ReadFn<B>,
+        delay: &mut DynDelay,
+    ) -> Result<()> {
+        let mut retries = policy.retries;
+        let mut reinit = policy.reinit;
+
+        loop {
+            let mut matches = true;
+            let mut counter = start;
+
+            // Read moving right, so reading never shifts the display
+            self.write_command(0b0000_0110, delay)?;
+
+            for _ in 0..len {
+                self.send_command(0b1000_0000 | counter.address, delay)?;
+                self.cache.forget_address();
+
+                let byte = read(&mut self.bus, true, delay)?;
+                matches &= byte == self.shadow.get(counter.address);
+                self.wait(byte, true, delay)?;
+
+                counter.write(&self.entry_mode);
+            }
+
+            if matches {
+                break;
+            } else if retries > 0 {
+                retries -= 1;
+                counter = start;
+
+                for _ in 0..len {
+                    self.send_command(0b1000_0000 | counter.address, delay)?;
+                    self.write_data(self.shadow.get(counter.address), delay)?;
+
+                    counter.write(&self.entry_mode);
+                }
+            } else if reinit {
+                reinit = false;
+                self.reinit(delay)?;
+            } else {
+                return Err(Error::Mismatch);
+            }
+        }
+
+        self.write_command(self.entry_mode.as_byte(), delay)?;
+        self.send_command(0b1000_0000 | self.counter.address, delay)
     }
 
     /// Writes a single byte to the HD44780. These usually map to ASCII characters when printed on the
@@ -536,10 +1340,70 @@ where
         data: u8,
         delay: &mut D,
     ) -> Result<()> {
+        self.write_data(data, delay)?;
+
+        self.shadow.write(self.counter.address, data);
+        self.counter.write(&self.entry_mode);
+
+        Ok(())
+    }
+
+    /// Send a byte to whichever of DDRAM or CGRAM is being addressed,
+    /// without following it in the models of the display
+    fn write_data<D: DelayUs<u16> + DelayMs<u8>>(&mut self, data: u8, delay: &mut D) -> Result<()> {
         self.bus.write(data, true, delay)?;
 
-        // Wait for the command to be processed
-        delay.delay_us(100);
+        self.wait(data, true, delay)
+    }
+
+    /// Wait for the display to process `byte`, written as data or as an
+    /// instruction, by polling the busy flag or for a fixed time
+    fn wait<D: DelayUs<u16> + DelayMs<u8>>(
+        &mut self,
+        byte: u8,
+        data: bool,
+        delay: &mut D,
+    ) -> Result<()> {
+        if let Some(BusyPoller { policy, read }) = self.busy {
+            let mut delay = DynDelay(delay);
+            let mut waited = 0;
+            // Waiting no time at all between reads would never time out
+            let interval_us = policy.interval_us.max(1);
+
+            loop {
+                let busy = match read(&mut self.bus, false, &mut delay) {
+                    Ok(status) => status & 0b1000_0000 != 0,
+                    Err(error) if !policy.fallback => return Err(error),
+                    // A flag that can't be read is as good as a stuck one
+                    Err(_) => {
+                        waited = policy.timeout_us;
+                        true
+                    }
+                };
+
+                if !busy {
+                    break;
+                }
+
+                if waited >= policy.timeout_us {
+                    if !policy.fallback {
+                        return Err(Error::Timeout);
+                    }
+
+                    // Don't trust the busy flag again
+                    self.busy = None;
+                    delay.delay_us(self.timing.execution_time_us(byte, data));
+                    break;
+                }
+
+                delay.delay_us(interval_us);
+                waited += interval_us as u32;
+            }
+
+            return Ok(());
+        }
+
+        delay.delay_us(self.timing.execution_time_us(byte, data));
 
         Ok(())
     }
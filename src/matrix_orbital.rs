@@ -0,0 +1,345 @@
+//! The Matrix Orbital serial command set
+//!
+//! Host software such as LCDproc and lcd4linux drive serial and USB character
+//! displays with the commands of the Matrix Orbital LK series: text bytes are
+//! shown as they are, and commands start with `0xFE`. An [`Interpreter`]
+//! carries these out on an [`HD44780`], so a board forwarding its serial port
+//! to it works with that software.
+//!
+//! | Command            | Arguments              | Effect                       |
+//! |--------------------|------------------------|------------------------------|
+//! | `0x58`             |                        | Clear the display            |
+//! | `0x48`             |                        | Move to the top left corner  |
+//! | `0x47`             | column, row, from 1    | Move the cursor              |
+//! | `0x4C`, `0x4D`     |                        | Move the cursor left, right  |
+//! | `0x4A`, `0x4B`     |                        | Show, hide the cursor        |
+//! | `0x53`, `0x54`     |                        | Start, stop blinking         |
+//! | `0x43`, `0x44`     |                        | Wrap lines on, off           |
+//! | `0x4E`             | slot, 8 rows           | Define a custom character    |
+//! | `0x42`             | minutes                | Backlight on                 |
+//! | `0x46`             |                        | Backlight off                |
+//! | `0x50`, `0x91`     | contrast               | Set the contrast             |
+//! | `0x99`, `0x98`     | brightness             | Set the backlight brightness |
+//! | `0x57`, `0x56`     | output                 | Switch an output on, off     |
+//! | `0x36`, `0x37`     |                        | Read the version, the type   |
+//!
+//! Contrast, brightness, outputs and reads need hardware or answers the
+//! driver doesn't have, so they are returned as [`Event`]s for the firmware
+//! to handle. The backlight is switched through
+//! [set_backlight](../struct.HD44780.html#method.set_backlight), and also
+//! returned, for backlights not controlled by the bus. Other commands are
+//! skipped, along with their arguments if they take any of the listed
+//! numbers. Automatic scrolling isn't supported.
+//!
+//! ```rust,ignore
+//! let mut interpreter = Interpreter::new();
+//!
+//! loop {
+//!     let byte = block!(serial.read())?;
+//!
+//!     match interpreter.feed(&mut lcd, byte, &mut delay)? {
+//!         Some(Event::Contrast(contrast)) => contrast_pwm.set_duty(contrast),
+//!         Some(Event::ReadVersion) => block!(serial.write(0x10))?,
+//!         _ => {}
+//!     }
+//! }
+//! ```
+
+use embedded_hal::blocking::delay::{DelayMs, DelayUs};
+
+use crate::bus::DataBus;
+use crate::error::Result;
+use crate::{Cursor, CursorBlink, HD44780};
+
+/// Starts every command
+pub const PREFIX: u8 = 0xFE;
+
+const CLEAR: u8 = 0x58;
+const HOME: u8 = 0x48;
+const GOTO: u8 = 0x47;
+const LEFT: u8 = 0x4C;
+const RIGHT: u8 = 0x4D;
+const CURSOR_ON: u8 = 0x4A;
+const CURSOR_OFF: u8 = 0x4B;
+const BLINK_ON: u8 = 0x53;
+const BLINK_OFF: u8 = 0x54;
+const WRAP_ON: u8 = 0x43;
+const WRAP_OFF: u8 = 0x44;
+const CUSTOM_CHAR: u8 = 0x4E;
+const BACKLIGHT_ON: u8 = 0x42;
+const BACKLIGHT_OFF: u8 = 0x46;
+const CONTRAST: u8 = 0x50;
+const SAVE_CONTRAST: u8 = 0x91;
+const BRIGHTNESS: u8 = 0x99;
+const SAVE_BRIGHTNESS: u8 = 0x98;
+const OUTPUT_ON: u8 = 0x57;
+const OUTPUT_OFF: u8 = 0x56;
+const READ_VERSION: u8 = 0x36;
+const READ_TYPE: u8 = 0x37;
+
+/// Most arguments a command takes, those of `CUSTOM_CHAR`
+const MAX_ARGS: usize = 9;
+
+/// A command the firmware has to carry out itself
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    Backlight(bool),
+    Contrast(u8),
+    Brightness(u8),
+    /// A general purpose output, numbered from 1, switched on or off
+    Output(u8, bool),
+    /// The host waits for a version byte
+    ReadVersion,
+    /// The host waits for a module type byte
+    ReadModuleType,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Text,
+    /// After the prefix, waiting for the command
+    Command,
+    /// Collecting the arguments of a command
+    Arguments(u8),
+}
+
+/// Carries out the command set on a display, see the
+/// [module documentation](index.html)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interpreter {
+    state: State,
+    args: [u8; MAX_ARGS],
+    len: usize,
+    /// Where the next character goes, as (column, row)
+    cursor: (u8, u8),
+    wrap: bool,
+}
+
+impl Default for Interpreter {
+    fn default() -> Interpreter {
+        Interpreter {
+            state: State::Text,
+            args: [0; MAX_ARGS],
+            len: 0,
+            cursor: (0, 0),
+            wrap: true,
+        }
+    }
+}
+
+impl Interpreter {
+    /// Create an interpreter for a display that was just cleared
+    pub fn new() -> Interpreter {
+        Interpreter::default()
+    }
+
+    /// Handle the next byte from the host
+    pub fn feed<B: DataBus, D: DelayUs<u16> + DelayMs<u8>>(
+        &mut self,
+        lcd: &mut HD44780<B>,
+        byte: u8,
+        delay: &mut D,
+    ) -> Result<Option<Event>> {
+        match self.state {
+            State::Text if byte == PREFIX => {
+                self.state = State::Command;
+                Ok(None)
+            }
+            State::Text => {
+                self.write(lcd, byte, delay)?;
+                Ok(None)
+            }
+            State::Command => {
+                self.len = 0;
+                self.state = State::Arguments(byte);
+                self.finish(lcd, byte, delay)
+            }
+            State::Arguments(command) => {
+                self.args[self.len] = byte;
+                self.len += 1;
+                self.finish(lcd, command, delay)
+            }
+        }
+    }
+
+    /// Handle bytes from the host, passing the events to `on_event`
+    pub fn feed_bytes<B: DataBus, D: DelayUs<u16> + DelayMs<u8>>(
+        &mut self,
+        lcd: &mut HD44780<B>,
+        bytes: &[u8],
+        delay: &mut D,
+        mut on_event: impl FnMut(Event),
+    ) -> Result<()> {
+        for &byte in bytes {
+            if let Some(event) = self.feed(lcd, byte, delay)? {
+                on_event(event);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Run `command` if all its arguments have arrived
+    fn finish<B: DataBus, D: DelayUs<u16> + DelayMs<u8>>(
+        &mut self,
+        lcd: &mut HD44780<B>,
+        command: u8,
+        delay: &mut D,
+    ) -> Result<Option<Event>> {
+        if self.len < arguments(command) {
+            return Ok(None);
+        }
+
+        self.state = State::Text;
+
+        let args = self.args;
+        let (column, row) = self.cursor;
+
+        match command {
+            CLEAR => {
+                lcd.clear(delay)?;
+                self.cursor = (0, 0);
+            }
+            HOME => self.move_to(lcd, (0, 0), delay)?,
+            GOTO => self.move_to(
+                lcd,
+                (args[0].saturating_sub(1), args[1].saturating_sub(1)),
+                delay,
+            )?,
+            LEFT => self.move_to(lcd, (column.saturating_sub(1), row), delay)?,
+            RIGHT => self.move_to(lcd, (column.saturating_add(1), row), delay)?,
+            CURSOR_ON => lcd.set_cursor_visibility(Cursor::Visible, delay)?,
+            CURSOR_OFF => lcd.set_cursor_visibility(Cursor::Invisible, delay)?,
+            BLINK_ON => lcd.set_cursor_blink(CursorBlink::On, delay)?,
+            BLINK_OFF => lcd.set_cursor_blink(CursorBlink::Off, delay)?,
+            WRAP_ON => self.wrap = true,
+            WRAP_OFF => self.wrap = false,
+            CUSTOM_CHAR => {
+                let mut bitmap = [0; 8];
+                bitmap.copy_from_slice(&args[1..9]);
+
+                // Slots above 7 are mirrors of the eight real ones
+                lcd.set_custom_char(args[0] & 0b111, &bitmap, delay)?;
+            }
+            BACKLIGHT_ON | BACKLIGHT_OFF => {
+                let on = command == BACKLIGHT_ON;
+
+                lcd.set_backlight(on, delay)?;
+                return Ok(Some(Event::Backlight(on)));
+            }
+            CONTRAST | SAVE_CONTRAST => return Ok(Some(Event::Contrast(args[0]))),
+            BRIGHTNESS | SAVE_BRIGHTNESS => return Ok(Some(Event::Brightness(args[0]))),
+            OUTPUT_ON => return Ok(Some(Event::Output(args[0], true))),
+            OUTPUT_OFF => return Ok(Some(Event::Output(args[0], false))),
+            READ_VERSION => return Ok(Some(Event::ReadVersion)),
+            READ_TYPE => return Ok(Some(Event::ReadModuleType)),
+            _ => {}
+        }
+
+        Ok(None)
+    }
+
+    fn write<B: DataBus, D: DelayUs<u16> + DelayMs<u8>>(
+        &mut self,
+        lcd: &mut HD44780<B>,
+        byte: u8,
+        delay: &mut D,
+    ) -> Result<()> {
+        let geometry = lcd.geometry();
+        let (column, row) = self.cursor;
+
+        if column >= geometry.columns {
+            if !self.wrap {
+                return Ok(());
+            }
+
+            self.move_to(lcd, (0, (row + 1) % geometry.rows.max(1)), delay)?;
+        }
+
+        lcd.write_byte(byte, delay)?;
+        self.cursor.0 += 1;
+
+        Ok(())
+    }
+
+    fn move_to<B: DataBus, D: DelayUs<u16> + DelayMs<u8>>(
+        &mut self,
+        lcd: &mut HD44780<B>,
+        position: (u8, u8),
+        delay: &mut D,
+    ) -> Result<()> {
+        let geometry = lcd.geometry();
+        let (column, row) = position;
+
+        self.cursor = (
+            column.min(geometry.columns.saturating_sub(1)),
+            row.min(geometry.rows.saturating_sub(1)),
+        );
+
+        lcd.set_cursor_xy(self.cursor, delay)
+    }
+}
+
+/// Number of argument bytes taken by `command`
+fn arguments(command: u8) -> usize {
+    match command {
+        CUSTOM_CHAR => 9,
+        GOTO => 2,
+        BACKLIGHT_ON | CONTRAST | SAVE_CONTRAST | BRIGHTNESS | SAVE_BRIGHTNESS | OUTPUT_ON
+        | OUTPUT_OFF => 1,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::mock::{NoDelay, Recorder};
+
+    #[test]
+    fn moves_and_writes() {
+        let mut lcd = HD44780::from_bus(Recorder::default(), 0b0011_1000);
+        let mut interpreter = Interpreter::new();
+
+        interpreter
+            .feed_bytes(&mut lcd, &[PREFIX, GOTO, 4, 2, b'a'], &mut NoDelay, |_| {})
+            .unwrap();
+
+        let bus = lcd.release();
+
+        assert_eq!(
+            bus.written[..bus.len],
+            [(0b1000_0000 | 0x43, false), (b'a', true)]
+        );
+    }
+
+    #[test]
+    fn returns_events() {
+        let mut lcd = HD44780::from_bus(Recorder::default(), 0b0011_1000);
+        let mut interpreter = Interpreter::new();
+        let mut events = [None; 4];
+        let mut len = 0;
+
+        let bytes = [
+            PREFIX, CONTRAST, 200, PREFIX, 0x68, PREFIX, OUTPUT_ON, 1, PREFIX, READ_TYPE,
+        ];
+
+        interpreter
+            .feed_bytes(&mut lcd, &bytes, &mut NoDelay, |event| {
+                events[len] = Some(event);
+                len += 1;
+            })
+            .unwrap();
+
+        assert_eq!(
+            events[..len],
+            [
+                Some(Event::Contrast(200)),
+                Some(Event::Output(1, true)),
+                Some(Event::ReadModuleType),
+            ]
+        );
+        assert_eq!(lcd.release().len, 0);
+    }
+}
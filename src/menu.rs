@@ -0,0 +1,624 @@
+//! Hierarchical menus driven by buttons or a rotary encoder
+//!
+//! A menu is a tree of [`Item`]s borrowed by a [`Menu`], which keeps track of
+//! the selection, scrolls lists longer than the display, and edits numeric and
+//! enumerated values in place. [`Menu::render`] only rewrites the cells that
+//! changed since the previous call.
+//!
+//! Values are stored in [`Cell`]s owned by the application, so they can be
+//! read at any time and are updated as they are edited.
+//!
+//! ```rust,ignore
+//! let volume = Cell::new(5);
+//! let mode = Cell::new(0);
+//!
+//! let settings = [
+//!     Item::Number { label: "Volume", id: 1, value: &volume, min: 0, max: 10, step: 1 },
+//!     Item::Choice { label: "Mode", id: 2, options: &["Auto", "Manual"], selected: &mode },
+//! ];
+//! let root = [
+//!     Item::Action { label: "Start", id: 0 },
+//!     Item::Submenu { label: "Settings", items: &settings },
+//! ];
+//!
+//! let mut menu = Menu::new(&root);
+//! let mut buttons = Buttons::new(up, down, select, back);
+//!
+//! loop {
+//!     if let Some(input) = buttons.poll() {
+//!         match menu.handle(input) {
+//!             Some(Event::Action(0)) => start(),
+//!             Some(Event::Changed(1)) => set_volume(volume.get()),
+//!             _ => {}
+//!         }
+//!     }
+//!     menu.render(&mut lcd, &mut delay)?;
+//! }
+//! ```
+
+use core::cell::Cell;
+
+use embedded_hal::blocking::delay::{DelayMs, DelayUs};
+use embedded_hal::digital::v2::InputPin;
+
+use crate::bus::DataBus;
+use crate::error::Result;
+use crate::HD44780;
+
+/// Deepest nesting of submenus
+const MAX_DEPTH: usize = 4;
+
+/// Largest display the menu can be drawn on
+const MAX_COLUMNS: usize = 40;
+const MAX_ROWS: usize = 4;
+
+/// Marks the selected item
+const INDICATOR: u8 = b'>';
+
+/// Marks the selected item while its value is edited
+const EDIT_INDICATOR: u8 = b'*';
+
+/// Shown after the label of a submenu, a right arrow in the common character ROMs
+const SUBMENU_MARKER: u8 = 0x7E;
+
+/// An entry of a menu
+pub enum Item<'a> {
+    /// Opens a nested list of items
+    Submenu {
+        label: &'a str,
+        items: &'a [Item<'a>],
+    },
+    /// Reports [`Event::Action`] when selected
+    Action { label: &'a str, id: u8 },
+    /// An integer edited in steps between `min` and `max`
+    Number {
+        label: &'a str,
+        id: u8,
+        value: &'a Cell<i32>,
+        min: i32,
+        max: i32,
+        step: i32,
+    },
+    /// One of a list of options, `selected` being the index of the current one
+    Choice {
+        label: &'a str,
+        id: u8,
+        options: &'a [&'a str],
+        selected: &'a Cell<usize>,
+    },
+}
+
+impl<'a> Item<'a> {
+    fn label(&self) -> &'a str {
+        match *self {
+            Item::Submenu { label, .. }
+            | Item::Action { label, .. }
+            | Item::Number { label, .. }
+            | Item::Choice { label, .. } => label,
+        }
+    }
+}
+
+/// A user action
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Input {
+    Up,
+    Down,
+    Select,
+    Back,
+}
+
+/// Something the application may want to react to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// An [`Item::Action`] was selected
+    Action(u8),
+    /// The value of an [`Item::Number`] or [`Item::Choice`] was confirmed
+    Changed(u8),
+    /// Back was pressed in the top level menu
+    Exit,
+}
+
+/// A source of user input, polled from the application's main loop
+pub trait InputSource {
+    fn poll(&mut self) -> Option<Input>;
+}
+
+/// Four push buttons, pulling their pins low when pressed. Each press is
+/// reported once.
+pub struct Buttons<UP: InputPin, DOWN: InputPin, SELECT: InputPin, BACK: InputPin> {
+    up: UP,
+    down: DOWN,
+    select: SELECT,
+    back: BACK,
+    pressed: [bool; 4],
+}
+
+impl<UP: InputPin, DOWN: InputPin, SELECT: InputPin, BACK: InputPin>
+    Buttons<UP, DOWN, SELECT, BACK>
+{
+    pub fn new(up: UP, down: DOWN, select: SELECT, back: BACK) -> Buttons<UP, DOWN, SELECT, BACK> {
+        Buttons {
+            up,
+            down,
+            select,
+            back,
+            pressed: [false; 4],
+        }
+    }
+}
+
+impl<UP: InputPin, DOWN: InputPin, SELECT: InputPin, BACK: InputPin> InputSource
+    for Buttons<UP, DOWN, SELECT, BACK>
+{
+    fn poll(&mut self) -> Option<Input> {
+        let now = [
+            self.up.is_low().unwrap_or(false),
+            self.down.is_low().unwrap_or(false),
+            self.select.is_low().unwrap_or(false),
+            self.back.is_low().unwrap_or(false),
+        ];
+        let inputs = [Input::Up, Input::Down, Input::Select, Input::Back];
+
+        let mut input = None;
+        for i in 0..4 {
+            if now[i] && !self.pressed[i] && input.is_none() {
+                input = Some(inputs[i]);
+            }
+        }
+
+        self.pressed = now;
+        input
+    }
+}
+
+/// Turns the position of a rotary encoder into [`Input::Up`] and
+/// [`Input::Down`] steps, one for every `detent` counts
+pub struct Encoder {
+    position: i32,
+    detent: i32,
+}
+
+impl Encoder {
+    pub fn new(position: i32, detent: i32) -> Encoder {
+        Encoder {
+            position,
+            detent: detent.max(1),
+        }
+    }
+
+    /// Feed the current encoder count, getting at most one step per call
+    pub fn update(&mut self, position: i32) -> Option<Input> {
+        let delta = position.wrapping_sub(self.position);
+
+        if delta >= self.detent {
+            self.position = self.position.wrapping_add(self.detent);
+            Some(Input::Down)
+        } else if delta <= -self.detent {
+            self.position = self.position.wrapping_sub(self.detent);
+            Some(Input::Up)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Level<'a> {
+    items: &'a [Item<'a>],
+    selected: usize,
+    top: usize,
+}
+
+/// The state of a menu and of what is shown on the display
+pub struct Menu<'a> {
+    levels: [Level<'a>; MAX_DEPTH],
+    depth: usize,
+    /// Value of the edited item before editing started, to restore on [`Input::Back`]
+    editing: Option<i32>,
+    shown: [[u8; MAX_COLUMNS]; MAX_ROWS],
+    valid: bool,
+}
+
+impl<'a> Menu<'a> {
+    pub fn new(root: &'a [Item<'a>]) -> Menu<'a> {
+        let level = Level {
+            items: root,
+            selected: 0,
+            top: 0,
+        };
+
+        Menu {
+            levels: [level; MAX_DEPTH],
+            depth: 1,
+            editing: None,
+            shown: [[b' '; MAX_COLUMNS]; MAX_ROWS],
+            valid: false,
+        }
+    }
+
+    /// The item that is currently selected
+    pub fn selected(&self) -> Option<&'a Item<'a>> {
+        let level = self.level();
+        level.items.get(level.selected)
+    }
+
+    /// Whether the selected item's value is being edited
+    pub fn is_editing(&self) -> bool {
+        self.editing.is_some()
+    }
+
+    /// Update the menu for an input
+    pub fn handle(&mut self, input: Input) -> Option<Event> {
+        match self.editing {
+            Some(original) => self.handle_edit(input, original),
+            None => self.handle_navigation(input),
+        }
+    }
+
+    /// Redraw everything on the next [`render`](#method.render), for example
+    /// after something else was written to the display
+    pub fn invalidate(&mut self) {
+        self.valid = false;
+    }
+
+    /// Draw the menu, only writing the cells that changed since the last call
+    pub fn render<B: DataBus, D: DelayUs<u16> + DelayMs<u8>>(
+        &mut self,
+        lcd: &mut HD44780<B>,
+        delay: &mut D,
+    ) -> Result<()> {
+        let geometry = lcd.geometry();
+        let columns = (geometry.columns as usize).min(MAX_COLUMNS);
+        let rows = (geometry.rows as usize).min(MAX_ROWS);
+
+        self.scroll(rows);
+
+        for row in 0..rows {
+            let mut line = [b' '; MAX_COLUMNS];
+            self.compose(row, &mut line[..columns]);
+
+            let old = &self.shown[row][..columns];
+            let new = &line[..columns];
+            let first = if self.valid {
+                new.iter().zip(old).position(|(a, b)| a != b)
+            } else {
+                Some(0)
+            };
+
+            if let Some(first) = first {
+                let last = if self.valid {
+                    columns
+                        - new
+                            .iter()
+                            .zip(old)
+                            .rev()
+                            .position(|(a, b)| a != b)
+                            .unwrap_or(0)
+                } else {
+                    columns
+                };
+
+                lcd.set_cursor_xy((first as u8, row as u8), delay)?;
+                lcd.write_bytes(&new[first..last], delay)?;
+                self.shown[row] = line;
+            }
+        }
+
+        self.valid = true;
+        Ok(())
+    }
+
+    fn level(&self) -> &Level<'a> {
+        &self.levels[self.depth - 1]
+    }
+
+    fn level_mut(&mut self) -> &mut Level<'a> {
+        &mut self.levels[self.depth - 1]
+    }
+
+    fn handle_navigation(&mut self, input: Input) -> Option<Event> {
+        let level = self.level_mut();
+
+        match input {
+            Input::Up => {
+                level.selected = level.selected.saturating_sub(1);
+                None
+            }
+            Input::Down => {
+                if level.selected + 1 < level.items.len() {
+                    level.selected += 1;
+                }
+                None
+            }
+            Input::Back => {
+                if self.depth > 1 {
+                    self.depth -= 1;
+                    None
+                } else {
+                    Some(Event::Exit)
+                }
+            }
+            Input::Select => match self.selected()? {
+                Item::Submenu { items, .. } => {
+                    if self.depth < MAX_DEPTH {
+                        self.levels[self.depth] = Level {
+                            items,
+                            selected: 0,
+                            top: 0,
+                        };
+                        self.depth += 1;
+                    }
+                    None
+                }
+                Item::Action { id, .. } => Some(Event::Action(*id)),
+                Item::Number { value, .. } => {
+                    self.editing = Some(value.get());
+                    None
+                }
+                Item::Choice { selected, .. } => {
+                    self.editing = Some(selected.get() as i32);
+                    None
+                }
+            },
+        }
+    }
+
+    fn handle_edit(&mut self, input: Input, original: i32) -> Option<Event> {
+        let item = self.selected()?;
+
+        match (input, item) {
+            (Input::Select, Item::Number { id, .. }) | (Input::Select, Item::Choice { id, .. }) => {
+                self.editing = None;
+                Some(Event::Changed(*id))
+            }
+            (Input::Back, Item::Number { value, .. }) => {
+                value.set(original);
+                self.editing = None;
+                None
+            }
+            (Input::Back, Item::Choice { selected, .. }) => {
+                selected.set(original as usize);
+                self.editing = None;
+                None
+            }
+            (
+                Input::Up,
+                Item::Number {
+                    value, max, step, ..
+                },
+            ) => {
+                value.set(value.get().saturating_add(*step).min(*max));
+                None
+            }
+            (
+                Input::Down,
+                Item::Number {
+                    value, min, step, ..
+                },
+            ) => {
+                value.set(value.get().saturating_sub(*step).max(*min));
+                None
+            }
+            (
+                Input::Up,
+                Item::Choice {
+                    options, selected, ..
+                },
+            ) => {
+                selected.set((selected.get() + 1) % options.len().max(1));
+                None
+            }
+            (
+                Input::Down,
+                Item::Choice {
+                    options, selected, ..
+                },
+            ) => {
+                let len = options.len().max(1);
+                selected.set((selected.get() + len - 1) % len);
+                None
+            }
+            _ => {
+                self.editing = None;
+                None
+            }
+        }
+    }
+
+    /// Keep the selected item within the `rows` visible rows
+    fn scroll(&mut self, rows: usize) {
+        let level = self.level_mut();
+
+        if level.selected < level.top {
+            level.top = level.selected;
+        } else if rows > 0 && level.selected >= level.top + rows {
+            level.top = level.selected + 1 - rows;
+        }
+    }
+
+    /// Fill `line` with the contents of the `row`-th visible row
+    fn compose(&self, row: usize, line: &mut [u8]) {
+        let level = self.level();
+        let index = level.top + row;
+        let item = match level.items.get(index) {
+            Some(item) => item,
+            None => return,
+        };
+
+        if line.is_empty() {
+            return;
+        }
+
+        if index == level.selected {
+            line[0] = match self.editing {
+                Some(_) => EDIT_INDICATOR,
+                None => INDICATOR,
+            };
+        }
+
+        let mut number = [0; 11];
+        let value: &[u8] = match item {
+            Item::Submenu { .. } => &[SUBMENU_MARKER],
+            Item::Action { .. } => &[],
+            Item::Number { value, .. } => format_i32(value.get(), &mut number),
+            Item::Choice {
+                options, selected, ..
+            } => options
+                .get(selected.get())
+                .map(|option| option.as_bytes())
+                .unwrap_or(&[]),
+        };
+
+        // The value is right aligned and takes precedence over the label
+        let value = &value[..value.len().min(line.len() - 1)];
+        let value_start = line.len() - value.len();
+        line[value_start..].copy_from_slice(value);
+
+        let label = item.label().as_bytes();
+        let label_end = (1 + label.len()).min(value_start.saturating_sub(1).max(1));
+        line[1..label_end].copy_from_slice(&label[..label_end - 1]);
+    }
+}
+
+/// Format `value` in decimal into `buffer`
+fn format_i32(value: i32, buffer: &mut [u8; 11]) -> &[u8] {
+    let mut n = (value as i64).abs();
+    let mut start = buffer.len();
+
+    loop {
+        start -= 1;
+        buffer[start] = b'0' + (n % 10) as u8;
+        n /= 10;
+
+        if n == 0 {
+            break;
+        }
+    }
+
+    if value < 0 {
+        start -= 1;
+        buffer[start] = b'-';
+    }
+
+    &buffer[start..]
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn navigation() {
+        let inner = [Item::Action {
+            label: "Inner",
+            id: 2,
+        }];
+        let root = [
+            Item::Action {
+                label: "First",
+                id: 0,
+            },
+            Item::Submenu {
+                label: "Sub",
+                items: &inner,
+            },
+        ];
+        let mut menu = Menu::new(&root);
+
+        assert_eq!(menu.handle(Input::Up), None);
+        assert_eq!(menu.handle(Input::Select), Some(Event::Action(0)));
+        assert_eq!(menu.handle(Input::Down), None);
+        assert_eq!(menu.handle(Input::Down), None);
+        assert_eq!(menu.handle(Input::Select), None);
+        assert_eq!(menu.handle(Input::Select), Some(Event::Action(2)));
+        assert_eq!(menu.handle(Input::Back), None);
+        assert_eq!(menu.selected().map(Item::label), Some("Sub"));
+        assert_eq!(menu.handle(Input::Back), Some(Event::Exit));
+    }
+
+    #[test]
+    fn editing() {
+        let value = Cell::new(9);
+        let mode = Cell::new(0);
+        let options = ["A", "B", "C"];
+        let root = [
+            Item::Number {
+                label: "Value",
+                id: 1,
+                value: &value,
+                min: 0,
+                max: 10,
+                step: 1,
+            },
+            Item::Choice {
+                label: "Mode",
+                id: 2,
+                options: &options,
+                selected: &mode,
+            },
+        ];
+        let mut menu = Menu::new(&root);
+
+        menu.handle(Input::Select);
+        assert!(menu.is_editing());
+        menu.handle(Input::Up);
+        menu.handle(Input::Up);
+        assert_eq!(value.get(), 10);
+        assert_eq!(menu.handle(Input::Select), Some(Event::Changed(1)));
+
+        menu.handle(Input::Down);
+        menu.handle(Input::Select);
+        menu.handle(Input::Down);
+        assert_eq!(mode.get(), 2);
+        assert_eq!(menu.handle(Input::Back), None);
+        assert_eq!(mode.get(), 0);
+        assert!(!menu.is_editing());
+    }
+
+    #[test]
+    fn rows() {
+        let value = Cell::new(-42);
+        let root = [
+            Item::Number {
+                label: "Temperature",
+                id: 0,
+                value: &value,
+                min: -100,
+                max: 100,
+                step: 1,
+            },
+            Item::Action { label: "Go", id: 1 },
+        ];
+        let menu = Menu::new(&root);
+        let mut line = [b' '; 12];
+
+        menu.compose(0, &mut line);
+        assert_eq!(&line, b">Tempera -42");
+
+        let mut line = [b' '; 12];
+        menu.compose(1, &mut line);
+        assert_eq!(&line, b" Go         ");
+    }
+
+    #[test]
+    fn encoder() {
+        let mut encoder = Encoder::new(0, 4);
+
+        assert_eq!(encoder.update(3), None);
+        assert_eq!(encoder.update(9), Some(Input::Down));
+        assert_eq!(encoder.update(9), Some(Input::Down));
+        assert_eq!(encoder.update(9), None);
+        assert_eq!(encoder.update(0), Some(Input::Up));
+    }
+
+    #[test]
+    fn numbers() {
+        let mut buffer = [0; 11];
+
+        assert_eq!(format_i32(0, &mut buffer), b"0");
+        assert_eq!(format_i32(1234, &mut buffer), b"1234");
+        assert_eq!(format_i32(i32::MIN, &mut buffer), b"-2147483648");
+    }
+}
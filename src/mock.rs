@@ -0,0 +1,171 @@
+//! Test doubles for buses and delays
+
+use embedded_hal::blocking::delay::{DelayMs, DelayUs};
+use embedded_hal::blocking::i2c::{Read, Write};
+
+use crate::bus::{DataBus, ReadableBus};
+use crate::error::{Error, Result};
+
+pub(crate) use crate::timing::NoDelay;
+
+/// Records everything written, as (byte, data) pairs
+pub(crate) struct Recorder {
+    pub written: [(u8, bool); 64],
+    pub len: usize,
+}
+
+impl Default for Recorder {
+    fn default() -> Recorder {
+        Recorder {
+            written: [(0, false); 64],
+            len: 0,
+        }
+    }
+}
+
+impl DataBus for Recorder {
+    fn write<D: DelayUs<u16> + DelayMs<u8>>(
+        &mut self,
+        byte: u8,
+        data: bool,
+        _delay: &mut D,
+    ) -> Result<()> {
+        self.written[self.len] = (byte, data);
+        self.len += 1;
+        Ok(())
+    }
+}
+
+/// Records I2C transactions as (address, last byte) pairs
+pub(crate) struct I2cLog {
+    pub transfers: [(u8, u8); 64],
+    pub len: usize,
+    /// Fail every transaction when set
+    pub fail: bool,
+    /// Only acknowledge this address when set
+    pub present: Option<u8>,
+    /// Returned by reads, instead of the last byte written
+    pub input: Option<u8>,
+    /// Last byte written
+    port: u8,
+}
+
+impl Default for I2cLog {
+    fn default() -> I2cLog {
+        I2cLog {
+            transfers: [(0, 0); 64],
+            len: 0,
+            fail: false,
+            present: None,
+            input: None,
+            port: 0,
+        }
+    }
+}
+
+impl Write for I2cLog {
+    type Error = ();
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> core::result::Result<(), ()> {
+        if self.fail || self.present.is_some_and(|present| present != address) {
+            return Err(());
+        }
+
+        self.port = bytes.last().copied().unwrap_or(0);
+
+        // Keep the latest transactions
+        if self.len == self.transfers.len() {
+            self.transfers.copy_within(1.., 0);
+            self.len -= 1;
+        }
+
+        self.transfers[self.len] = (address, bytes.last().copied().unwrap_or(0));
+        self.len += 1;
+        Ok(())
+    }
+}
+
+impl Read for I2cLog {
+    type Error = ();
+
+    fn read(&mut self, address: u8, buffer: &mut [u8]) -> core::result::Result<(), ()> {
+        if self.fail || self.present.is_some_and(|present| present != address) {
+            return Err(());
+        }
+
+        buffer.fill(self.input.unwrap_or(self.port));
+        Ok(())
+    }
+}
+
+/// Keeps DDRAM like a display would, without wrapping between lines
+pub(crate) struct Ram {
+    pub ddram: [u8; 128],
+    pub address: u8,
+    /// Bytes written to this address are lost
+    pub stuck: Option<u8>,
+    /// Data bytes written
+    pub writes: usize,
+    /// Report the busy flag as set
+    pub busy: bool,
+    /// Fail reads of the busy flag
+    pub unreadable: bool,
+}
+
+impl Default for Ram {
+    fn default() -> Ram {
+        Ram {
+            ddram: [b' '; 128],
+            address: 0,
+            stuck: None,
+            writes: 0,
+            busy: false,
+            unreadable: false,
+        }
+    }
+}
+
+impl Ram {
+    fn step(&mut self) {
+        self.address = (self.address + 1) & 0b0111_1111;
+    }
+}
+
+impl DataBus for Ram {
+    fn write<D: DelayUs<u16> + DelayMs<u8>>(
+        &mut self,
+        byte: u8,
+        data: bool,
+        _delay: &mut D,
+    ) -> Result<()> {
+        if data {
+            if self.stuck != Some(self.address) {
+                self.ddram[self.address as usize] = byte;
+            }
+
+            self.writes += 1;
+            self.step();
+        } else if byte & 0b1000_0000 != 0 {
+            self.address = byte & 0b0111_1111;
+        }
+
+        Ok(())
+    }
+}
+
+impl ReadableBus for Ram {
+    fn read<D: DelayUs<u16> + DelayMs<u8>>(&mut self, data: bool, _delay: &mut D) -> Result<u8> {
+        if !data {
+            if self.unreadable {
+                return Err(Error::Io);
+            }
+
+            return Ok(self.address | if self.busy { 0b1000_0000 } else { 0 });
+        }
+
+        let byte = self.ddram[self.address as usize];
+        self.step();
+
+        Ok(byte)
+    }
+}
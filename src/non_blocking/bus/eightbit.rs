@@ -75,6 +75,14 @@ impl<
         }
     }
 
+    /// Give back the pins and the delay
+    pub fn release(self) -> (RS, EN, D0, D1, D2, D3, D4, D5, D6, D7, D) {
+        (
+            self.rs, self.en, self.d0, self.d1, self.d2, self.d3, self.d4, self.d5, self.d6,
+            self.d7, self.delay,
+        )
+    }
+
     fn set_bus_bits(&mut self, data: u8) -> Result<()> {
         let db0: bool = (0b0000_0001 & data) != 0;
         let db1: bool = (0b0000_0010 & data) != 0;
@@ -86,51 +94,51 @@ impl<
         let db7: bool = (0b1000_0000 & data) != 0;
 
         if db0 {
-            self.d0.set_high().map_err(|_| Error)?;
+            self.d0.set_high().map_err(|_| Error::Io)?;
         } else {
-            self.d0.set_low().map_err(|_| Error)?;
+            self.d0.set_low().map_err(|_| Error::Io)?;
         }
 
         if db1 {
-            self.d1.set_high().map_err(|_| Error)?;
+            self.d1.set_high().map_err(|_| Error::Io)?;
         } else {
-            self.d1.set_low().map_err(|_| Error)?;
+            self.d1.set_low().map_err(|_| Error::Io)?;
         }
 
         if db2 {
-            self.d2.set_high().map_err(|_| Error)?;
+            self.d2.set_high().map_err(|_| Error::Io)?;
         } else {
-            self.d2.set_low().map_err(|_| Error)?;
+            self.d2.set_low().map_err(|_| Error::Io)?;
         }
 
         if db3 {
-            self.d3.set_high().map_err(|_| Error)?;
+            self.d3.set_high().map_err(|_| Error::Io)?;
         } else {
-            self.d3.set_low().map_err(|_| Error)?;
+            self.d3.set_low().map_err(|_| Error::Io)?;
         }
 
         if db4 {
-            self.d4.set_high().map_err(|_| Error)?;
+            self.d4.set_high().map_err(|_| Error::Io)?;
         } else {
-            self.d4.set_low().map_err(|_| Error)?;
+            self.d4.set_low().map_err(|_| Error::Io)?;
         }
 
         if db5 {
-            self.d5.set_high().map_err(|_| Error)?;
+            self.d5.set_high().map_err(|_| Error::Io)?;
         } else {
-            self.d5.set_low().map_err(|_| Error)?;
+            self.d5.set_low().map_err(|_| Error::Io)?;
         }
 
         if db6 {
-            self.d6.set_high().map_err(|_| Error)?;
+            self.d6.set_high().map_err(|_| Error::Io)?;
         } else {
-            self.d6.set_low().map_err(|_| Error)?;
+            self.d6.set_low().map_err(|_| Error::Io)?;
         }
 
         if db7 {
-            self.d7.set_high().map_err(|_| Error)?;
+            self.d7.set_high().map_err(|_| Error::Io)?;
         } else {
-            self.d7.set_low().map_err(|_| Error)?;
+            self.d7.set_low().map_err(|_| Error::Io)?;
         }
 
         Ok(())
@@ -163,16 +171,16 @@ impl<
     fn write<'a>(&'a mut self, byte: u8, data: bool) -> Self::WriteFuture<'a> {
         async move {
             if data {
-                self.rs.set_high().map_err(|_| Error)?;
+                self.rs.set_high().map_err(|_| Error::Io)?;
             } else {
-                self.rs.set_low().map_err(|_| Error)?;
+                self.rs.set_low().map_err(|_| Error::Io)?;
             }
             self.set_bus_bits(byte)?;
-            self.en.set_high().map_err(|_| Error)?;
+            self.en.set_high().map_err(|_| Error::Io)?;
             self.delay.delay_ms(2).await.unwrap();
-            self.en.set_low().map_err(|_| Error)?;
+            self.en.set_low().map_err(|_| Error::Io)?;
             if data {
-                self.rs.set_low().map_err(|_| Error)?;
+                self.rs.set_low().map_err(|_| Error::Io)?;
             }
             Ok(())
         }
@@ -53,6 +53,13 @@ impl<
         }
     }
 
+    /// Give back the pins and the delay
+    pub fn release(self) -> (RS, EN, D4, D5, D6, D7, D) {
+        (
+            self.rs, self.en, self.d4, self.d5, self.d6, self.d7, self.delay,
+        )
+    }
+
     fn write_lower_nibble(&mut self, data: u8) -> Result<()> {
         let db0: bool = (0b0000_0001 & data) != 0;
         let db1: bool = (0b0000_0010 & data) != 0;
@@ -60,27 +67,27 @@ impl<
         let db3: bool = (0b0000_1000 & data) != 0;
 
         if db0 {
-            self.d4.set_high().map_err(|_| Error)?;
+            self.d4.set_high().map_err(|_| Error::Io)?;
         } else {
-            self.d4.set_low().map_err(|_| Error)?;
+            self.d4.set_low().map_err(|_| Error::Io)?;
         }
 
         if db1 {
-            self.d5.set_high().map_err(|_| Error)?;
+            self.d5.set_high().map_err(|_| Error::Io)?;
         } else {
-            self.d5.set_low().map_err(|_| Error)?;
+            self.d5.set_low().map_err(|_| Error::Io)?;
         }
 
         if db2 {
-            self.d6.set_high().map_err(|_| Error)?;
+            self.d6.set_high().map_err(|_| Error::Io)?;
         } else {
-            self.d6.set_low().map_err(|_| Error)?;
+            self.d6.set_low().map_err(|_| Error::Io)?;
         }
 
         if db3 {
-            self.d7.set_high().map_err(|_| Error)?;
+            self.d7.set_high().map_err(|_| Error::Io)?;
         } else {
-            self.d7.set_low().map_err(|_| Error)?;
+            self.d7.set_low().map_err(|_| Error::Io)?;
         }
 
         Ok(())
@@ -93,27 +100,27 @@ impl<
         let db7: bool = (0b1000_0000 & data) != 0;
 
         if db4 {
-            self.d4.set_high().map_err(|_| Error)?;
+            self.d4.set_high().map_err(|_| Error::Io)?;
         } else {
-            self.d4.set_low().map_err(|_| Error)?;
+            self.d4.set_low().map_err(|_| Error::Io)?;
         }
 
         if db5 {
-            self.d5.set_high().map_err(|_| Error)?;
+            self.d5.set_high().map_err(|_| Error::Io)?;
         } else {
-            self.d5.set_low().map_err(|_| Error)?;
+            self.d5.set_low().map_err(|_| Error::Io)?;
         }
 
         if db6 {
-            self.d6.set_high().map_err(|_| Error)?;
+            self.d6.set_high().map_err(|_| Error::Io)?;
         } else {
-            self.d6.set_low().map_err(|_| Error)?;
+            self.d6.set_low().map_err(|_| Error::Io)?;
         }
 
         if db7 {
-            self.d7.set_high().map_err(|_| Error)?;
+            self.d7.set_high().map_err(|_| Error::Io)?;
         } else {
-            self.d7.set_low().map_err(|_| Error)?;
+            self.d7.set_low().map_err(|_| Error::Io)?;
         }
         Ok(())
     }
@@ -136,22 +143,22 @@ impl<
     fn write<'a>(&'a mut self, byte: u8, data: bool) -> Self::WriteFuture<'a> {
         async move {
             if data {
-                self.rs.set_high().map_err(|_| Error)?;
+                self.rs.set_high().map_err(|_| Error::Io)?;
             } else {
-                self.rs.set_low().map_err(|_| Error)?;
+                self.rs.set_low().map_err(|_| Error::Io)?;
             }
             self.write_upper_nibble(byte)?;
             // Pulse the enable pin to recieve the upper nibble
-            self.en.set_high().map_err(|_| Error)?;
+            self.en.set_high().map_err(|_| Error::Io)?;
             self.delay.delay_ms(2).await.unwrap();
-            self.en.set_low().map_err(|_| Error)?;
+            self.en.set_low().map_err(|_| Error::Io)?;
             self.write_lower_nibble(byte)?;
             // Pulse the enable pin to recieve the lower nibble
-            self.en.set_high().map_err(|_| Error)?;
+            self.en.set_high().map_err(|_| Error::Io)?;
             self.delay.delay_ms(2).await.unwrap();
-            self.en.set_low().map_err(|_| Error)?;
+            self.en.set_low().map_err(|_| Error::Io)?;
             if data {
-                self.rs.set_low().map_err(|_| Error)?;
+                self.rs.set_low().map_err(|_| Error::Io)?;
             }
             Ok(())
         }
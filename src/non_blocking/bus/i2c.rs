@@ -2,41 +2,67 @@ use core::future::Future;
 use embedded_hal_async::delay::DelayUs;
 use embedded_hal_async::i2c::I2c;
 
-use crate::error::Result;
-use crate::non_blocking::bus::DataBus;
+use crate::error::{Error, Result};
+use crate::non_blocking::bus::{DataBus, ReadableBus};
 
 pub struct I2CBus<I2C: I2c, D: DelayUs> {
     i2c_bus: I2C,
     address: u8,
+    backlight: u8,
     delay: D,
 }
 
 const BACKLIGHT: u8 = 0b0000_1000;
 const ENABLE: u8 = 0b0000_0100;
-// const READ_WRITE: u8 = 0b0000_0010; // Not used as no reading of the `HD44780` is done
+const READ_WRITE: u8 = 0b0000_0010;
 const REGISTER_SELECT: u8 = 0b0000_0001;
 
+pub use crate::bus::PROBE_ADDRESSES;
+
+/// Find the address of an I2C backpack, see
+/// [`bus::probe_i2c`](../../bus/fn.probe_i2c.html)
+pub async fn probe_i2c<I2C: I2c>(i2c_bus: &mut I2C) -> Result<u8> {
+    for address in PROBE_ADDRESSES {
+        let mut port = [0];
+
+        if i2c_bus.write(address, &[BACKLIGHT]).await.is_ok()
+            && i2c_bus.read(address, &mut port).await.is_ok()
+            && port[0] & !BACKLIGHT == 0
+        {
+            return Ok(address);
+        }
+    }
+
+    Err(Error::NotFound)
+}
+
 impl<I2C: I2c, D: DelayUs> I2CBus<I2C, D> {
     pub fn new(i2c_bus: I2C, address: u8, delay: D) -> I2CBus<I2C, D> {
         I2CBus {
             i2c_bus,
             address,
+            backlight: BACKLIGHT,
             delay,
         }
     }
+
+    /// Give back the I2C peripheral and the delay
+    pub fn release(self) -> (I2C, D) {
+        (self.i2c_bus, self.delay)
+    }
 }
 
-impl<I2C: I2c + 'static, D: DelayUs> DataBus for I2CBus<I2C, D> {
+impl<I2C: I2c, D: DelayUs> DataBus for I2CBus<I2C, D> {
     type WriteFuture<'a> = impl Future<Output = Result<()>> + 'a
     where
-        D: 'a;
+        Self: 'a;
 
     fn write<'a>(&'a mut self, byte: u8, data: bool) -> Self::WriteFuture<'a> {
         async move {
             let rs = match data {
                 false => 0u8,
                 true => REGISTER_SELECT,
-            };
+            } | self.backlight;
 
             let write_chain = [
                 // using the same hack as arduino lib (https://github.com/duinoWitchery/hd44780/):
@@ -45,13 +71,16 @@ impl<I2C: I2c + 'static, D: DelayUs> DataBus for I2CBus<I2C, D> {
                 // also we send both nibbles in one i2c transaction (it's nice =))
                 // I think using DMA we can actually offload even more work off cpu sacrificing memory usage
                 // but no DMA yet + will need to change the library structure... Uncool
-                rs | BACKLIGHT | (byte & 0xF0) | ENABLE,
-                rs | BACKLIGHT | (byte & 0xF0),
-                rs | BACKLIGHT | ((byte & 0x0F) << 4) | ENABLE,
-                rs | BACKLIGHT | ((byte & 0x0F) << 4),
+                rs | (byte & 0xF0) | ENABLE,
+                rs | (byte & 0xF0),
+                rs | ((byte & 0x0F) << 4) | ENABLE,
+                rs | ((byte & 0x0F) << 4),
             ];
 
-            let _ = self.i2c_bus.write(self.address, &write_chain).await;
+            self.i2c_bus
+                .write(self.address, &write_chain)
+                .await
+                .map_err(|_| Error::Io)?;
 
             // TODO: display stopped working w/o this... Maybe we want to pack everything into one chunky transaction
             self.delay.delay_ms(1).await.unwrap();
@@ -59,4 +88,52 @@ impl<I2C: I2c + 'static, D: DelayUs> DataBus for I2CBus<I2C, D> {
             Ok(())
         }
     }
+
+    fn set_backlight(&mut self, on: bool) {
+        self.backlight = if on { BACKLIGHT } else { 0 };
+    }
+}
+
+impl<I2C: I2c, D: DelayUs> I2CBus<I2C, D> {
+    /// Read a nibble from the lcd
+    /// The nibble is returned in the upper part of the byte
+    async fn read_nibble(&mut self, data: bool) -> Result<u8> {
+        let rs = match data {
+            false => 0u8,
+            true => REGISTER_SELECT,
+        };
+        // The data lines are left high so the `HD44780` can pull them low
+        let byte = 0xF0 | READ_WRITE | rs | self.backlight;
+        let mut port = [0];
+
+        self.i2c_bus
+            .write(self.address, &[byte, byte | ENABLE])
+            .await
+            .map_err(|_| Error::Io)?;
+        self.i2c_bus
+            .read(self.address, &mut port)
+            .await
+            .map_err(|_| Error::Io)?;
+        self.i2c_bus
+            .write(self.address, &[byte])
+            .await
+            .map_err(|_| Error::Io)?;
+
+        Ok(port[0] & 0xF0)
+    }
+}
+
+impl<I2C: I2c, D: DelayUs> ReadableBus for I2CBus<I2C, D> {
+    type ReadFuture<'a> = impl Future<Output = Result<u8>> + 'a
+    where
+        Self: 'a;
+
+    fn read<'a>(&'a mut self, data: bool) -> Self::ReadFuture<'a> {
+        async move {
+            let upper_nibble = self.read_nibble(data).await?;
+            let lower_nibble = self.read_nibble(data).await?;
+
+            Ok(upper_nibble | (lower_nibble >> 4))
+        }
+    }
 }
@@ -6,7 +6,7 @@ mod i2c;
 
 pub use self::eightbit::EightBitBus;
 pub use self::fourbit::FourBitBus;
-pub use self::i2c::I2CBus;
+pub use self::i2c::{probe_i2c, I2CBus, PROBE_ADDRESSES};
 
 use crate::error::Result;
 
@@ -17,6 +17,18 @@ pub trait DataBus {
 
     fn write<'a>(&'a mut self, byte: u8, data: bool) -> Self::WriteFuture<'a>;
 
-    // TODO
-    // fn read(...)
+    /// Turn the backlight on or off, for buses that control it. The change
+    /// takes effect with the next byte written. Does nothing by default.
+    fn set_backlight(&mut self, _on: bool) {}
+}
+
+/// A bus that can also read from the `HD44780`, with its R/W line wired
+pub trait ReadableBus: DataBus {
+    type ReadFuture<'a>: Future<Output = Result<u8>>
+    where
+        Self: 'a;
+
+    /// Read the busy flag and address counter if `data` is false, or the
+    /// byte at the address counter if it is true
+    fn read<'a>(&'a mut self, data: bool) -> Self::ReadFuture<'a>;
 }
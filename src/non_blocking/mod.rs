@@ -6,10 +6,11 @@ use embedded_hal_async::delay::DelayUs;
 use embedded_hal_async::i2c;
 
 pub mod bus;
-use bus::{DataBus, EightBitBus, FourBitBus};
+pub mod service;
+use bus::{DataBus, EightBitBus, FourBitBus, ReadableBus};
 
 pub use crate::error;
-use error::Result;
+use error::{Error, Result};
 
 pub use crate::entry_mode;
 
@@ -19,11 +20,56 @@ pub use crate::display_mode;
 
 pub use display_mode::DisplayMode;
 
+pub use crate::geometry;
+
+pub use geometry::Geometry;
+
+pub use crate::detect;
+
+use detect::{Detection, PROBED_BYTES, PROBE_LENGTHS};
+
+use crate::cache::CommandCache;
+use crate::counter::AddressCounter;
+use crate::shadow::Shadow;
+
+/// An `HD44780` driven through an async bus
+///
+/// # Cancellation
+///
+/// Any future returned by the driver may be dropped, for example by a
+/// `select` with a timeout. A byte cut off halfway through its transfer on a
+/// `FourBitBus` or an `I2CBus` leaves the `HD44780` expecting the second half
+/// of it, so the next operation first resynchronizes the interface by
+/// instruction, then restores the function set, display mode, entry mode and
+/// cursor position. A display shift may be lost while resynchronizing.
+///
+/// What has been done when a future is dropped depends on the method:
+///
+/// - Methods sending a single instruction or byte, such as `clear`,
+///   `set_cursor_pos` or `write_byte`, either take effect or don't. The
+///   driver follows a byte in its models of the display as soon as it is
+///   sent, and waits for it to be executed before sending the next one, so
+///   there is no await point in between.
+/// - `write_str` and `write_bytes` have written a prefix of their input.
+/// - `set_custom_char` has stored some of the rows of the glyph.
+/// - `new_4bit`, `new_8bit` and `new_i2c` leave the display uninitialized,
+///   and there is no driver to use.
 pub struct HD44780<B: DataBus, D: DelayUs> {
     bus: B,
     entry_mode: EntryMode,
     display_mode: DisplayMode,
+    geometry: Geometry,
+    counter: AddressCounter,
     delay: D,
+    /// Function set instruction selecting the interface width
+    function_set: u8,
+    cache: CommandCache,
+    shadow: Shadow,
+    /// Set while a byte is being sent, and left set if sending it was cancelled
+    in_transfer: bool,
+    /// Microseconds the `HD44780` still needs to execute the last byte sent,
+    /// waited before the next one
+    settle_us: u32,
 }
 
 pub use crate::Cursor;
@@ -71,12 +117,11 @@ impl<
         d7: D7,
         delay: D,
     ) -> Result<HD44780<EightBitBus<RS, EN, D0, D1, D2, D3, D4, D5, D6, D7, D>, D>> {
-        let mut hd = HD44780 {
-            bus: EightBitBus::from_pins(rs, en, d0, d1, d2, d3, d4, d5, d6, d7, delay.clone()),
-            entry_mode: EntryMode::default(),
-            display_mode: DisplayMode::default(),
-            delay: delay,
-        };
+        let mut hd = HD44780::from_bus(
+            EightBitBus::from_pins(rs, en, d0, d1, d2, d3, d4, d5, d6, d7, delay.clone()),
+            delay,
+            0b0011_1000,
+        );
 
         hd.init_8bit().await?;
 
@@ -122,12 +167,11 @@ impl<
         d7: D7,
         delay: D,
     ) -> Result<HD44780<FourBitBus<RS, EN, D4, D5, D6, D7, D>, D>> {
-        let mut hd = HD44780 {
-            bus: FourBitBus::from_pins(rs, en, d4, d5, d6, d7, delay.clone()),
-            entry_mode: EntryMode::default(),
-            display_mode: DisplayMode::default(),
-            delay: delay,
-        };
+        let mut hd = HD44780::from_bus(
+            FourBitBus::from_pins(rs, en, d4, d5, d6, d7, delay.clone()),
+            delay,
+            0b0010_1000,
+        );
 
         hd.init_4bit().await?;
 
@@ -135,7 +179,7 @@ impl<
     }
 }
 
-impl<I2C: i2c::I2c + 'static, D: DelayUs + Clone> HD44780<I2CBus<I2C, D>, D> {
+impl<I2C: i2c::I2c, D: DelayUs + Clone> HD44780<I2CBus<I2C, D>, D> {
     /// Create an instance of a `HD44780` from an i2c write peripheral,
     /// the `HD44780` I2C address and a struct implementing the delay trait.
     /// - The delay instance is used to sleep between commands to
@@ -150,16 +194,269 @@ impl<I2C: i2c::I2c + 'static, D: DelayUs + Clone> HD44780<I2CBus<I2C, D>, D> {
         address: u8,
         delay: D,
     ) -> Result<HD44780<I2CBus<I2C, D>, D>> {
-        let mut hd = HD44780 {
-            bus: I2CBus::new(i2c_bus, address, delay.clone()),
+        let mut hd = HD44780::from_bus(
+            I2CBus::new(i2c_bus, address, delay.clone()),
+            delay,
+            0b0010_1000,
+        );
+
+        hd.init_4bit().await?;
+
+        return Ok(hd);
+    }
+
+    /// Create an instance of a `HD44780` on an I2C backpack whose address
+    /// isn't known, returning it along with the address found. See
+    /// [`probe_i2c`](bus/fn.probe_i2c.html) for the addresses tried.
+    ///
+    /// Fails with `Error::NotFound` if no backpack answers.
+    ///
+    /// ```rust,ignore
+    /// let (mut lcd, address) = HD44780::new_i2c_probe(i2c, delay).await?;
+    /// ```
+    pub async fn new_i2c_probe(
+        mut i2c_bus: I2C,
+        delay: D,
+    ) -> Result<(HD44780<I2CBus<I2C, D>, D>, u8)> {
+        let address = bus::probe_i2c(&mut i2c_bus).await?;
+
+        Ok((HD44780::new_i2c(i2c_bus, address, delay).await?, address))
+    }
+
+    /// Take over a display that is already initialized, for example by the
+    /// firmware before a restart, without clearing it. The interface is
+    /// resynchronized, then the text, glyphs and cursor position are read
+    /// back from the display, see [reload](#method.reload).
+    ///
+    /// The display and entry modes can't be read back, so they are set to
+    /// their defaults.
+    ///
+    /// ```rust,ignore
+    /// let mut lcd = HD44780::attach_i2c(i2c, 0x27, delay).await?;
+    /// ```
+    pub async fn attach_i2c(
+        i2c_bus: I2C,
+        address: u8,
+        delay: D,
+    ) -> Result<HD44780<I2CBus<I2C, D>, D>> {
+        let mut hd = HD44780::from_bus(
+            I2CBus::new(i2c_bus, address, delay.clone()),
+            delay,
+            0b0010_1000,
+        );
+        // Resynchronize before anything else
+        hd.in_transfer = true;
+
+        hd.reload().await?;
+
+        Ok(hd)
+    }
+}
+
+impl<B: DataBus, D: DelayUs> HD44780<B, D> {
+    /// A driver for the display behind `bus`, which is left uninitialized.
+    /// The constructors set it up for the interface width in `function_set`.
+    fn from_bus(bus: B, delay: D, function_set: u8) -> HD44780<B, D> {
+        HD44780 {
+            bus,
             entry_mode: EntryMode::default(),
             display_mode: DisplayMode::default(),
-            delay: delay,
+            geometry: Geometry::default(),
+            counter: AddressCounter::default(),
+            delay,
+            function_set,
+            cache: CommandCache::default(),
+            shadow: Shadow::default(),
+            in_transfer: false,
+            settle_us: 0,
+        }
+    }
+}
+
+impl<B, D> HD44780<B, D>
+where
+    B: ReadableBus,
+    D: DelayUs,
+{
+    /// Read the byte shown at `position` (column, row)
+    ///
+    /// ```rust,ignore
+    /// let byte = lcd.read_char((3, 1)).await?
+    /// ```
+    pub async fn read_char(&mut self, position: (u8, u8)) -> Result<u8> {
+        let mut byte = [0];
+
+        self.read_ram(0b1000_0000 | self.geometry.address(position), &mut byte)
+            .await?;
+
+        Ok(byte[0])
+    }
+
+    /// Read a row of the display into `buffer`, up to the number of columns
+    /// of the display [geometry](#method.set_geometry). Returns the number
+    /// of bytes read.
+    pub async fn read_row(&mut self, row: u8, buffer: &mut [u8]) -> Result<usize> {
+        let len = buffer.len().min(self.geometry.columns as usize);
+        let address = self.geometry.address((0, row));
+
+        self.read_ram(0b1000_0000 | address, &mut buffer[..len])
+            .await?;
+
+        Ok(len)
+    }
+
+    /// Read back the custom character in one of the eight CGRAM slots, see
+    /// [set_custom_char](#method.set_custom_char)
+    pub async fn read_custom_char(&mut self, slot: u8) -> Result<[u8; 8]> {
+        if slot > 7 {
+            return Err(Error::InvalidArgument);
+        }
+
+        let mut bitmap = [0; 8];
+
+        self.read_ram(0b0100_0000 | (slot << 3), &mut bitmap)
+            .await?;
+
+        for row in bitmap.iter_mut() {
+            *row &= 0b0001_1111;
+        }
+
+        Ok(bitmap)
+    }
+
+    /// Read the text and glyphs stored in the display and the cursor
+    /// position, replacing what the driver remembers of them. This picks up
+    /// a display that was written to before the driver was created, for
+    /// example by the firmware before a restart.
+    pub async fn reload(&mut self) -> Result<()> {
+        let address = self.receive(false).await? & 0b0111_1111;
+        self.counter.set(address);
+
+        let mut shadow = self.shadow;
+
+        for slot in 0..8 {
+            self.read_ram(0b0100_0000 | (slot << 3), &mut shadow.cgram[slot as usize])
+                .await?;
+        }
+
+        for line in [0x00, 0x40] {
+            self.read_ram(0b1000_0000 | line, shadow.line_mut(line))
+                .await?;
+        }
+
+        self.shadow = shadow;
+
+        Ok(())
+    }
+
+    /// Find out how the display memory is organized, and check that bytes
+    /// written to it read back unchanged. A garbled read back, for example
+    /// from a four bit interface that is out of step, gives
+    /// [Error::Mismatch](error/enum.Error.html#variant.Mismatch).
+    ///
+    /// The text is restored afterwards. See [detect](detect/index.html) for
+    /// what can and can't be detected.
+    ///
+    /// ```rust,ignore
+    /// let detection = lcd.detect().await?;
+    /// lcd.set_geometry(detection.geometry());
+    /// ```
+    pub async fn detect(&mut self) -> Result<Detection> {
+        let mut saved = [0; PROBED_BYTES];
+        self.read_ram(0b1000_0000, &mut saved).await?;
+
+        let detection = self.probe().await;
+
+        // Put the text back even if probing failed
+        self.write_command(0b0000_0110).await?;
+        self.send_command(0b1000_0000).await?;
+
+        for &byte in saved.iter() {
+            self.write_data(byte).await?;
+        }
+
+        self.write_command(self.entry_mode.as_byte()).await?;
+        self.send_command(0b1000_0000 | self.counter.address)
+            .await?;
+
+        detection
+    }
+
+    async fn probe(&mut self) -> Result<Detection> {
+        // Distinct nibbles, so swapped or shifted ones show up
+        for &pattern in [0b1010_0101, 0b0101_1010].iter() {
+            self.poke(0x00, pattern).await?;
+
+            if self.peek(0x00).await? != pattern {
+                return Err(Error::Mismatch);
+            }
+        }
+
+        // In two line mode the address counter skips to the second line
+        self.poke(0x27, b' ').await?;
+        let two_line = self.receive(false).await? & 0b0111_1111 == 0x40;
+
+        let mut detection = Detection {
+            two_line,
+            line_length: if two_line { 40 } else { 80 },
         };
 
-        hd.init_4bit().await?;
+        self.poke(0x00, 0xFF).await?;
 
-        return Ok(hd);
+        for &length in PROBE_LENGTHS.iter() {
+            // 0x28 isn't a valid address in two line mode
+            if two_line && length == 40 {
+                break;
+            }
+
+            self.poke(length, length).await?;
+
+            if self.peek(0x00).await? == length {
+                detection.line_length = length;
+                break;
+            }
+        }
+
+        Ok(detection)
+    }
+
+    /// Write a byte to DDRAM without the driver following it
+    async fn poke(&mut self, address: u8, byte: u8) -> Result<()> {
+        self.write_command(0b0000_0110).await?;
+        self.send_command(0b1000_0000 | address).await?;
+        self.cache.forget_address();
+
+        self.write_data(byte).await
+    }
+
+    async fn peek(&mut self, address: u8) -> Result<u8> {
+        let mut byte = [0];
+        self.read_ram(0b1000_0000 | address, &mut byte).await?;
+
+        Ok(byte[0])
+    }
+
+    /// Read consecutive bytes from DDRAM or CGRAM, starting at the address
+    /// set by `cmd`, then move the cursor back
+    async fn read_ram(&mut self, cmd: u8, buffer: &mut [u8]) -> Result<()> {
+        let entry_mode = self.entry_mode.as_byte();
+
+        // Read moving right, reading never shifts the display
+        self.write_command(0b0000_0110).await?;
+
+        // Reading only works right after setting the address
+        self.send_command(cmd).await?;
+        self.cache.forget_address();
+
+        for byte in buffer.iter_mut() {
+            *byte = self.receive(true).await?;
+
+            // Wait for the read to be processed before the next transfer
+            self.settle_us = 100;
+        }
+
+        self.write_command(entry_mode).await?;
+        self.send_command(0b1000_0000 | self.counter.address).await
     }
 }
 
@@ -184,6 +481,7 @@ where
     /// ```
     pub async fn reset(&mut self) -> Result<()> {
         self.write_command(0b0000_0010).await?;
+        self.counter.home();
 
         Ok(())
     }
@@ -203,6 +501,88 @@ where
         Ok(())
     }
 
+    /// Turn the backlight on or off. Only the `I2CBus` controls a backlight,
+    /// on other buses this does nothing.
+    ///
+    /// ```rust,ignore
+    /// lcd.set_backlight(false).await?;
+    /// ```
+    pub async fn set_backlight(&mut self, on: bool) -> Result<()> {
+        self.bus.set_backlight(on);
+
+        // The backlight is only updated along with a write
+        let cmd_byte = self.display_mode.as_byte();
+
+        self.send_command(cmd_byte).await
+    }
+
+    /// Send the function set, display mode, entry mode and cursor position
+    /// again. Instructions that wouldn't change anything are normally
+    /// skipped, so use this if the `HD44780` may have lost its state, for
+    /// example after a glitch on its supply or bus.
+    ///
+    /// ```rust,ignore
+    /// lcd.resend_state().await?;
+    /// ```
+    pub async fn resend_state(&mut self) -> Result<()> {
+        self.cache.invalidate();
+
+        self.send_command(self.function_set).await?;
+        self.send_command(self.display_mode.as_byte()).await?;
+        self.send_command(self.entry_mode.as_byte()).await?;
+        self.send_command(0b1000_0000 | self.counter.address).await
+    }
+
+    /// Run the initialization sequence again, then restore the glyphs, the
+    /// text, the display and entry modes, the display shift and the cursor.
+    /// Use this when the display may have been scrambled or blanked, for
+    /// example by electrical noise.
+    ///
+    /// The driver keeps a copy of everything written to the display for this,
+    /// and rewrites all of it, which takes a while.
+    ///
+    /// ```rust,ignore
+    /// lcd.reinit().await?;
+    /// ```
+    pub async fn reinit(&mut self) -> Result<()> {
+        self.cache.invalidate();
+
+        self.in_transfer = false;
+
+        if self.function_set & 0b0001_0000 != 0 {
+            self.init_8bit().await?;
+        } else {
+            self.init_4bit().await?;
+        }
+
+        // Restore memory moving right without shifting the display
+        self.send_command(0b0000_0110).await?;
+
+        let shadow = self.shadow;
+
+        self.send_command(0b0100_0000).await?;
+        for &row in shadow.cgram.iter().flatten() {
+            self.write_data(row).await?;
+        }
+
+        for line in [0x00, 0x40] {
+            self.send_command(0b1000_0000 | line).await?;
+
+            for &byte in shadow.line(line) {
+                self.write_data(byte).await?;
+            }
+        }
+
+        self.send_command(self.display_mode.as_byte()).await?;
+        self.send_command(self.entry_mode.as_byte()).await?;
+
+        for _ in 0..self.counter.shift {
+            self.send_command(0b0001_1000).await?;
+        }
+
+        self.send_command(0b1000_0000 | self.counter.address).await
+    }
+
     /// Clear the entire display
     ///
     /// ```rust,ignore
@@ -211,6 +591,11 @@ where
     pub async fn clear(&mut self) -> Result<()> {
         self.write_command(0b0000_0001).await?;
 
+        // Clearing also makes the cursor move right
+        self.counter.home();
+        self.shadow.clear();
+        self.entry_mode.cursor_mode = CursorMode::Increment;
+
         Ok(())
     }
 
@@ -292,10 +677,71 @@ where
         let lower_7_bits = 0b0111_1111 & position;
 
         self.write_command(0b1000_0000 | lower_7_bits).await?;
+        self.counter.set(lower_7_bits);
 
         Ok(())
     }
 
+    /// Set the cursor position using a column and a row, counted from the
+    /// top-left corner of the display. The position is translated using the
+    /// display [geometry](#method.set_geometry).
+    ///
+    /// ```rust,ignore
+    /// // Move to the 3rd column of the second row
+    /// lcd.set_cursor_xy((2, 1))
+    /// ```
+    pub async fn set_cursor_xy(&mut self, position: (u8, u8)) -> Result<()> {
+        self.set_cursor_pos(self.geometry.address(position)).await
+    }
+
+    /// Set the number of columns and rows of the display. This doesn't send
+    /// anything to the `HD44780`, it only changes how positions are translated
+    /// to addresses. Defaults to 20x4, which also fits 16x2 and 20x2 displays.
+    ///
+    /// ```rust,ignore
+    /// lcd.set_geometry(Geometry::new(16, 4));
+    /// ```
+    pub fn set_geometry(&mut self, geometry: Geometry) {
+        self.geometry = geometry;
+    }
+
+    /// The number of columns and rows of the display
+    pub fn geometry(&self) -> Geometry {
+        self.geometry
+    }
+
+    /// The DDRAM address the next byte will be written to. The driver keeps
+    /// track of it as it sends instructions and bytes, without reading it
+    /// back from the display.
+    pub fn cursor_pos(&self) -> u8 {
+        self.counter.address
+    }
+
+    /// The (column, row) position of the cursor, as given to
+    /// [set_cursor_xy](#method.set_cursor_xy), or `None` if the cursor is
+    /// outside the display [geometry](#method.set_geometry). This ignores
+    /// any display shift.
+    pub fn cursor_xy(&self) -> Option<(u8, u8)> {
+        self.geometry.position(self.counter.address)
+    }
+
+    /// How many positions the display is shifted to the left, from 0 to 39.
+    /// Shifting right from 0 gives 39.
+    pub fn display_shift(&self) -> u8 {
+        self.counter.shift
+    }
+
+    /// Give back the bus and the delay, leaving the display as it is. The
+    /// pins or I2C peripheral can then be taken back from the bus.
+    ///
+    /// ```rust,ignore
+    /// let (bus, delay) = lcd.release();
+    /// let (i2c, _) = bus.release();
+    /// ```
+    pub fn release(self) -> (B, D) {
+        (self.bus, self.delay)
+    }
+
     /// Shift just the cursor to the left or the right
     ///
     /// ```rust,ignore
@@ -309,6 +755,7 @@ where
         };
 
         self.write_command(0b0001_0000 | bits | bits).await?;
+        self.counter.step(matches!(dir, Direction::Right));
 
         Ok(())
     }
@@ -326,6 +773,7 @@ where
         };
 
         self.write_command(0b0001_1000 | bits).await?;
+        self.counter.shift_display(matches!(dir, Direction::Left));
 
         Ok(())
     }
@@ -342,11 +790,138 @@ where
         self.write_byte(data as u8).await
     }
 
+    /// Define a custom character in one of the eight CGRAM slots. Each entry
+    /// of `bitmap` is one row of the 5x8 glyph, from top to bottom, using the
+    /// lower 5 bits with bit 4 being the leftmost pixel.
+    ///
+    /// The glyph is displayed by writing the slot number as a byte. The
+    /// cursor is moved back to where it was afterwards.
+    ///
+    /// If the future is dropped, only some rows of the glyph may be stored.
+    ///
+    /// ```rust,ignore
+    /// lcd.set_custom_char(0, &[0x00, 0x0A, 0x0A, 0x00, 0x11, 0x0E, 0x00, 0x00]).await?;
+    /// lcd.write_byte(0).await?; // prints a smiley
+    /// ```
+    pub async fn set_custom_char(&mut self, slot: u8, bitmap: &[u8; 8]) -> Result<()> {
+        if slot > 7 {
+            return Err(Error::InvalidArgument);
+        }
+
+        // Fill the glyph in the direction the address counter moves
+        match self.entry_mode.cursor_mode {
+            CursorMode::Increment => {
+                self.write_command(0b0100_0000 | (slot << 3)).await?;
+
+                for &row in bitmap {
+                    self.write_data(row & 0b0001_1111).await?;
+                }
+            }
+            CursorMode::Decrement => {
+                self.write_command(0b0100_0000 | (slot << 3) | 0b111)
+                    .await?;
+
+                for &row in bitmap.iter().rev() {
+                    self.write_data(row & 0b0001_1111).await?;
+                }
+            }
+        }
+
+        for (shadow, &row) in self.shadow.cgram[slot as usize].iter_mut().zip(bitmap) {
+            *shadow = row & 0b0001_1111;
+        }
+
+        self.set_cursor_pos(self.counter.address).await
+    }
+
+    /// Send a byte to the bus, resynchronizing first if the last transfer
+    /// was interrupted
+    async fn send(&mut self, byte: u8, data: bool) -> Result<()> {
+        self.settle().await;
+
+        if self.in_transfer {
+            self.resync().await?;
+        }
+
+        self.in_transfer = true;
+        self.bus.write(byte, data).await?;
+        self.in_transfer = false;
+
+        Ok(())
+    }
+
+    /// Read a byte from the bus, resynchronizing first if the last transfer
+    /// was interrupted
+    async fn receive(&mut self, data: bool) -> Result<u8> {
+        self.settle().await;
+
+        if self.in_transfer {
+            self.resync().await?;
+        }
+
+        self.in_transfer = true;
+        let byte = self.bus.read(data).await?;
+        self.in_transfer = false;
+
+        Ok(byte)
+    }
+
+    /// Wait until the last byte sent has been executed. If the future is
+    /// dropped meanwhile, the wait is done again before the next byte.
+    async fn settle(&mut self) {
+        if self.settle_us > 0 {
+            self.delay_us(self.settle_us).await;
+            self.settle_us = 0;
+        }
+    }
+
+    /// Bring the interface back in step with the `HD44780` and restore its
+    /// modes and cursor. `in_transfer` stays set until this completes.
+    async fn resync(&mut self) -> Result<()> {
+        self.cache.invalidate();
+
+        if self.function_set & 0b0001_0000 == 0 {
+            // Whether or not the first nibble completes a half sent byte, the
+            // nibbles 0x3, 0x3, 0x3 switch to 8-bit mode, and 0x2 back to 4-bit
+            self.bus.write(0x33, false).await?;
+            self.delay_ms(5).await;
+
+            self.bus.write(0x32, false).await?;
+            self.delay_ms(5).await;
+        }
+
+        for cmd in [
+            self.function_set,
+            self.display_mode.as_byte(),
+            self.entry_mode.as_byte(),
+            0b1000_0000 | self.counter.address,
+        ] {
+            self.bus.write(cmd, false).await?;
+            self.delay_us(100).await;
+        }
+
+        Ok(())
+    }
+
+    /// Send an instruction, unless it wouldn't change anything
     async fn write_command(&mut self, cmd: u8) -> Result<()> {
-        self.bus.write(cmd, false).await?;
+        if self.cache.is_redundant(cmd, self.counter.address) {
+            return Ok(());
+        }
+
+        self.send_command(cmd).await
+    }
+
+    /// Send an instruction, even if it was just sent
+    ///
+    /// Returns as soon as the instruction is sent, so callers update their
+    /// models of the display before the next await point. The wait for it to
+    /// be processed comes before the next byte.
+    async fn send_command(&mut self, cmd: u8) -> Result<()> {
+        self.send(cmd, false).await?;
+        self.cache.record(cmd);
+        self.settle_us = 100;
 
-        // Wait for the command to be processed
-        self.delay_us(100).await;
         Ok(())
     }
 
@@ -355,41 +930,41 @@ where
         self.delay_ms(15).await;
 
         // Initialize Lcd in 4-bit mode
-        self.bus.write(0x33, false).await?;
+        self.send(0x33, false).await?;
 
         // Wait for the command to be processed
         self.delay_ms(5).await;
 
         // Sets 4-bit operation and enables 5x7 mode for chars
-        self.bus.write(0x32, false).await?;
+        self.send(0x32, false).await?;
 
         // Wait for the command to be processed
         self.delay_ms(5).await;
 
-        self.bus.write(0x28, false).await?;
+        self.send(self.function_set, false).await?;
 
         // Wait for the command to be processed
         self.delay_ms(5).await;
 
         // Clear Display
-        self.bus.write(0x0E, false).await?;
+        self.send(0x0E, false).await?;
 
         // Wait for the command to be processed
         self.delay_ms(5).await;
 
         // Move the cursor to beginning of first line
-        self.bus.write(0x01, false).await?;
+        self.send(0x01, false).await?;
 
         // Wait for the command to be processed
         self.delay_ms(5).await;
 
         // Set entry mode
-        self.bus.write(self.entry_mode.as_byte(), false).await?;
+        self.send(self.entry_mode.as_byte(), false).await?;
 
         // Wait for the command to be processed
         self.delay_ms(5).await;
 
-        self.bus.write(0x80, false).await?;
+        self.send(0x80, false).await?;
 
         // Wait for the command to be processed
         self.delay_ms(5).await;
@@ -403,36 +978,36 @@ where
         self.delay_ms(15).await;
 
         // Initialize Lcd in 8-bit mode
-        self.bus.write(0b0011_0000, false).await?;
+        self.send(0b0011_0000, false).await?;
 
         // Wait for the command to be processed
         self.delay_ms(5).await;
 
         // Sets 8-bit operation and enables 5x7 mode for chars
-        self.bus.write(0b0011_1000, false).await?;
+        self.send(self.function_set, false).await?;
 
         // Wait for the command to be processed
         self.delay_us(100).await;
 
-        self.bus.write(0b0000_1110, false).await?;
+        self.send(0b0000_1110, false).await?;
 
         // Wait for the command to be processed
         self.delay_us(100).await;
 
         // Clear Display
-        self.bus.write(0b0000_0001, false).await?;
+        self.send(0b0000_0001, false).await?;
 
         // Wait for the command to be processed
         self.delay_us(100).await;
 
         // Move the cursor to beginning of first line
-        self.bus.write(0b000_0111, false).await?;
+        self.send(0b000_0111, false).await?;
 
         // Wait for the command to be processed
         self.delay_us(100).await;
 
         // Set entry mode
-        self.bus.write(self.entry_mode.as_byte(), false).await?;
+        self.send(self.entry_mode.as_byte(), false).await?;
 
         // Wait for the command to be processed
         self.delay_us(100).await;
@@ -444,6 +1019,8 @@ where
     /// make sure the characters in the string fit in a normal `u8`. See the documentation on
     /// [write_byte](#method.write_byte) for more details on compatibility.
     ///
+    /// If the future is dropped, a prefix of the string has been written.
+    ///
     /// ```rust,ignore
     /// lcd.write_str("Hello, World!")?;
     /// ```
@@ -454,6 +1031,8 @@ where
     /// Writes a sequence of bytes to the HD44780. See the documentation on the
     /// [write_byte](#method.write_byte) function for more details about compatibility.
     ///
+    /// If the future is dropped, a prefix of the bytes has been written.
+    ///
     /// ```rust,ignore
     /// lcd.write_bytes(b"Hello, World!")?;
     /// ```
@@ -479,10 +1058,21 @@ where
     /// lcd.write_byte(b'\x7f')?; // usually prints 🡠
     /// ```
     pub async fn write_byte(&mut self, data: u8) -> Result<()> {
-        self.bus.write(data, true).await?;
+        self.write_data(data).await?;
 
-        // Wait for the command to be processed
-        self.delay_us(100).await;
+        self.shadow.write(self.counter.address, data);
+        self.counter.write(&self.entry_mode);
+
+        Ok(())
+    }
+
+    /// Send a byte to whichever of DDRAM or CGRAM is being addressed,
+    /// without following it in the models of the display. Like
+    /// `send_command`, the wait for it to be processed comes before the next
+    /// byte.
+    async fn write_data(&mut self, data: u8) -> Result<()> {
+        self.send(data, true).await?;
+        self.settle_us = 100;
 
         Ok(())
     }
@@ -506,3 +1096,226 @@ where
 //        Ok(())
 //    }
 //}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    use core::convert::Infallible;
+    use core::future::{ready, Future, Ready};
+    use core::pin::Pin;
+    use core::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+    /// A delay whose futures are pending once before they complete, like a
+    /// timer that hasn't expired yet
+    #[derive(Clone)]
+    struct YieldDelay;
+
+    struct Yield(bool);
+
+    impl Future for Yield {
+        type Output = core::result::Result<(), Infallible>;
+
+        fn poll(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Self::Output> {
+            if self.0 {
+                Poll::Ready(Ok(()))
+            } else {
+                self.0 = true;
+                Poll::Pending
+            }
+        }
+    }
+
+    impl DelayUs for YieldDelay {
+        type Error = Infallible;
+
+        type DelayUsFuture<'a>
+            = Yield
+        where
+            Self: 'a;
+
+        fn delay_us(&mut self, _us: u32) -> Self::DelayUsFuture<'_> {
+            Yield(false)
+        }
+
+        type DelayMsFuture<'a>
+            = Yield
+        where
+            Self: 'a;
+
+        fn delay_ms(&mut self, _ms: u32) -> Self::DelayMsFuture<'_> {
+            Yield(false)
+        }
+    }
+
+    /// Records everything written, as (byte, data) pairs
+    #[derive(Default)]
+    struct Recorder {
+        written: [(u8, bool); 16],
+        len: usize,
+    }
+
+    impl DataBus for Recorder {
+        type WriteFuture<'a>
+            = Ready<Result<()>>
+        where
+            Self: 'a;
+
+        fn write<'a>(&'a mut self, byte: u8, data: bool) -> Self::WriteFuture<'a> {
+            self.written[self.len] = (byte, data);
+            self.len += 1;
+            ready(Ok(()))
+        }
+    }
+
+    fn waker() -> Waker {
+        fn clone(_: *const ()) -> RawWaker {
+            RawWaker::new(core::ptr::null(), &VTABLE)
+        }
+        fn noop(_: *const ()) {}
+
+        static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+
+        unsafe { Waker::from_raw(clone(core::ptr::null())) }
+    }
+
+    fn poll_once<F: Future>(future: Pin<&mut F>) -> Poll<F::Output> {
+        future.poll(&mut Context::from_waker(&waker()))
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = future;
+        // The future isn't moved again while it is polled
+        let mut future = unsafe { Pin::new_unchecked(&mut future) };
+
+        loop {
+            if let Poll::Ready(output) = poll_once(future.as_mut()) {
+                return output;
+            }
+        }
+    }
+
+    #[test]
+    fn keeps_models_when_dropped_while_waiting() {
+        let mut lcd = HD44780::from_bus(Recorder::default(), YieldDelay, 0b0010_1000);
+
+        block_on(lcd.set_cursor_pos(0x10)).unwrap();
+        block_on(lcd.write_byte(b'a')).unwrap();
+
+        // Dropped while waiting for the `a` to be executed, before sending
+        {
+            let mut write = lcd.write_byte(b'b');
+            let write = unsafe { Pin::new_unchecked(&mut write) };
+            assert!(poll_once(write).is_pending());
+        }
+
+        assert_eq!(lcd.cursor_pos(), 0x11);
+
+        // The cursor is already there, so this is rightly skipped
+        block_on(lcd.set_cursor_pos(0x11)).unwrap();
+        block_on(lcd.write_byte(b'c')).unwrap();
+
+        let (bus, _) = lcd.release();
+
+        assert_eq!(
+            bus.written[..bus.len],
+            [(0b1001_0000, false), (b'a', true), (b'c', true)]
+        );
+    }
+
+    /// Records the nibbles of each byte, as (nibble, data) pairs, and is
+    /// pending between the two halves like a 4-bit bus waiting on its pins
+    #[derive(Default)]
+    struct NibbleBus {
+        written: [(u8, bool); 32],
+        len: usize,
+    }
+
+    impl NibbleBus {
+        fn push(&mut self, nibble: u8, data: bool) {
+            self.written[self.len] = (nibble, data);
+            self.len += 1;
+        }
+    }
+
+    struct Nibbles<'a> {
+        bus: &'a mut NibbleBus,
+        byte: u8,
+        data: bool,
+        halfway: bool,
+    }
+
+    impl Future for Nibbles<'_> {
+        type Output = Result<()>;
+
+        fn poll(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Self::Output> {
+            let (byte, data) = (self.byte, self.data);
+
+            if self.halfway {
+                self.bus.push(byte & 0x0F, data);
+                Poll::Ready(Ok(()))
+            } else {
+                self.bus.push(byte >> 4, data);
+                self.halfway = true;
+                Poll::Pending
+            }
+        }
+    }
+
+    impl DataBus for NibbleBus {
+        type WriteFuture<'a>
+            = Nibbles<'a>
+        where
+            Self: 'a;
+
+        fn write<'a>(&'a mut self, byte: u8, data: bool) -> Self::WriteFuture<'a> {
+            Nibbles {
+                bus: self,
+                byte,
+                data,
+                halfway: false,
+            }
+        }
+    }
+
+    #[test]
+    fn resyncs_after_half_a_byte() {
+        let mut lcd = HD44780::from_bus(NibbleBus::default(), YieldDelay, 0b0010_1000);
+
+        // Dropped between the two nibbles of the `a`
+        {
+            let mut write = lcd.write_str("ab");
+            let write = unsafe { Pin::new_unchecked(&mut write) };
+            assert!(poll_once(write).is_pending());
+        }
+
+        block_on(lcd.write_byte(b'c')).unwrap();
+
+        let (bus, _) = lcd.release();
+        let display_mode = DisplayMode::default().as_byte();
+        let entry_mode = EntryMode::default().as_byte();
+
+        assert_eq!(
+            bus.written[..bus.len],
+            [
+                (0x6, true),
+                // The first nibble completes the half sent byte
+                (0x3, false),
+                (0x3, false),
+                (0x3, false),
+                (0x2, false),
+                (0x2, false),
+                (0x8, false),
+                (display_mode >> 4, false),
+                (display_mode & 0x0F, false),
+                (entry_mode >> 4, false),
+                (entry_mode & 0x0F, false),
+                (0x8, false),
+                (0x0, false),
+                (0x6, true),
+                (0x3, true),
+            ]
+        );
+    }
+}
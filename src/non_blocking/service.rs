@@ -0,0 +1,288 @@
+//! A display task shared by other tasks through a channel
+//!
+//! [`display_task`] owns the driver and a copy of what is shown on the
+//! display. Any number of tasks print through cheap, copyable
+//! [`DisplayHandle`]s, which only send a [`Command`] over a bounded channel.
+//!
+//! The task applies every command waiting in the channel before touching the
+//! display, then rewrites only the cells that changed. Several updates of the
+//! same field in quick succession therefore cost a single write.
+//!
+//! Embassy tasks can't be generic, so the task is started from a small
+//! wrapper naming the concrete driver type:
+//!
+//! ```rust,ignore
+//! static DISPLAY: DisplayChannel<ThreadModeRawMutex, 8> = Channel::new();
+//!
+//! #[embassy_executor::task]
+//! async fn display(lcd: HD44780<I2CBus<I2c, Delay>, Delay>) {
+//!     display_task(lcd, &DISPLAY).await
+//! }
+//!
+//! // In any other task
+//! let display = DisplayHandle::new(&DISPLAY);
+//! display.write_str((0, 1), "Temp 21.5").await;
+//! display.set_backlight(false).await;
+//! ```
+
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_sync::channel::{Channel, Sender};
+use embedded_hal_async::delay::DelayUs;
+
+use crate::error::Result;
+use crate::geometry::Geometry;
+use crate::non_blocking::bus::DataBus;
+use crate::non_blocking::HD44780;
+
+/// Longest text carried by a single [`Command::Text`]. Longer text is split
+/// over several commands by [`DisplayHandle::write_bytes`].
+pub const MAX_TEXT: usize = 20;
+
+/// Largest display handled by the task, 40 columns by 4 rows
+const COLUMNS: usize = 40;
+const ROWS: usize = 4;
+
+/// A request sent to the display task
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    /// Write the first `len` bytes at `position` (column, row). Text running
+    /// past the end of the row is cut off.
+    Text {
+        position: (u8, u8),
+        len: u8,
+        bytes: [u8; MAX_TEXT],
+    },
+    /// Blank the entire display
+    Clear,
+    /// Turn the backlight on or off
+    Backlight(bool),
+    /// Define a custom character, see
+    /// [`HD44780::set_custom_char`](../struct.HD44780.html#method.set_custom_char)
+    Glyph { slot: u8, bitmap: [u8; 8] },
+}
+
+/// The channel between the handles and the display task, holding up to `N`
+/// commands
+pub type DisplayChannel<M, const N: usize> = Channel<M, Command, N>;
+
+/// Sends commands to the display task. Waits when the channel is full.
+pub struct DisplayHandle<'a, M: RawMutex, const N: usize> {
+    sender: Sender<'a, M, Command, N>,
+}
+
+impl<'a, M: RawMutex, const N: usize> Clone for DisplayHandle<'a, M, N> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, M: RawMutex, const N: usize> Copy for DisplayHandle<'a, M, N> {}
+
+impl<'a, M: RawMutex, const N: usize> DisplayHandle<'a, M, N> {
+    pub fn new(channel: &'a DisplayChannel<M, N>) -> DisplayHandle<'a, M, N> {
+        DisplayHandle {
+            sender: channel.sender(),
+        }
+    }
+
+    /// Write a string at `position` (column, row), see
+    /// [`write_bytes`](#method.write_bytes)
+    pub async fn write_str(&self, position: (u8, u8), text: &str) {
+        self.write_bytes(position, text.as_bytes()).await
+    }
+
+    /// Write bytes at `position` (column, row). Text longer than
+    /// [`MAX_TEXT`] is sent as several commands.
+    pub async fn write_bytes(&self, position: (u8, u8), text: &[u8]) {
+        let (column, row) = position;
+
+        for (i, chunk) in text.chunks(MAX_TEXT).enumerate() {
+            let mut bytes = [b' '; MAX_TEXT];
+            bytes[..chunk.len()].copy_from_slice(chunk);
+
+            let offset = (i * MAX_TEXT).min(u8::MAX as usize) as u8;
+
+            self.send(Command::Text {
+                position: (column.saturating_add(offset), row),
+                len: chunk.len() as u8,
+                bytes,
+            })
+            .await;
+        }
+    }
+
+    /// Blank the entire display
+    pub async fn clear(&self) {
+        self.send(Command::Clear).await
+    }
+
+    /// Turn the backlight on or off
+    pub async fn set_backlight(&self, on: bool) {
+        self.send(Command::Backlight(on)).await
+    }
+
+    /// Define a custom character in one of the eight CGRAM slots
+    pub async fn set_custom_char(&self, slot: u8, bitmap: &[u8; 8]) {
+        self.send(Command::Glyph {
+            slot,
+            bitmap: *bitmap,
+        })
+        .await
+    }
+
+    /// Send any command
+    pub async fn send(&self, command: Command) {
+        self.sender.send(command).await
+    }
+}
+
+/// Serve commands from `channel`, never returns. The display must be
+/// initialized, blank and left in the default entry mode.
+///
+/// A failed write is retried on the next update, and a failed custom
+/// character or backlight change is dropped.
+pub async fn display_task<B, D, M, const N: usize>(
+    mut lcd: HD44780<B, D>,
+    channel: &DisplayChannel<M, N>,
+) where
+    B: DataBus,
+    D: DelayUs,
+    M: RawMutex,
+{
+    let mut shadow = Shadow::new();
+
+    loop {
+        let command = channel.receive().await;
+        apply(&mut lcd, &mut shadow, command).await;
+
+        // Coalesce everything already waiting before touching the display
+        while let Ok(command) = channel.try_receive() {
+            apply(&mut lcd, &mut shadow, command).await;
+        }
+
+        let _ = flush(&mut lcd, &mut shadow).await;
+    }
+}
+
+async fn apply<B: DataBus, D: DelayUs>(
+    lcd: &mut HD44780<B, D>,
+    shadow: &mut Shadow,
+    command: Command,
+) {
+    let geometry = lcd.geometry();
+
+    match command {
+        Command::Text {
+            position,
+            len,
+            bytes,
+        } => shadow.put(geometry, position, &bytes[..(len as usize).min(MAX_TEXT)]),
+        Command::Clear => shadow.fill(geometry, b' '),
+        Command::Backlight(on) => {
+            let _ = lcd.set_backlight(on).await;
+        }
+        Command::Glyph { slot, bitmap } => {
+            let _ = lcd.set_custom_char(slot, &bitmap).await;
+        }
+    }
+}
+
+async fn flush<B: DataBus, D: DelayUs>(lcd: &mut HD44780<B, D>, shadow: &mut Shadow) -> Result<()> {
+    for row in 0..ROWS {
+        if let Some((start, end)) = shadow.dirty[row] {
+            lcd.set_cursor_xy((start as u8, row as u8)).await?;
+            lcd.write_bytes(&shadow.cells[row][start..end]).await?;
+
+            shadow.dirty[row] = None;
+        }
+    }
+
+    Ok(())
+}
+
+/// What the display should show, and which part of each row differs from
+/// what it shows now
+struct Shadow {
+    cells: [[u8; COLUMNS]; ROWS],
+    /// Range of columns to rewrite in each row
+    dirty: [Option<(usize, usize)>; ROWS],
+}
+
+impl Shadow {
+    fn new() -> Shadow {
+        Shadow {
+            cells: [[b' '; COLUMNS]; ROWS],
+            dirty: [None; ROWS],
+        }
+    }
+
+    fn put(&mut self, geometry: Geometry, position: (u8, u8), bytes: &[u8]) {
+        let (column, row) = (position.0 as usize, position.1 as usize);
+        let columns = (geometry.columns as usize).min(COLUMNS);
+
+        if row >= (geometry.rows as usize).min(ROWS) {
+            return;
+        }
+
+        for (i, &byte) in bytes.iter().enumerate() {
+            let column = column + i;
+
+            if column >= columns {
+                break;
+            }
+
+            if self.cells[row][column] != byte {
+                self.cells[row][column] = byte;
+                self.mark(row, column);
+            }
+        }
+    }
+
+    fn fill(&mut self, geometry: Geometry, byte: u8) {
+        let columns = (geometry.columns as usize).min(COLUMNS);
+
+        for row in 0..(geometry.rows as usize).min(ROWS) {
+            self.put(geometry, (0, row as u8), &[byte; COLUMNS][..columns]);
+        }
+    }
+
+    fn mark(&mut self, row: usize, column: usize) {
+        self.dirty[row] = Some(match self.dirty[row] {
+            Some((start, end)) => (start.min(column), end.max(column + 1)),
+            None => (column, column + 1),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn coalesces_changes() {
+        let geometry = Geometry::new(16, 2);
+        let mut shadow = Shadow::new();
+
+        shadow.put(geometry, (2, 0), b"12.0");
+        shadow.put(geometry, (2, 0), b"12.5");
+        assert_eq!(shadow.dirty[0], Some((2, 6)));
+        assert_eq!(&shadow.cells[0][..8], b"  12.5  ");
+
+        shadow.dirty[0] = None;
+        shadow.put(geometry, (2, 0), b"12.7");
+        assert_eq!(shadow.dirty[0], Some((5, 6)));
+    }
+
+    #[test]
+    fn clips_to_geometry() {
+        let geometry = Geometry::new(16, 2);
+        let mut shadow = Shadow::new();
+
+        shadow.put(geometry, (14, 1), b"abcd");
+        shadow.put(geometry, (0, 2), b"hidden");
+
+        assert_eq!(shadow.dirty, [None, Some((14, 16)), None, None]);
+        assert_eq!(shadow.cells[1][16], b' ');
+    }
+}
@@ -0,0 +1,346 @@
+//! A driver that never waits, for firmware built around a superloop
+//!
+//! [`PollDriver`] queues instructions instead of sending them right away.
+//! Each call to [`PollDriver::poll`] sends at most one queued byte to the bus,
+//! and returns `WouldBlock` until the `HD44780` has had time to execute it, so
+//! the rest of the main loop keeps running while the display updates.
+//!
+//! Time is passed in as a free-running microsecond counter, which is allowed
+//! to wrap around.
+//!
+//! ```rust,ignore
+//! let bus = FourBitBus::from_pins(rs, en, d4, d5, d6, d7);
+//! let mut lcd: PollDriver<_, _, 64> = PollDriver::new_4bit(bus, pulse_delay)?;
+//!
+//! lcd.write_str("Hello, world!")?;
+//!
+//! loop {
+//!     let _ = lcd.poll(timer.micros());
+//!     // ... everything else
+//! }
+//! ```
+//!
+//! The bus still pulses its enable pin using the delay it is given, which is
+//! the only time the driver waits. A delay that returns immediately usually
+//! gives a long enough pulse.
+
+use embedded_hal::blocking::delay::{DelayMs, DelayUs};
+
+use crate::bus::DataBus;
+use crate::entry_mode::EntryMode;
+use crate::error::{Error, Result};
+use crate::timing;
+use crate::{DisplayMode, Geometry};
+
+#[derive(Clone, Copy)]
+enum Step {
+    Write { byte: u8, data: bool },
+    Wait { micros: u32 },
+}
+
+pub struct PollDriver<B: DataBus, D: DelayUs<u16> + DelayMs<u8>, const N: usize> {
+    bus: B,
+    delay: D,
+    geometry: Geometry,
+    queue: [Step; N],
+    head: usize,
+    len: usize,
+    /// Start and length of the wait for the last step to finish
+    busy: Option<(u32, u32)>,
+}
+
+impl<B: DataBus, D: DelayUs<u16> + DelayMs<u8>, const N: usize> PollDriver<B, D, N> {
+    /// Create a driver with an empty queue, for a display that is already initialized
+    pub fn new(bus: B, delay: D) -> PollDriver<B, D, N> {
+        PollDriver {
+            bus,
+            delay,
+            geometry: Geometry::default(),
+            queue: [Step::Wait { micros: 0 }; N],
+            head: 0,
+            len: 0,
+            busy: None,
+        }
+    }
+
+    /// Create a driver and queue the initialization sequence of a 4-bit bus,
+    /// such as a `FourBitBus` or an `I2CBus`
+    ///
+    /// Fails with `Error::Full` if the queue is too short to hold it.
+    pub fn new_4bit(bus: B, delay: D) -> Result<PollDriver<B, D, N>> {
+        let mut driver = PollDriver::new(bus, delay);
+
+        driver.push_all(&[
+            Step::Wait {
+                micros: timing::POWER_ON_US,
+            },
+            Step::Write {
+                byte: 0x33,
+                data: false,
+            },
+            Step::Wait {
+                micros: timing::INIT_US,
+            },
+            Step::Write {
+                byte: 0x32,
+                data: false,
+            },
+            Step::Write {
+                byte: 0x28,
+                data: false,
+            },
+            Step::Write {
+                byte: DisplayMode::default().as_byte(),
+                data: false,
+            },
+            Step::Write {
+                byte: 0x01,
+                data: false,
+            },
+            Step::Write {
+                byte: EntryMode::default().as_byte(),
+                data: false,
+            },
+        ])?;
+
+        Ok(driver)
+    }
+
+    /// Create a driver and queue the initialization sequence of an `EightBitBus`
+    ///
+    /// Fails with `Error::Full` if the queue is too short to hold it.
+    pub fn new_8bit(bus: B, delay: D) -> Result<PollDriver<B, D, N>> {
+        let mut driver = PollDriver::new(bus, delay);
+
+        driver.push_all(&[
+            Step::Wait {
+                micros: timing::POWER_ON_US,
+            },
+            Step::Write {
+                byte: 0b0011_0000,
+                data: false,
+            },
+            Step::Wait {
+                micros: timing::INIT_US,
+            },
+            Step::Write {
+                byte: 0b0011_1000,
+                data: false,
+            },
+            Step::Write {
+                byte: DisplayMode::default().as_byte(),
+                data: false,
+            },
+            Step::Write {
+                byte: 0x01,
+                data: false,
+            },
+            Step::Write {
+                byte: EntryMode::default().as_byte(),
+                data: false,
+            },
+        ])?;
+
+        Ok(driver)
+    }
+
+    /// Make progress on the queue, where `now` is the current time in
+    /// microseconds. Returns `Ok` once the queue is empty and the last
+    /// instruction has been executed, and `WouldBlock` until then.
+    pub fn poll(&mut self, now: u32) -> nb::Result<(), Error> {
+        if let Some((start, micros)) = self.busy {
+            if now.wrapping_sub(start) < micros {
+                return Err(nb::Error::WouldBlock);
+            }
+
+            self.busy = None;
+        }
+
+        let step = match self.pop() {
+            Some(step) => step,
+            None => return Ok(()),
+        };
+
+        let micros = match step {
+            Step::Write { byte, data } => {
+                self.bus.write(byte, data, &mut self.delay)?;
+                timing::execution_time_us(byte, data)
+            }
+            Step::Wait { micros } => micros,
+        };
+
+        self.busy = Some((now, micros));
+
+        Err(nb::Error::WouldBlock)
+    }
+
+    /// Whether everything queued has been sent and executed
+    pub fn is_idle(&self) -> bool {
+        self.len == 0 && self.busy.is_none()
+    }
+
+    /// Number of bytes that can still be queued
+    pub fn free(&self) -> usize {
+        N - self.len
+    }
+
+    /// Give back the bus and the delay, dropping anything still queued
+    pub fn release(self) -> (B, D) {
+        (self.bus, self.delay)
+    }
+
+    /// Set the number of columns and rows of the display, see
+    /// [`HD44780::set_geometry`](../struct.HD44780.html#method.set_geometry)
+    pub fn set_geometry(&mut self, geometry: Geometry) {
+        self.geometry = geometry;
+    }
+
+    /// Queue an instruction
+    pub fn write_command(&mut self, cmd: u8) -> Result<()> {
+        self.push_all(&[Step::Write {
+            byte: cmd,
+            data: false,
+        }])
+    }
+
+    /// Queue a byte to be written at the cursor
+    pub fn write_byte(&mut self, data: u8) -> Result<()> {
+        self.push_all(&[Step::Write {
+            byte: data,
+            data: true,
+        }])
+    }
+
+    /// Queue a sequence of bytes. Fails without queuing anything if they don't
+    /// all fit in the queue.
+    pub fn write_bytes(&mut self, string: &[u8]) -> Result<()> {
+        if string.len() > self.free() {
+            return Err(Error::Full);
+        }
+
+        for &b in string {
+            self.write_byte(b)?;
+        }
+
+        Ok(())
+    }
+
+    /// Queue a string, see [`write_bytes`](#method.write_bytes)
+    pub fn write_str(&mut self, string: &str) -> Result<()> {
+        self.write_bytes(string.as_bytes())
+    }
+
+    /// Queue clearing the entire display
+    pub fn clear(&mut self) -> Result<()> {
+        self.write_command(0b0000_0001)
+    }
+
+    /// Queue unshifting the display and moving the cursor to 0
+    pub fn reset(&mut self) -> Result<()> {
+        self.write_command(0b0000_0010)
+    }
+
+    /// Queue setting the display, cursor and blink modes
+    pub fn set_display_mode(&mut self, display_mode: DisplayMode) -> Result<()> {
+        self.write_command(display_mode.as_byte())
+    }
+
+    /// Queue moving the cursor to a DDRAM address
+    pub fn set_cursor_pos(&mut self, position: u8) -> Result<()> {
+        self.write_command(0b1000_0000 | (position & 0b0111_1111))
+    }
+
+    /// Queue moving the cursor to `position` (column, row)
+    pub fn set_cursor_xy(&mut self, position: (u8, u8)) -> Result<()> {
+        self.set_cursor_pos(self.geometry.address(position))
+    }
+
+    /// Queue defining a custom character, see
+    /// [`HD44780::set_custom_char`](../struct.HD44780.html#method.set_custom_char)
+    pub fn set_custom_char(&mut self, slot: u8, bitmap: &[u8; 8]) -> Result<()> {
+        if slot > 7 {
+            return Err(Error::InvalidArgument);
+        }
+
+        if self.free() < 9 {
+            return Err(Error::Full);
+        }
+
+        self.write_command(0b0100_0000 | (slot << 3))?;
+
+        for &row in bitmap {
+            self.write_byte(row & 0b0001_1111)?;
+        }
+
+        Ok(())
+    }
+
+    fn push_all(&mut self, steps: &[Step]) -> Result<()> {
+        if steps.len() > self.free() {
+            return Err(Error::Full);
+        }
+
+        for &step in steps {
+            self.queue[(self.head + self.len) % N] = step;
+            self.len += 1;
+        }
+
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Option<Step> {
+        if self.len == 0 {
+            return None;
+        }
+
+        let step = self.queue[self.head];
+        self.head = (self.head + 1) % N;
+        self.len -= 1;
+
+        Some(step)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    use crate::mock::{NoDelay, Recorder};
+
+    #[test]
+    fn rejects_short_queue() {
+        assert!(PollDriver::<_, _, 8>::new_4bit(Recorder::default(), NoDelay).is_ok());
+        assert_eq!(
+            PollDriver::<_, _, 4>::new_4bit(Recorder::default(), NoDelay).err(),
+            Some(Error::Full)
+        );
+    }
+
+    #[test]
+    fn waits_for_execution() {
+        let mut lcd: PollDriver<_, _, 4> = PollDriver::new(Recorder::default(), NoDelay);
+
+        lcd.clear().unwrap();
+        lcd.write_str("ab").unwrap();
+        assert!(lcd.write_str("cd").is_err());
+
+        assert_eq!(lcd.poll(u32::MAX - 100), Err(nb::Error::WouldBlock));
+        assert_eq!(lcd.bus.len, 1);
+
+        // Clearing takes longer than 100 us, even when the counter wraps
+        assert_eq!(lcd.poll(100), Err(nb::Error::WouldBlock));
+        assert_eq!(lcd.bus.len, 1);
+
+        assert_eq!(lcd.poll(2_000), Err(nb::Error::WouldBlock));
+        assert_eq!(lcd.poll(2_050), Err(nb::Error::WouldBlock));
+        assert_eq!(lcd.poll(2_100), Err(nb::Error::WouldBlock));
+        assert_eq!(lcd.poll(2_200), Ok(()));
+
+        assert!(lcd.is_idle());
+        assert_eq!(
+            lcd.bus.written[..3],
+            [(0x01, false), (b'a', true), (b'b', true)]
+        );
+    }
+}
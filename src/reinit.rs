@@ -0,0 +1,68 @@
+//! Running [`HD44780::reinit`](../struct.HD44780.html#method.reinit) on a
+//! schedule
+//!
+//! Electrical noise can scramble or blank a display without the driver
+//! noticing. Re-initializing it every so often from the main loop puts it
+//! back in order without a power cycle:
+//!
+//! ```rust,ignore
+//! let mut schedule = ReinitSchedule::new(60_000);
+//!
+//! loop {
+//!     lcd.reinit_if_due(&mut schedule, clock.millis(), &mut delay)?;
+//!     // ... everything else
+//! }
+//! ```
+//!
+//! Time is passed in as a free-running millisecond counter, which is allowed
+//! to wrap around.
+
+/// Tells when to re-initialize the display next
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReinitSchedule {
+    interval_ms: u32,
+    last: Option<u32>,
+}
+
+impl ReinitSchedule {
+    /// Re-initialize every `interval_ms` milliseconds
+    pub const fn new(interval_ms: u32) -> ReinitSchedule {
+        ReinitSchedule {
+            interval_ms,
+            last: None,
+        }
+    }
+
+    /// Whether the display is due to be re-initialized at `now`. The first
+    /// call only starts the schedule, and returns `false`.
+    pub fn is_due(&mut self, now: u32) -> bool {
+        match self.last {
+            Some(last) if now.wrapping_sub(last) < self.interval_ms => false,
+            Some(_) => {
+                self.last = Some(now);
+                true
+            }
+            None => {
+                self.last = Some(now);
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn fires_each_interval() {
+        let mut schedule = ReinitSchedule::new(1_000);
+
+        assert!(!schedule.is_due(u32::MAX - 100));
+        assert!(!schedule.is_due(800));
+        assert!(schedule.is_due(900));
+        assert!(!schedule.is_due(1_800));
+        assert!(schedule.is_due(1_900));
+    }
+}
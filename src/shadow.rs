@@ -0,0 +1,97 @@
+//! A copy of the DDRAM and CGRAM contents of the `HD44780`, kept up to date
+//! by the drivers so that the display can be restored after it loses them
+
+/// Characters in each of the two DDRAM lines
+const LINE_LENGTH: usize = 40;
+const SECOND_LINE: u8 = 0x40;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Shadow {
+    /// Both DDRAM lines, one after the other
+    pub ddram: [u8; 2 * LINE_LENGTH],
+    /// The eight glyphs, one row per byte
+    pub cgram: [[u8; 8]; 8],
+}
+
+impl Default for Shadow {
+    fn default() -> Shadow {
+        Shadow {
+            ddram: [b' '; 2 * LINE_LENGTH],
+            cgram: [[0; 8]; 8],
+        }
+    }
+}
+
+impl Shadow {
+    /// Follow a byte written to DDRAM at `address`
+    pub fn write(&mut self, address: u8, byte: u8) {
+        if let Some(index) = Shadow::index(address) {
+            self.ddram[index] = byte;
+        }
+    }
+
+    /// The byte at DDRAM `address`, or a space for addresses outside DDRAM
+    pub fn get(&self, address: u8) -> u8 {
+        Shadow::index(address).map_or(b' ', |index| self.ddram[index])
+    }
+
+    /// Follow clearing the display
+    pub fn clear(&mut self) {
+        self.ddram = [b' '; 2 * LINE_LENGTH];
+    }
+
+    /// The DDRAM contents of the line starting at `address`, either `0x00`
+    /// or `0x40`
+    pub fn line(&self, address: u8) -> &[u8] {
+        let start = if address < SECOND_LINE {
+            0
+        } else {
+            LINE_LENGTH
+        };
+
+        &self.ddram[start..start + LINE_LENGTH]
+    }
+
+    /// See [`line`](#method.line)
+    pub fn line_mut(&mut self, address: u8) -> &mut [u8] {
+        let start = if address < SECOND_LINE {
+            0
+        } else {
+            LINE_LENGTH
+        };
+
+        &mut self.ddram[start..start + LINE_LENGTH]
+    }
+
+    fn index(address: u8) -> Option<usize> {
+        let address = address as usize;
+        let second = SECOND_LINE as usize;
+
+        if address < LINE_LENGTH {
+            Some(address)
+        } else if (second..second + LINE_LENGTH).contains(&address) {
+            Some(address - second + LINE_LENGTH)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn maps_lines() {
+        let mut shadow = Shadow::default();
+
+        shadow.write(0x00, b'a');
+        shadow.write(0x41, b'b');
+        shadow.write(0x30, b'x');
+
+        assert_eq!(shadow.line(0x00)[0], b'a');
+        assert_eq!(shadow.line(0x40)[..2], *b" b");
+        assert!(!shadow.ddram.contains(&b'x'));
+    }
+}
@@ -0,0 +1,369 @@
+//! A simulated `HD44780`, for running code written against the driver on a
+//! computer without a display
+//!
+//! [`SimBus`] carries out instructions and data writes the way the
+//! controller does, and shows what the display would. Clones share the same
+//! simulated display, so one can be handed to the driver while another is
+//! kept to look at it.
+//!
+//! ```rust,ignore
+//! let sim = SimBus::new();
+//! let mut lcd = HD44780::new_simulated(sim.clone(), &mut NoDelay)?;
+//!
+//! lcd.write_str("Hello", &mut NoDelay)?;
+//! assert_eq!(sim.text(Geometry::new(16, 2))[0], "Hello           ");
+//! ```
+
+use std::string::String;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::vec::Vec;
+
+use embedded_hal::blocking::delay::{DelayMs, DelayUs};
+
+use crate::bus::{DataBus, ReadableBus};
+use crate::error::Result;
+use crate::geometry::Geometry;
+
+pub use crate::timing::NoDelay;
+
+/// Characters in each DDRAM line
+const LINE_LENGTH: u8 = 40;
+const SECOND_LINE: u8 = 0x40;
+
+/// The state of the simulated controller
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Controller {
+    ddram: [u8; 128],
+    cgram: [u8; 64],
+    /// Address counter
+    address: u8,
+    /// Whether the address counter points into CGRAM
+    cgram_selected: bool,
+    increment: bool,
+    shift_on_write: bool,
+    display_on: bool,
+    cursor_on: bool,
+    blink_on: bool,
+    /// Positions the display is shifted to the left
+    shift: u8,
+    backlight: bool,
+}
+
+impl Default for Controller {
+    fn default() -> Controller {
+        Controller {
+            ddram: [b' '; 128],
+            cgram: [0; 64],
+            address: 0,
+            cgram_selected: false,
+            increment: true,
+            shift_on_write: false,
+            display_on: false,
+            cursor_on: false,
+            blink_on: false,
+            shift: 0,
+            backlight: true,
+        }
+    }
+}
+
+impl Controller {
+    fn instruction(&mut self, byte: u8) {
+        match byte.leading_zeros() {
+            0 => {
+                self.cgram_selected = false;
+                self.address = byte & 0b0111_1111;
+            }
+            1 => {
+                self.cgram_selected = true;
+                self.address = byte & 0b0011_1111;
+            }
+            // Function set, the simulation is always in two line mode
+            2 => {}
+            3 => {
+                let right = byte & 0b0000_0100 != 0;
+
+                if byte & 0b0000_1000 != 0 {
+                    self.shift_display(!right);
+                } else {
+                    self.step(right);
+                }
+            }
+            4 => {
+                self.display_on = byte & 0b0000_0100 != 0;
+                self.cursor_on = byte & 0b0000_0010 != 0;
+                self.blink_on = byte & 0b0000_0001 != 0;
+            }
+            5 => {
+                self.increment = byte & 0b0000_0010 != 0;
+                self.shift_on_write = byte & 0b0000_0001 != 0;
+            }
+            6 => {
+                self.cgram_selected = false;
+                self.address = 0;
+                self.shift = 0;
+            }
+            7 => {
+                self.ddram = [b' '; 128];
+                self.cgram_selected = false;
+                self.address = 0;
+                self.shift = 0;
+                self.increment = true;
+            }
+            _ => {}
+        }
+    }
+
+    fn write(&mut self, byte: u8) {
+        if self.cgram_selected {
+            self.cgram[self.address as usize] = byte & 0b0001_1111;
+        } else {
+            self.ddram[self.address as usize] = byte;
+
+            if self.shift_on_write {
+                self.shift_display(self.increment);
+            }
+        }
+
+        self.step(self.increment);
+    }
+
+    fn read(&mut self) -> u8 {
+        let byte = if self.cgram_selected {
+            self.cgram[self.address as usize]
+        } else {
+            self.ddram[self.address as usize]
+        };
+
+        self.step(self.increment);
+        byte
+    }
+
+    /// Move the address counter by one, within the memory selected
+    fn step(&mut self, forward: bool) {
+        if self.cgram_selected {
+            self.address = if forward {
+                self.address.wrapping_add(1)
+            } else {
+                self.address.wrapping_sub(1)
+            } & 0b0011_1111;
+            return;
+        }
+
+        let line = self.address & SECOND_LINE;
+        let offset = (self.address & !SECOND_LINE).min(LINE_LENGTH - 1);
+
+        self.address = match (forward, offset) {
+            (true, offset) if offset == LINE_LENGTH - 1 => line ^ SECOND_LINE,
+            (true, offset) => line | (offset + 1),
+            (false, 0) => (line ^ SECOND_LINE) | (LINE_LENGTH - 1),
+            (false, offset) => line | (offset - 1),
+        };
+    }
+
+    fn shift_display(&mut self, left: bool) {
+        self.shift = if left {
+            (self.shift + 1) % LINE_LENGTH
+        } else {
+            (self.shift + LINE_LENGTH - 1) % LINE_LENGTH
+        };
+    }
+
+    /// DDRAM address shown at `position` (column, row)
+    fn shown_address(&self, geometry: Geometry, position: (u8, u8)) -> u8 {
+        let start = geometry.address((0, position.1));
+        let line = start & SECOND_LINE;
+        let offset = ((start & !SECOND_LINE) + position.0 + self.shift) % LINE_LENGTH;
+
+        line | offset
+    }
+}
+
+/// A bus to a simulated display, see the [module documentation](index.html)
+#[derive(Debug, Clone, Default)]
+pub struct SimBus {
+    controller: Arc<Mutex<Controller>>,
+}
+
+impl SimBus {
+    /// A display that was just powered up
+    pub fn new() -> SimBus {
+        SimBus::default()
+    }
+
+    /// The rows of the display as text. Custom characters are shown as `*`,
+    /// the solid block `0xFF` as `#`, and other bytes outside of ASCII as
+    /// `?`. A display that is switched off shows spaces.
+    pub fn text(&self, geometry: Geometry) -> Vec<String> {
+        let controller = self.controller();
+
+        (0..geometry.rows)
+            .map(|row| {
+                (0..geometry.columns)
+                    .map(|column| {
+                        let address = controller.shown_address(geometry, (column, row));
+
+                        match controller.ddram[address as usize] {
+                            _ if !controller.display_on => ' ',
+                            byte @ 0x20..=0x7E => byte as char,
+                            0x00..=0x0F => '*',
+                            0xFF => '#',
+                            _ => '?',
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// The (column, row) position of the cursor, if it is shown and on
+    /// screen
+    pub fn cursor(&self, geometry: Geometry) -> Option<(u8, u8)> {
+        let controller = self.controller();
+
+        if !controller.display_on || !(controller.cursor_on || controller.blink_on) {
+            return None;
+        }
+
+        (0..geometry.rows)
+            .flat_map(|row| (0..geometry.columns).map(move |column| (column, row)))
+            .find(|&position| controller.shown_address(geometry, position) == controller.address)
+    }
+
+    /// The rows of the custom character in `slot`
+    pub fn custom_char(&self, slot: u8) -> [u8; 8] {
+        let start = (slot as usize & 0b111) << 3;
+        let mut bitmap = [0; 8];
+
+        bitmap.copy_from_slice(&self.controller().cgram[start..start + 8]);
+        bitmap
+    }
+
+    pub fn backlight(&self) -> bool {
+        self.controller().backlight
+    }
+
+    fn controller(&self) -> MutexGuard<'_, Controller> {
+        // A panic while holding the lock can't leave the state inconsistent
+        self.controller
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl DataBus for SimBus {
+    fn write<D: DelayUs<u16> + DelayMs<u8>>(
+        &mut self,
+        byte: u8,
+        data: bool,
+        _delay: &mut D,
+    ) -> Result<()> {
+        let mut controller = self.controller();
+
+        if data {
+            controller.write(byte);
+        } else {
+            controller.instruction(byte);
+        }
+
+        Ok(())
+    }
+
+    fn set_backlight(&mut self, on: bool) {
+        self.controller().backlight = on;
+    }
+}
+
+impl ReadableBus for SimBus {
+    fn read<D: DelayUs<u16> + DelayMs<u8>>(&mut self, data: bool, _delay: &mut D) -> Result<u8> {
+        let mut controller = self.controller();
+
+        // Never busy, the simulation carries out instructions right away
+        Ok(if data {
+            controller.read()
+        } else {
+            controller.address
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::entry_mode::CursorMode;
+    use crate::reinit::ReinitSchedule;
+    use crate::{CursorBlink, Direction, HD44780};
+
+    #[test]
+    fn shows_text() {
+        let sim = SimBus::new();
+        let geometry = Geometry::new(20, 4);
+        let mut lcd = HD44780::new_simulated(sim.clone(), &mut NoDelay).unwrap();
+
+        lcd.write_str("Hello", &mut NoDelay).unwrap();
+        lcd.set_cursor_xy((2, 3), &mut NoDelay).unwrap();
+        lcd.write_str("world", &mut NoDelay).unwrap();
+
+        let text = sim.text(geometry);
+
+        assert_eq!(text[0], "Hello               ");
+        assert_eq!(text[3], "  world             ");
+        assert_eq!(sim.cursor(geometry), Some((7, 3)));
+    }
+
+    #[test]
+    fn reads_back() {
+        let sim = SimBus::new();
+        let mut lcd = HD44780::new_simulated(sim, &mut NoDelay).unwrap();
+
+        lcd.write_str("abc", &mut NoDelay).unwrap();
+        lcd.set_custom_char(2, &[0b1_0101; 8], &mut NoDelay)
+            .unwrap();
+
+        assert_eq!(lcd.read_char((1, 0), &mut NoDelay), Ok(b'b'));
+        assert_eq!(lcd.read_custom_char(2, &mut NoDelay), Ok([0b1_0101; 8]));
+        assert_eq!(lcd.cursor_pos(), 3);
+    }
+
+    #[test]
+    fn reinit_restores_everything() {
+        let sim = SimBus::new();
+        let mut lcd = HD44780::new_simulated(sim.clone(), &mut NoDelay).unwrap();
+
+        lcd.set_custom_char(1, &[0b1_0101; 8], &mut NoDelay)
+            .unwrap();
+        lcd.write_str("Hello", &mut NoDelay).unwrap();
+        lcd.set_cursor_xy((3, 1), &mut NoDelay).unwrap();
+        lcd.write_bytes(b"\x01 world", &mut NoDelay).unwrap();
+        lcd.set_cursor_blink(CursorBlink::Off, &mut NoDelay)
+            .unwrap();
+        lcd.shift_display(Direction::Left, &mut NoDelay).unwrap();
+        lcd.shift_display(Direction::Left, &mut NoDelay).unwrap();
+        lcd.set_cursor_mode(CursorMode::Decrement, &mut NoDelay)
+            .unwrap();
+        lcd.set_cursor_xy((5, 2), &mut NoDelay).unwrap();
+
+        let shown = sim.controller().clone();
+        let scramble = || {
+            HD44780::new_simulated(sim.clone(), &mut NoDelay).unwrap();
+            assert_ne!(*sim.controller(), shown);
+        };
+
+        scramble();
+        lcd.reinit(&mut NoDelay).unwrap();
+        assert_eq!(*sim.controller(), shown);
+
+        // The first call starts the schedule, the next one is early
+        let mut schedule = ReinitSchedule::new(1_000);
+        scramble();
+
+        lcd.reinit_if_due(&mut schedule, 0, &mut NoDelay).unwrap();
+        lcd.reinit_if_due(&mut schedule, 999, &mut NoDelay).unwrap();
+        assert_ne!(*sim.controller(), shown);
+
+        lcd.reinit_if_due(&mut schedule, 1_000, &mut NoDelay)
+            .unwrap();
+        assert_eq!(*sim.controller(), shown);
+    }
+}
@@ -0,0 +1,306 @@
+//! A text terminal that scrolls up when output reaches the bottom row
+//!
+//! The `HD44780` can shift its lines sideways but not scroll them up, which
+//! log-style output needs. A [`Terminal`] keeps the lines in a buffer,
+//! moves them up itself, and on [`flush`](Terminal::flush) redraws only the
+//! cells that differ from what it last drew.
+//!
+//! It understands these control characters:
+//!
+//! - `\n` moves to the start of the next row, scrolling at the bottom
+//! - `\r` moves to the start of the row
+//! - `\t` moves to the next multiple of [`TAB_WIDTH`] columns
+//! - backspace (`\x08`) moves one column left without erasing, so
+//!   `"\x08 \x08"` erases the character before the cursor
+//!
+//! Other control characters are ignored, apart from `0x00..=0x07`, which
+//! show the custom characters. Text wraps at the width of the display.
+//!
+//! ```rust,ignore
+//! let mut terminal = Terminal::new(Geometry::new(20, 4));
+//!
+//! writeln!(terminal, "boot ok")?;
+//! writeln!(terminal, "temp\t{}", 21)?;
+//! terminal.flush(&mut lcd, &mut delay)?;
+//! ```
+
+use core::fmt;
+
+use embedded_hal::blocking::delay::{DelayMs, DelayUs};
+
+use crate::bus::DataBus;
+use crate::error::Result;
+use crate::geometry::Geometry;
+use crate::HD44780;
+
+/// Most columns a terminal can have, the length of a DDRAM line
+const MAX_COLUMNS: usize = 40;
+
+/// Most rows a terminal can have
+const MAX_ROWS: usize = 4;
+
+/// Distance between tab stops
+pub const TAB_WIDTH: u8 = 4;
+
+const BACKSPACE: u8 = 0x08;
+
+/// Highest character code of a custom character
+const LAST_CUSTOM: u8 = 0x07;
+
+/// Which part of a row or of the display to erase, counting from the cursor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Erase {
+    /// From the cursor to the end, the cursor included
+    ToEnd,
+    /// From the start to the cursor, the cursor included
+    ToStart,
+    All,
+}
+
+pub struct Terminal {
+    geometry: Geometry,
+    /// What the display should show
+    lines: [[u8; MAX_COLUMNS]; MAX_ROWS],
+    /// What was last drawn in each row, or `None` for rows not drawn yet.
+    /// No byte can stand for an undrawn cell, as custom character 0 is one.
+    shown: [Option<[u8; MAX_COLUMNS]>; MAX_ROWS],
+    /// Column the next character goes to, equal to the width after the last
+    /// column was written, so a line can be filled without scrolling
+    column: u8,
+    row: u8,
+}
+
+impl Terminal {
+    /// Create an empty terminal covering a display of size `geometry`, up to
+    /// 40 columns and 4 rows
+    pub fn new(geometry: Geometry) -> Terminal {
+        Terminal {
+            geometry: Geometry::new(
+                geometry.columns.clamp(1, MAX_COLUMNS as u8),
+                geometry.rows.clamp(1, MAX_ROWS as u8),
+            ),
+            lines: [[b' '; MAX_COLUMNS]; MAX_ROWS],
+            shown: [None; MAX_ROWS],
+            column: 0,
+            row: 0,
+        }
+    }
+
+    /// Add text to the buffer, without drawing it yet
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.write_byte(byte);
+        }
+    }
+
+    /// Add a single byte to the buffer, without drawing it yet
+    pub fn write_byte(&mut self, byte: u8) {
+        match byte {
+            b'\n' => self.new_line(),
+            b'\r' => self.column = 0,
+            b'\t' => {
+                let stop = (self.column / TAB_WIDTH + 1) * TAB_WIDTH;
+
+                while self.column < stop.min(self.geometry.columns) {
+                    self.put(b' ');
+                }
+            }
+            BACKSPACE => {
+                self.column = self.column.min(self.geometry.columns - 1).saturating_sub(1);
+            }
+            byte if byte <= LAST_CUSTOM || byte >= b' ' => self.put(byte),
+            _ => {}
+        }
+    }
+
+    /// Empty the buffer and move to the top left corner
+    pub fn clear(&mut self) {
+        self.lines = [[b' '; MAX_COLUMNS]; MAX_ROWS];
+        self.column = 0;
+        self.row = 0;
+    }
+
+    /// Move to `position` (column, row), kept within the display
+    pub fn move_to(&mut self, position: (u8, u8)) {
+        let (column, row) = position;
+
+        self.column = column.min(self.geometry.columns - 1);
+        self.row = row.min(self.geometry.rows - 1);
+    }
+
+    /// Erase part of the row the cursor is on, without moving the cursor
+    pub fn erase_line(&mut self, erase: Erase) {
+        let (column, row) = self.cursor();
+        let line = &mut self.lines[row as usize][..self.geometry.columns as usize];
+
+        match erase {
+            Erase::ToEnd => line[column as usize..].fill(b' '),
+            Erase::ToStart => line[..=column as usize].fill(b' '),
+            Erase::All => line.fill(b' '),
+        }
+    }
+
+    /// Erase part of the display, without moving the cursor
+    pub fn erase_display(&mut self, erase: Erase) {
+        let row = self.row as usize;
+
+        match erase {
+            Erase::ToEnd => self.lines[row + 1..].fill([b' '; MAX_COLUMNS]),
+            Erase::ToStart => self.lines[..row].fill([b' '; MAX_COLUMNS]),
+            Erase::All => self.lines = [[b' '; MAX_COLUMNS]; MAX_ROWS],
+        }
+
+        self.erase_line(erase);
+    }
+
+    /// The text of `row`, as wide as the display
+    pub fn line(&self, row: u8) -> &[u8] {
+        &self.lines[row as usize][..self.geometry.columns as usize]
+    }
+
+    /// Position (column, row) the next character goes to
+    pub fn cursor(&self) -> (u8, u8) {
+        (self.column.min(self.geometry.columns - 1), self.row)
+    }
+
+    /// Draw the cells that changed since the last flush, and move the cursor
+    /// of the display to where the next character goes
+    pub fn flush<B: DataBus, D: DelayUs<u16> + DelayMs<u8>>(
+        &mut self,
+        lcd: &mut HD44780<B>,
+        delay: &mut D,
+    ) -> Result<()> {
+        let columns = self.geometry.columns as usize;
+
+        for row in 0..self.geometry.rows as usize {
+            let line = &self.lines[row][..columns];
+            let shown = self.shown[row];
+            let changed = |col: &usize| match shown {
+                Some(shown) => line[*col] != shown[*col],
+                None => true,
+            };
+
+            if let Some(first) = (0..columns).find(changed) {
+                let last = (0..columns).rev().find(changed).unwrap_or(first);
+
+                lcd.set_cursor_xy((first as u8, row as u8), delay)?;
+                lcd.write_bytes(&line[first..=last], delay)?;
+
+                // Remember what is shown even if a later row fails
+                self.shown[row] = Some(self.lines[row]);
+            }
+        }
+
+        lcd.set_cursor_xy(self.cursor(), delay)
+    }
+
+    /// Redraw everything on the next flush, after the display was written to
+    /// by something else
+    pub fn invalidate(&mut self) {
+        self.shown = [None; MAX_ROWS];
+    }
+
+    /// Write `text` and draw it
+    pub fn print<B: DataBus, D: DelayUs<u16> + DelayMs<u8>>(
+        &mut self,
+        lcd: &mut HD44780<B>,
+        text: &str,
+        delay: &mut D,
+    ) -> Result<()> {
+        self.write_bytes(text.as_bytes());
+        self.flush(lcd, delay)
+    }
+
+    fn put(&mut self, byte: u8) {
+        if self.column >= self.geometry.columns {
+            self.new_line();
+        }
+
+        self.lines[self.row as usize][self.column as usize] = byte;
+        self.column += 1;
+    }
+
+    fn new_line(&mut self) {
+        self.column = 0;
+
+        if self.row + 1 < self.geometry.rows {
+            self.row += 1;
+        } else {
+            let rows = self.geometry.rows as usize;
+
+            self.lines.copy_within(1..rows, 0);
+            self.lines[rows - 1] = [b' '; MAX_COLUMNS];
+        }
+    }
+}
+
+impl fmt::Write for Terminal {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_bytes(s.as_bytes());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::mock::{NoDelay, Recorder};
+
+    #[test]
+    fn wraps_and_scrolls() {
+        let mut terminal = Terminal::new(Geometry::new(4, 2));
+
+        terminal.write_bytes(b"abcd\nef\ngh");
+
+        assert_eq!(terminal.line(0), b"ef  ");
+        assert_eq!(terminal.line(1), b"gh  ");
+        assert_eq!(terminal.cursor(), (2, 1));
+
+        terminal.write_bytes(b"ijk");
+
+        assert_eq!(terminal.line(0), b"ghij");
+        assert_eq!(terminal.line(1), b"k   ");
+    }
+
+    #[test]
+    fn handles_controls() {
+        let mut terminal = Terminal::new(Geometry::new(8, 2));
+
+        terminal.write_bytes(b"a\tb\rc\x08\x08d\x1b");
+
+        assert_eq!(terminal.line(0), b"d   b   ");
+        assert_eq!(terminal.cursor(), (1, 0));
+    }
+
+    #[test]
+    fn draws_changes() {
+        let mut lcd = HD44780::from_bus(Recorder::default(), 0b0011_1000);
+        let mut terminal = Terminal::new(Geometry::new(8, 2));
+
+        terminal.print(&mut lcd, "ab", &mut NoDelay).unwrap();
+        terminal.print(&mut lcd, "c", &mut NoDelay).unwrap();
+
+        let bus = lcd.release();
+        let data = bus.written[..bus.len].iter().filter(|(_, data)| *data);
+
+        // Every cell once, then only the new one
+        assert_eq!(data.count(), 16 + 1);
+    }
+
+    #[test]
+    fn draws_custom_character_zero() {
+        let mut lcd = HD44780::from_bus(Recorder::default(), 0b0011_1000);
+        let mut terminal = Terminal::new(Geometry::new(4, 1));
+
+        terminal.print(&mut lcd, "\x00ab", &mut NoDelay).unwrap();
+
+        let bus = lcd.release();
+        let mut data = bus.written[..bus.len].iter().filter(|(_, data)| *data);
+
+        // The custom character is drawn although nothing was shown before
+        for byte in [0x00, b'a', b'b', b' '] {
+            assert_eq!(data.next(), Some(&(byte, true)));
+        }
+    }
+}
@@ -0,0 +1,141 @@
+//! How long the `HD44780` needs to process instructions
+
+use embedded_hal::blocking::delay::{DelayMs, DelayUs};
+
+/// Time to wait after powering up before the first instruction
+pub(crate) const POWER_ON_US: u32 = 15_000;
+
+/// Time to wait after the first function set of the initialization sequence
+pub(crate) const INIT_US: u32 = 5_000;
+
+/// Execution time of clear display and return home
+pub(crate) const LONG_COMMAND_US: u32 = 2_000;
+
+/// Execution time of every other instruction and of data writes
+pub(crate) const COMMAND_US: u32 = 100;
+
+/// Time needed by the `HD44780` to process `byte`, written as data or as an instruction
+pub(crate) fn execution_time_us(byte: u8, data: bool) -> u32 {
+    Timing::default().execution_time_us(byte, data) as u32
+}
+
+/// Fixed times the blocking driver waits after each instruction and data
+/// write, when it doesn't poll the busy flag. Slow displays or long cables
+/// may need longer ones than the datasheet.
+///
+/// ```rust,ignore
+/// lcd.set_timing(Timing {
+///     command_us: 200,
+///     long_command_us: 4_000,
+/// });
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timing {
+    /// Execution time of most instructions and of data writes
+    pub command_us: u16,
+    /// Execution time of clear display and return home
+    pub long_command_us: u16,
+}
+
+impl Default for Timing {
+    /// The times from the datasheet
+    fn default() -> Timing {
+        Timing {
+            command_us: COMMAND_US as u16,
+            long_command_us: LONG_COMMAND_US as u16,
+        }
+    }
+}
+
+impl Timing {
+    /// Time needed to process `byte`, written as data or as an instruction
+    pub fn execution_time_us(&self, byte: u8, data: bool) -> u16 {
+        if !data && byte & 0b1111_1100 == 0 {
+            self.long_command_us
+        } else {
+            self.command_us
+        }
+    }
+}
+
+/// A delay that returns right away, for displays that don't need the
+/// driver to wait, such as a [simulated](../sim/index.html) one
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoDelay;
+
+impl DelayUs<u16> for NoDelay {
+    fn delay_us(&mut self, _us: u16) {}
+}
+
+impl DelayMs<u8> for NoDelay {
+    fn delay_ms(&mut self, _ms: u8) {}
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::mock::{Ram, Recorder};
+    use crate::verify::VerifyPolicy;
+    use crate::HD44780;
+
+    #[test]
+    fn looks_up_times() {
+        let timing = Timing::default();
+
+        assert_eq!(timing.execution_time_us(0b0000_0001, false), 2_000);
+        assert_eq!(timing.execution_time_us(0b0000_0001, true), 100);
+        assert_eq!(timing.execution_time_us(0b1000_0000, false), 100);
+    }
+
+    /// Sums the microseconds waited
+    #[derive(Default)]
+    struct Clock {
+        us: u32,
+    }
+
+    impl DelayUs<u16> for Clock {
+        fn delay_us(&mut self, us: u16) {
+            self.us += us as u32;
+        }
+    }
+
+    impl DelayMs<u8> for Clock {
+        fn delay_ms(&mut self, ms: u8) {
+            self.us += ms as u32 * 1_000;
+        }
+    }
+
+    #[test]
+    fn initializes_with_timing() {
+        let slow = Timing {
+            command_us: 200,
+            long_command_us: 4_000,
+        };
+
+        let mut datasheet = Clock::default();
+        HD44780::new_4bit_bus(Recorder::default(), Timing::default(), &mut datasheet).unwrap();
+
+        let mut clone = Clock::default();
+        HD44780::new_4bit_bus(Recorder::default(), slow, &mut clone).unwrap();
+
+        // Longer waits for five instructions and the clear
+        assert_eq!(clone.us - datasheet.us, 5 * 100 + 2_000);
+    }
+
+    #[test]
+    fn waits_for_reads_as_told() {
+        let mut lcd = HD44780::from_bus(Ram::default(), 0b0011_1000);
+        lcd.set_timing(Timing {
+            command_us: 0,
+            long_command_us: 0,
+        });
+        lcd.set_verify(Some(VerifyPolicy::default()));
+
+        let mut clock = Clock::default();
+        lcd.write_str("ok", &mut clock).unwrap();
+        lcd.read_char((1, 0), &mut clock).unwrap();
+
+        assert_eq!(clock.us, 0);
+    }
+}
@@ -0,0 +1,74 @@
+//! Checking that text written to the display reads back unchanged
+//!
+//! With a policy set through
+//! [`HD44780::set_verify`](../struct.HD44780.html#method.set_verify), every
+//! [`write_str`](../struct.HD44780.html#method.write_str) and
+//! [`write_bytes`](../struct.HD44780.html#method.write_bytes) reads the
+//! characters it wrote back from DDRAM. When they differ the text is written
+//! again, and the display re-initialized, as the policy allows, before
+//! giving up with
+//! [`Error::Mismatch`](../error/enum.Error.html#variant.Mismatch).
+//!
+//! Only the blocking driver verifies writes, and only on buses that can read
+//! from the display.
+//!
+//! ```rust,ignore
+//! lcd.set_verify(Some(VerifyPolicy {
+//!     retries: 2,
+//!     reinit: true,
+//! }));
+//!
+//! lcd.write_str("SpO2 97%", &mut delay)?;
+//! ```
+
+use crate::erased::ReadFn;
+
+/// What to do when text reads back differently from how it was written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct VerifyPolicy {
+    /// How many times to write the text again
+    pub retries: u8,
+    /// Whether to re-initialize the display once the retries run out, and
+    /// check one last time
+    pub reinit: bool,
+}
+
+pub(crate) struct Verifier<B> {
+    pub policy: VerifyPolicy,
+    pub read: ReadFn<B>,
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::error::Error;
+    use crate::mock::{NoDelay, Ram};
+    use crate::HD44780;
+
+    fn lcd(policy: VerifyPolicy) -> HD44780<Ram> {
+        let mut lcd = HD44780::from_bus(Ram::default(), 0b0011_1000);
+        lcd.set_verify(Some(policy));
+        lcd
+    }
+
+    #[test]
+    fn passes_matching_text() {
+        let mut lcd = lcd(VerifyPolicy::default());
+
+        assert_eq!(lcd.write_str("ok", &mut NoDelay), Ok(()));
+        assert_eq!(lcd.release().writes, 2);
+    }
+
+    #[test]
+    fn retries_then_fails() {
+        let mut lcd = lcd(VerifyPolicy {
+            retries: 2,
+            reinit: false,
+        });
+        lcd.bus.stuck = Some(0x01);
+
+        assert_eq!(lcd.write_str("ok", &mut NoDelay), Err(Error::Mismatch));
+        assert_eq!(lcd.release().writes, 6);
+    }
+}
@@ -0,0 +1,127 @@
+#![cfg(feature = "std")]
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use hd44780_driver::lcdproc;
+use hd44780_driver::sim::{NoDelay, SimBus};
+use hd44780_driver::{Geometry, HD44780};
+
+const GEOMETRY: Geometry = Geometry::new(16, 2);
+
+/// Run a server on a simulated display, returning its address
+fn start(sim: &SimBus, stop: &Arc<AtomicBool>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+
+    let sim = sim.clone();
+    let stop = stop.clone();
+
+    thread::spawn(move || {
+        let mut lcd = HD44780::new_simulated(sim, &mut NoDelay).unwrap();
+        lcd.set_geometry(GEOMETRY);
+
+        lcdproc::serve(listener, &mut lcd, &mut NoDelay, &stop).unwrap();
+    });
+
+    address
+}
+
+struct Client {
+    writer: TcpStream,
+    reader: BufReader<TcpStream>,
+}
+
+impl Client {
+    fn connect(address: &str) -> Client {
+        let stream = TcpStream::connect(address).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+
+        Client {
+            reader: BufReader::new(stream.try_clone().unwrap()),
+            writer: stream,
+        }
+    }
+
+    fn send(&mut self, line: &str) -> String {
+        writeln!(self.writer, "{}", line).unwrap();
+
+        let mut reply = String::new();
+        self.reader.read_line(&mut reply).unwrap();
+        reply
+    }
+}
+
+/// Wait for the simulated display to show `expected`
+fn wait_for(sim: &SimBus, expected: &[&str]) {
+    let start = Instant::now();
+
+    while sim.text(GEOMETRY) != expected {
+        assert!(
+            start.elapsed() < Duration::from_secs(5),
+            "display shows {:?}",
+            sim.text(GEOMETRY)
+        );
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn shows_client_screens() {
+    let sim = SimBus::new();
+    let stop = Arc::new(AtomicBool::new(false));
+    let mut client = Client::connect(&start(&sim, &stop));
+
+    assert_eq!(
+        client.send("hello"),
+        "connect LCDproc 0.5.9 protocol 0.3 lcd wid 16 hgt 2 cellwid 5 cellhgt 8\n"
+    );
+
+    for line in [
+        "client_set -name test",
+        "screen_add status",
+        "screen_set status -name Status -priority foreground",
+        "widget_add status label string",
+        "widget_set status label 1 1 \"Temp 21 C\"",
+        "widget_add status level hbar",
+        "widget_set status level 1 2 40",
+    ] {
+        assert_eq!(client.send(line), "success\n", "{}", line);
+    }
+
+    wait_for(&sim, &["Temp 21 C       ", "########        "]);
+
+    assert_eq!(
+        client.send("widget_set status label 6 1 {25 C}"),
+        "success\n"
+    );
+    wait_for(&sim, &["     25 C       ", "########        "]);
+
+    stop.store(true, Ordering::Relaxed);
+}
+
+#[test]
+fn drops_screens_of_closed_clients() {
+    let sim = SimBus::new();
+    let stop = Arc::new(AtomicBool::new(false));
+    let address = start(&sim, &stop);
+
+    let mut client = Client::connect(&address);
+    client.send("hello");
+    client.send("screen_add s");
+    client.send("widget_add s w string");
+    client.send("widget_set s w 1 1 bye");
+
+    wait_for(&sim, &["bye             ", "                "]);
+
+    drop(client);
+    wait_for(&sim, &["                ", "                "]);
+
+    stop.store(true, Ordering::Relaxed);
+}